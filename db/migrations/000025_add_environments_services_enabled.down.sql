@@ -0,0 +1 @@
+ALTER TABLE environments_services DROP COLUMN IF EXISTS enabled;
\ No newline at end of file
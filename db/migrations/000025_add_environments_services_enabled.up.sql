@@ -0,0 +1,4 @@
+-- Tells whether a service is deployed in an environment. Disabled services stay
+-- in the environment, along with their config templates and values, but are
+-- left out of releases and deployments.
+ALTER TABLE environments_services ADD COLUMN IF NOT EXISTS enabled BOOLEAN NOT NULL DEFAULT true;
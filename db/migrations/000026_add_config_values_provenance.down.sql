@@ -0,0 +1,2 @@
+ALTER TABLE config_values DROP COLUMN IF EXISTS set_at;
+ALTER TABLE config_values DROP COLUMN IF EXISTS set_by;
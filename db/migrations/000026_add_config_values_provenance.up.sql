@@ -0,0 +1,5 @@
+-- Records who last set each config value and when, so that a value's
+-- provenance survives even when the Dolt history is squashed. Both columns
+-- are nullable because the values set before this migration have neither.
+ALTER TABLE config_values ADD COLUMN IF NOT EXISTS set_by TEXT;
+ALTER TABLE config_values ADD COLUMN IF NOT EXISTS set_at TIMESTAMP;
@@ -0,0 +1,4 @@
+-- Tells whether a repository is still in use. Disabled repositories stay in
+-- the database, along with the services backed by them, but aren't cloned
+-- into new sites.
+ALTER TABLE repos ADD COLUMN IF NOT EXISTS enabled BOOLEAN NOT NULL DEFAULT true;
@@ -0,0 +1 @@
+ALTER TABLE config_defaults DROP COLUMN IF EXISTS secret;
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Tells whether a config value is a secret, like a password or an API key,
+-- that's replaced with a placeholder when values are rendered with
+-- --redact-secrets. It's recorded on the defaults so that it applies to the
+-- value in every environment. The keys that are obviously secrets are
+-- marked to start with.
+ALTER TABLE config_defaults ADD COLUMN IF NOT EXISTS secret BOOLEAN NOT NULL DEFAULT false;
+UPDATE config_defaults SET secret = true
+WHERE cfg_key LIKE '%Password'
+   OR cfg_key LIKE '%Secret'
+   OR cfg_key LIKE '%Token'
+   OR cfg_key LIKE '%APIKey';
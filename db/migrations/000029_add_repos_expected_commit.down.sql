@@ -0,0 +1 @@
+ALTER TABLE repos DROP COLUMN IF EXISTS expected_commit;
\ No newline at end of file
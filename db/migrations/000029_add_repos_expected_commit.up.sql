@@ -0,0 +1,4 @@
+-- The commit a repository's checkout is expected to be at once it's cloned
+-- into a site, as a full or abbreviated SHA. site init fails if the cloned
+-- HEAD doesn't match it. Repositories without one aren't checked.
+ALTER TABLE repos ADD COLUMN IF NOT EXISTS expected_commit VARCHAR(64);
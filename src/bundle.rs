@@ -0,0 +1,284 @@
+//! # Bundle
+//!
+//! Site bundles are a single `.tar.zst` archive containing everything needed
+//! to recreate a site without network access to the Dolt remote: the Dolt
+//! database directory, the values files rendered into the site, and a
+//! manifest listing the repos in the site along with the commit each one had
+//! checked out. The repo checkouts themselves aren't included; importing a
+//! bundle clones them again and checks out the recorded commits, in the
+//! places the site's repo path template put them.
+use crate::{
+    dolt, git,
+    repo_path::{self, RepoPathTemplate},
+};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// The version of the bundle layout written by this version of the tool.
+pub const BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+/// The name of the manifest file at the root of a bundle.
+pub const MANIFEST_FILENAME: &str = "bundle.yaml";
+
+/// Describes the contents of a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub schema_version: u32,
+
+    // The name of the Dolt database directory in the bundle and the commit it
+    // was at when the bundle was created.
+    pub db_name: String,
+    pub db_commit: String,
+
+    // The values files in the bundle, relative to the site directory.
+    pub files: Vec<PathBuf>,
+
+    pub repos: Vec<BundleRepo>,
+
+    // Where the repos were cloned under the site's repos directory. Bundles
+    // from before it was recorded used the default template.
+    #[serde(default = "default_repo_path_template")]
+    pub repo_path_template: String,
+}
+
+fn default_repo_path_template() -> String {
+    repo_path::DEFAULT_TEMPLATE.to_string()
+}
+
+/// A repo in the site and the commit it had checked out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleRepo {
+    pub name: String,
+    pub url: String,
+    pub commit: String,
+}
+
+// Lists the repos cloned into the site directory, sorted by name.
+fn site_repos(site_dir: &Path, template: &RepoPathTemplate) -> Result<Vec<BundleRepo>> {
+    let repos_dir = site_dir.join("repos");
+
+    let mut repos = Vec::new();
+    for repo_dir in repo_path::find_repos(&repos_dir)? {
+        let url = git::remote_url(&repo_dir)?;
+        let name = template
+            .repo_name(&repos_dir, &url, &repo_dir)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} isn't where the repo path template {} puts the repo cloned from {}",
+                    repo_dir.display(),
+                    template.as_str(),
+                    url
+                )
+            })?;
+        repos.push(BundleRepo {
+            name,
+            url,
+            commit: git::head_commit(&repo_dir)?,
+        });
+    }
+    repos.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(repos)
+}
+
+/// Writes a bundle of the site to `out`. The values files listed in `files`
+/// are relative to the site directory; any that don't exist are left out.
+/// The repos are looked for where `template` puts them.
+/// The Dolt SQL server for the database shouldn't be running while the
+/// bundle is created.
+///
+/// # Examples
+/// ```ignore
+///     let manifest = bundle::export(
+///         Path::new("site"),
+///         "de_releases",
+///         &[PathBuf::from("defaults.yaml"), PathBuf::from("deployment.yaml")],
+///         &RepoPathTemplate::load(Path::new("site"))?,
+///         Path::new("site.tar.zst"),
+///     )?;
+/// ```
+pub fn export(
+    site_dir: &Path,
+    db_name: &str,
+    files: &[PathBuf],
+    template: &RepoPathTemplate,
+    out: &Path,
+) -> Result<BundleManifest> {
+    let db_dir = site_dir.join(db_name);
+    let db_dir_str = db_dir
+        .to_str()
+        .ok_or_else(|| anyhow!("failed to get database directory as string"))?;
+    if !db_dir.join(".dolt").exists() {
+        return Err(anyhow!(
+            "{} does not contain a Dolt database",
+            db_dir.display()
+        ));
+    }
+
+    let manifest = BundleManifest {
+        schema_version: BUNDLE_SCHEMA_VERSION,
+        db_name: db_name.to_string(),
+        db_commit: dolt::local_head_commit(db_dir_str)?,
+        files: files
+            .iter()
+            .filter(|f| site_dir.join(f).is_file())
+            .cloned()
+            .collect(),
+        repos: site_repos(site_dir, template)?,
+        repo_path_template: template.as_str().to_string(),
+    };
+
+    let out_file =
+        File::create(out).with_context(|| format!("failed to create {}", out.display()))?;
+    let encoder = zstd::Encoder::new(out_file, 0)?.auto_finish();
+    let mut archive = tar::Builder::new(encoder);
+
+    let manifest_yaml = serde_yaml::to_string(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_yaml.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_FILENAME, manifest_yaml.as_bytes())?;
+
+    archive
+        .append_dir_all(db_name, &db_dir)
+        .with_context(|| format!("failed to add {} to the bundle", db_dir.display()))?;
+    for file in &manifest.files {
+        archive
+            .append_path_with_name(site_dir.join(file), file)
+            .with_context(|| format!("failed to add {} to the bundle", file.display()))?;
+    }
+
+    archive.into_inner()?;
+
+    Ok(manifest)
+}
+
+fn open_bundle(bundle: &Path) -> Result<tar::Archive<zstd::Decoder<'static, BufReader<File>>>> {
+    let file =
+        File::open(bundle).with_context(|| format!("failed to open {}", bundle.display()))?;
+    Ok(tar::Archive::new(zstd::Decoder::new(file)?))
+}
+
+/// Reads the manifest out of a bundle, returning an error if the bundle was
+/// written with a different layout than this version of the tool uses.
+///
+/// # Examples
+/// ```ignore
+///     let manifest = bundle::read_manifest(Path::new("site.tar.zst"))?;
+/// ```
+pub fn read_manifest(bundle: &Path) -> Result<BundleManifest> {
+    let mut archive = open_bundle(bundle)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() != Path::new(MANIFEST_FILENAME) {
+            continue;
+        }
+
+        let manifest: BundleManifest = serde_yaml::from_reader(&mut entry)
+            .with_context(|| format!("failed to parse the manifest in {}", bundle.display()))?;
+        if manifest.schema_version != BUNDLE_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "{} has bundle schema version {}, but this version of mgmt only supports version {}",
+                bundle.display(),
+                manifest.schema_version,
+                BUNDLE_SCHEMA_VERSION
+            ));
+        }
+        return Ok(manifest);
+    }
+
+    Err(anyhow!(
+        "{} does not contain a {}",
+        bundle.display(),
+        MANIFEST_FILENAME
+    ))
+}
+
+/// Unpacks the Dolt database and values files in a bundle into the site
+/// directory, creating it if necessary. Files that already exist are
+/// overwritten, so an import that failed part of the way through can be run
+/// again. The repos aren't cloned; see `restore_repo`.
+///
+/// # Examples
+/// ```ignore
+///     let manifest = bundle::import(Path::new("site.tar.zst"), Path::new("site"))?;
+/// ```
+pub fn import(bundle: &Path, site_dir: &Path) -> Result<BundleManifest> {
+    // Check the manifest before anything is written to the site directory.
+    let manifest = read_manifest(bundle)?;
+
+    std::fs::create_dir_all(site_dir)
+        .with_context(|| format!("failed to create {}", site_dir.display()))?;
+
+    let mut archive = open_bundle(bundle)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new(MANIFEST_FILENAME) {
+            continue;
+        }
+        entry
+            .unpack_in(site_dir)
+            .with_context(|| format!("failed to unpack {}", bundle.display()))?;
+    }
+
+    Ok(manifest)
+}
+
+/// Clones a repo from a bundle manifest into the site's repos directory,
+/// where `template` puts it, and checks out the commit recorded for it. A repo that was already cloned is
+/// reused, so this is safe to run again after a failure. If `submodules` is
+/// true, the repo's submodules are brought in line with the checked out
+/// commit, even if the repo was already at it. Returns false if the repo was
+/// already at the recorded commit.
+///
+/// # Examples
+/// ```ignore
+///     let template = RepoPathTemplate::parse(&manifest.repo_path_template)?;
+///     for repo in &manifest.repos {
+///         bundle::restore_repo(Path::new("site"), repo, &template, false)?;
+///     }
+/// ```
+pub fn restore_repo(
+    site_dir: &Path,
+    repo: &BundleRepo,
+    template: &RepoPathTemplate,
+    submodules: bool,
+) -> Result<bool> {
+    let repo_dir = template.repo_dir(&site_dir.join("repos"), &repo.url, &repo.name)?;
+
+    let at_commit = repo_dir.join(".git").exists() && git::head_commit(&repo_dir)? == repo.commit;
+    if !at_commit {
+        if !repo_dir.join(".git").exists() {
+            let repo_dir_str = repo_dir
+                .to_str()
+                .ok_or_else(|| anyhow!("failed to get repo directory as string"))?;
+            git::clone(&repo.url, repo_dir_str, false)
+                .with_context(|| format!("failed to clone {} from {}", repo.name, repo.url))?;
+        }
+
+        git::checkout(&repo_dir, &repo.commit).with_context(|| {
+            format!(
+                "failed to check out commit {} in {}",
+                repo.commit,
+                repo_dir.display()
+            )
+        })?;
+    }
+
+    // The submodules are updated after the checkout since the recorded commit
+    // may point them somewhere other than where the clone left them. They're
+    // updated even when the repo was already at the commit, so that importing
+    // again repairs submodules that failed to update the first time.
+    if submodules {
+        git::update_submodules(&repo_dir).with_context(|| {
+            format!("failed to update the submodules in {}", repo_dir.display())
+        })?;
+    }
+
+    Ok(!at_commit)
+}
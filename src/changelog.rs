@@ -0,0 +1,158 @@
+//! # Changelog
+//!
+//! The changelog written into the releases repository when a release is
+//! created. It lists, for each environment and service, the commits between
+//! the version in the previous release and the version in the new one, so that
+//! reviewers can see what's actually shipping. Services whose version didn't
+//! change are left out.
+use crate::git::CommitInfo;
+use std::collections::BTreeMap;
+
+/// The name of the changelog file in the releases repository.
+pub const CHANGELOG_FILENAME: &str = "CHANGELOG.md";
+
+/// The change to a single service in a release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceChange {
+    pub service: String,
+
+    // The version in the previous release, if the service was in it.
+    pub from: Option<String>,
+    pub to: String,
+
+    // The commits between the two versions, or None if they couldn't be
+    // listed, for example because the repository isn't cloned.
+    pub commits: Option<Vec<CommitInfo>>,
+}
+
+impl ServiceChange {
+    /// Returns whether the service's version changed in the release.
+    pub fn is_changed(&self) -> bool {
+        self.from.as_deref() != Some(self.to.as_str())
+    }
+}
+
+/// The changes in a release, grouped by environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changelog {
+    environments: BTreeMap<String, Vec<ServiceChange>>,
+}
+
+impl Changelog {
+    /// Records the change to a service in an environment. Services whose
+    /// version didn't change aren't recorded.
+    pub fn add(&mut self, env: &str, change: ServiceChange) {
+        if change.is_changed() {
+            self.environments
+                .entry(env.to_string())
+                .or_default()
+                .push(change);
+        }
+    }
+
+    /// Returns whether no services changed.
+    pub fn is_empty(&self) -> bool {
+        self.environments.is_empty()
+    }
+
+    /// Renders the changelog as markdown under the given title.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     fs::write(repo_dir.join(CHANGELOG_FILENAME), changelog.render("Release v1.2.3"))?;
+    /// ```
+    pub fn render(&self, title: &str) -> String {
+        let mut out = format!("# {}\n", title);
+        if self.is_empty() {
+            out.push_str("\nNo services changed.\n");
+            return out;
+        }
+
+        for (env, changes) in &self.environments {
+            out.push_str(&format!("\n## {}\n", env));
+            for change in changes {
+                match &change.from {
+                    Some(from) => out.push_str(&format!(
+                        "\n### {} ({} → {})\n\n",
+                        change.service, from, change.to
+                    )),
+                    None => {
+                        out.push_str(&format!("\n### {} ({}, new)\n", change.service, change.to))
+                    }
+                }
+                match &change.commits {
+                    Some(commits) if commits.is_empty() => out.push_str("No commits.\n"),
+                    Some(commits) => {
+                        for commit in commits {
+                            out.push_str(&format!(
+                                "- {} {} ({}, {})\n",
+                                commit.hash, commit.subject, commit.author, commit.date
+                            ));
+                        }
+                    }
+                    // There's nothing to compare a new service's version to.
+                    None if change.from.is_none() => {}
+                    None => out.push_str("Commit history unavailable.\n"),
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn change(service: &str, from: Option<&str>, to: &str) -> ServiceChange {
+        ServiceChange {
+            service: service.to_string(),
+            from: from.map(|v| v.to_string()),
+            to: to.to_string(),
+            commits: None,
+        }
+    }
+
+    #[test]
+    fn test_unchanged_services_are_omitted() {
+        let mut changelog = Changelog::default();
+        changelog.add("qa", change("apps", Some("v1.0.0"), "v1.0.0"));
+        assert!(changelog.is_empty());
+        assert_eq!(
+            changelog.render("Release"),
+            "# Release\n\nNo services changed.\n"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut changelog = Changelog::default();
+        changelog.add(
+            "qa",
+            ServiceChange {
+                commits: Some(vec![CommitInfo {
+                    hash: "abc1234".to_string(),
+                    author: "Jane Doe".to_string(),
+                    date: "2024-01-02".to_string(),
+                    subject: "Fix the thing".to_string(),
+                }]),
+                ..change("apps", Some("v1.0.0"), "v1.1.0")
+            },
+        );
+        changelog.add("qa", change("analyses", None, "v2.0.0"));
+        changelog.add("qa", change("terrain", Some("v1.0.0"), "v1.0.1"));
+        changelog.add("qa", change("notifications", Some("v1.0.0"), "v1.0.0"));
+
+        assert_eq!(
+            changelog.render("Release"),
+            "# Release\n\
+             \n## qa\n\
+             \n### apps (v1.0.0 → v1.1.0)\n\n\
+             - abc1234 Fix the thing (Jane Doe, 2024-01-02)\n\
+             \n### analyses (v2.0.0, new)\n\
+             \n### terrain (v1.0.0 → v1.0.1)\n\n\
+             Commit history unavailable.\n"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config_values::format;
 use clap::{arg, ArgAction, Command};
 use std::path::PathBuf;
 
@@ -45,6 +46,9 @@ pub fn cli() -> Command {
                                     "tsv", "yaml", "xml",
                                 ]))
                                 .help("The type of the value"),
+                            arg!(--actor [ACTOR] "Who is setting the value")
+                                .long_help("The name recorded as the one who set the value, which env show --wide displays. Defaults to the OS user ($USER).")
+                                .value_parser(clap::value_parser!(String)),
                         ]),
                 )
                 .subcommand(
@@ -95,12 +99,27 @@ pub fn cli() -> Command {
                             arg!(
                                 -f --file <FILE> "The file to render the config values to"
                             )
-                                .required(true)
+                                .required_unless_present("split-sections")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                            arg!(--"split-sections" <DIR> "Render each section to its own file in DIR")
+                                .long_help("Render each section to its own file in DIR instead of rendering everything to a single file. The files are named after the sections, e.g. dashboard-aggregator.yaml, and DIR also gets an index file with the top-level settings and the name of each section's file.")
+                                .required(false)
+                                .conflicts_with("file")
                                 .value_parser(clap::value_parser!(PathBuf)),
                             arg!(
                                 -e --"environment" <ENVIRONMENT>
                                     "The environment to render the config values for"
                             ),
+                            arg!(--"always-write" "Write the file even if its contents haven't changed")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
+                            arg!(--"redact-secrets" "Replace the secret values with <redacted>")
+                                .long_help("Replace the values marked as secrets in the database, and the values that refer to them, with <redacted>, so that the rendered file can be committed. Leave this off to render the values for deploying.")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
+                            format::format_arg(),
                             arg!(--"include-all" "Include all settings in the rendered output")
                                 .required(false)
                                 .action(ArgAction::SetTrue)
@@ -169,13 +188,17 @@ pub fn cli() -> Command {
                 )
                 .subcommand(
                     Command::new("import")
+                        .about("Loads the config values for an environment from a values file")
                         .args([
-                            arg!(--"file" <FILE>)
+                            arg!(-f --"file" <FILE>)
                                 .required(true)
                                 .value_parser(clap::value_parser!(PathBuf)),
-                            arg!(--"environment" <ENVIRONMENT>)
+                            arg!(-e --"environment" <ENVIRONMENT>)
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
+                            arg!(--"dry-run" "Report what would change without writing anything")
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
                         ]),
                 ),
         )
@@ -235,10 +258,43 @@ pub fn cli() -> Command {
                                 .value_parser(clap::value_parser!(String)),
                         ]),
                 )
+                .subcommand(
+                    Command::new("secret")
+                        .about("Marks a config value as a secret that's redacted by --redact-secrets")
+                        .args([
+                            arg!(-s --"section" <SECTION>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-k --"key" <KEY>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(--"not-secret" "Mark the value as not a secret instead")
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
+                        ]),
+                )
                 .subcommand(
                 Command::new("render")
                         .arg(arg!(-f --file <FILE> "The file to render the config values to")
-                            .value_parser(clap::value_parser!(PathBuf))),
+                            .value_parser(clap::value_parser!(PathBuf)))
+                        .arg(arg!(--"always-write" "Write the file even if its contents haven't changed")
+                            .action(ArgAction::SetTrue)
+                            .value_parser(clap::value_parser!(bool)))
+                        .arg(format::format_arg()),
                 ),
         )
+        .subcommand(
+            Command::new("backup")
+                .about("Writes all of the config data in the database to a file")
+                .long_about("Writes all of the config data in the database to a file: the value types, sections, defaults, and every environment's feature flags and values. The file is JSON if its name ends in .json and YAML otherwise.")
+                .arg(arg!(-o --out <FILE> "The file to write the backup to")
+                    .value_parser(clap::value_parser!(PathBuf))),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Replaces the config data in the database with a backup")
+                .long_about("Replaces the config data in the database with a backup written by the backup subcommand. Everything is restored in a single transaction, so the database is left untouched if the restore fails.")
+                .arg(arg!(-f --file <FILE> "The backup file to restore")
+                    .value_parser(clap::value_parser!(PathBuf))),
+        )
 }
@@ -1,4 +1,14 @@
-use clap::{arg, ArgAction, Command};
+use crate::{commit, output};
+use clap::{arg, Arg, ArgAction, Command};
+use std::path::PathBuf;
+
+// Returns the --db-dir argument of the commands that only read from the
+// database.
+fn db_dir_arg() -> Arg {
+    arg!(--"db-dir" [DB_DIR] "Read from the Dolt database in this directory")
+        .long_help("Read from the Dolt database in this directory, such as a site's own copy cloned by site init, instead of the database given by --database-url. A temporary read-only Dolt server is started for it and stopped afterwards.")
+        .value_parser(clap::value_parser!(PathBuf))
+}
 
 pub fn cli() -> Command {
     let feature_flags = clap::builder::PossibleValuesParser::new([
@@ -44,7 +54,75 @@ pub fn cli() -> Command {
                         .value_parser(clap::value_parser!(String))
                 ]),
         )
-        .subcommand(Command::new("list").about("Lists the environments in the database."))
+        .subcommand(
+            Command::new("rename")
+                .about("Renames an environment and commits the change. The environment keeps its services, config templates, and config values.")
+                .args([
+                    arg!(-e --env <ENV> "The environment to rename")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-t --to <NAME> "The new name for the environment")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    commit::message_arg("Rename environment {env} to {to}")
+                        .long_help("The commit message. {env} is replaced with the old name of the environment, {to} with the new name, and {date} with today's date."),
+                ])
+                .args(commit::author_args()),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists the environments in the database.")
+                .args([output::format_arg(), output::no_truncate_arg(), db_dir_arg()]),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Shows the configuration values set for an environment.")
+                .args([
+                    arg!(-e --env <ENV> "The environment to show")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-w --wide "Also show who last set each value and when")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    output::format_arg(),
+                    output::no_truncate_arg(),
+                    db_dir_arg(),
+                ]),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Lists the commits that changed the configuration values for an environment, newest first.")
+                .args([
+                    arg!(-e --env <ENV> "The environment to list the history for")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-n --limit [N] "The maximum number of commits to list")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(i64).range(1..)),
+                    arg!(--offset [N] "The number of commits to skip before listing them")
+                        .visible_alias("skip")
+                        .default_value("0")
+                        .value_parser(clap::value_parser!(i64).range(0..)),
+                    arg!(--oneline "Print each commit on one line as its short hash and message")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool))
+                        .conflicts_with("format"),
+                    output::format_arg(),
+                    output::no_truncate_arg(),
+                ]),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Deletes configuration values that aren't linked to any environment and commits the change.")
+                .args([
+                    arg!(--"dry-run" "List the values that would be deleted without deleting them")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    commit::message_arg("Delete {count} orphaned config value(s)")
+                        .long_help("The commit message. {count} is replaced with the number of values that were deleted and {date} with today's date."),
+                ])
+                .args(commit::author_args()),
+        )
         .subcommand(
             Command::new("populate")
                 .args_conflicts_with_subcommands(true)
@@ -78,6 +156,32 @@ pub fn cli() -> Command {
                                 .value_parser(clap::value_parser!(String)),
                         ]),
                 )
+                .subcommand(
+                    Command::new("enable")
+                        .about("Enables one or more services in an environment so that they're released and deployed again.")
+                        .args([
+                            arg!(-e --env <ENV> "The environment to enable the service in.")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-s --service [SERVICE] "The name of the service to enable.")
+                                .required(true)
+                                .action(ArgAction::Append)
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("disable")
+                        .about("Disables one or more services in an environment. Disabled services keep their config templates and values but are left out of releases and deployments.")
+                        .args([
+                            arg!(-e --env <ENV> "The environment to disable the service in.")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-s --service [SERVICE] "The name of the service to disable.")
+                                .required(true)
+                                .action(ArgAction::Append)
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
                 .subcommand(
                     Command::new("list").args([
                         arg!(-e --env <ENV> "The environment to list the services for.")
@@ -86,6 +190,29 @@ pub fn cli() -> Command {
                     ]),
                 ),
         )
+        .subcommand(
+            Command::new("key")
+                .about("Manages the keys of the configuration values.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("move")
+                        .about("Moves a key and its values from one section to another in every environment and commits the change.")
+                        .args([
+                            arg!(-k --key <KEY> "The key to move")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-f --from <SECTION> "The section the key is in now")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-t --to <SECTION> "The section to move the key to")
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            commit::message_arg("Move {key} from {from} to {to}")
+                                .long_help("The commit message. {key} is replaced with the key, {from} and {to} with the sections, and {date} with today's date."),
+                        ])
+                        .args(commit::author_args()),
+                ),
+        )
         .subcommand(
             Command::new("feature-flags")
                 .about("Manages feature flags for an environment.")
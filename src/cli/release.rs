@@ -1,6 +1,14 @@
-use clap::{arg, ArgAction, Command};
+use crate::{commit, output, versions};
+use clap::{arg, Arg, ArgAction, Command};
 use std::path::PathBuf;
 
+fn versions_arg() -> Arg {
+    arg!(--versions [VERSIONS] "A file pinning the versions of the services")
+        .long_help(format!("A file pinning the versions of the services, which take precedence over the versions in the database. Defaults to {} if it exists.", versions::VERSIONS_FILENAME))
+        .required(false)
+        .value_parser(clap::value_parser!(PathBuf))
+}
+
 pub fn cli() -> Command {
     Command::new("release")
         .about("Creates and posts a release to a git repository")
@@ -8,12 +16,22 @@ pub fn cli() -> Command {
         .subcommand(
             Command::new("create").args([
                 arg!(-s --"skip" <SKIP> "A service to skip for the release")
+                    .long_help("A service to skip for the release. Applies to every environment being released unless written as <env>:<service>.")
                     .required(false)
                     .action(ArgAction::Append)
                     .value_parser(clap::value_parser!(String)),
+                arg!(--"include-disabled" "Include the services that are disabled in the environment")
+                    .required(false)
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
                 arg!(-e --env <ENV> "The environment to release")
-                    .required(true)
+                    .long_help("The environment to release. May be repeated to release more than one environment at once, in which case each environment gets its own subdirectory in the builds and services directories.")
+                    .required_unless_present("env-file")
+                    .action(ArgAction::Append)
                     .value_parser(clap::value_parser!(String)),
+                arg!(--"env-file" [ENV_FILE] "A file listing the environments to release, one per line")
+                    .required(false)
+                    .value_parser(clap::value_parser!(PathBuf)),
                 arg!(-r --"repo-name" [REPO_NAME] "The repository to release to")
                     .required(false)
                     .default_value("de-releases")
@@ -50,7 +68,20 @@ pub fn cli() -> Command {
                     .required(false)
                     .default_value("patch")
                     .value_parser(clap::builder::PossibleValuesParser::new(["major", "minor", "patch"])),
-            ]),
+                versions_arg(),
+                arg!(--"repos-dir" [REPOS_DIR] "The directory containing clones of the services' repositories")
+                    .long_help("The directory containing clones of the services' repositories, named after the repositories. They're used to list the commits between the previous and new versions of each service in the changelog. Services whose repositories aren't cloned there are listed without their commits.")
+                    .required(false)
+                    .default_value("repos")
+                    .value_parser(clap::value_parser!(PathBuf)),
+                arg!(--force "Overwrite the changes left in the releases repository by an unfinished release")
+                    .long_help("Overwrite the changes left in the releases repository by an unfinished release. Without it, the release fails if the builds, services, manifest, or changelog in the releases repository have uncommitted changes.")
+                    .required(false)
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                commit::message_arg("update builds for {env} on {date} at {time}"),
+            ])
+            .args(commit::author_args()),
         )
         .subcommand(
             Command::new("deploy")
@@ -78,6 +109,10 @@ pub fn cli() -> Command {
                         .required(false)
                         .action(ArgAction::Append)
                         .value_parser(clap::value_parser!(String)),
+                    arg!(--"include-disabled" "Deploy the services that are disabled in the environment")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                     arg!(-p --"pre-deploy" <PRE_DEPLOY> "A service to deploy before the rest")
                         .required(false)
                         .action(ArgAction::Append)
@@ -98,6 +133,93 @@ pub fn cli() -> Command {
                         .required(false)
                         .action(ArgAction::SetTrue)
                         .value_parser(clap::value_parser!(bool)),
+                    versions_arg(),
+                    arg!(--concurrency [N] "The maximum number of services to deploy at once")
+                        .long_help("The maximum number of services to deploy at once. The services passed to --pre-deploy are all deployed before any of the rest.")
+                        .required(false)
+                        .default_value("3")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                    arg!(--"continue-on-error" "Keep deploying and exit successfully when services fail to deploy")
+                        .long_help("Keep deploying and exit successfully when services fail to deploy. The failures are still listed in the report at the end. Without it, a failure in a pre-deploy service stops the rest from being deployed, and any failure makes the command fail.")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(-w --wait "Wait for the services' rollouts to finish")
+                        .long_help("Wait for the rollout of each deployed service to finish, failing with a list of the services that aren't ready if --wait-timeout runs out. Without it, the deployment returns as soon as the services are applied.")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--"wait-timeout" [SECONDS] "The maximum number of seconds to wait for all of the rollouts")
+                        .required(false)
+                        .default_value("600")
+                        .value_parser(clap::value_parser!(u64)),
+                    arg!(--"manifest-out" <DIR> "The directory to write the rendered manifests to before applying them")
+                        .long_help("The directory to write the rendered manifests to before applying them: the secret holding the configs, each of the secrets, and one file per service, named after the service. Files containing secrets are prefixed with 'secret-'. The directory and the files are only readable by their owner.")
+                        .required(false)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"dry-run" "Render everything without applying it to the cluster")
+                        .long_help("Render the configs, secrets, and, with --manifest-out, the manifests without applying anything to the cluster. Combined with --manifest-out, this generates the manifests for an environment.")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                 ])
         )
+        .subcommand(
+            Command::new("services")
+                .about("Lists the services in an environment and their current versions")
+                .args([
+                    arg!(-e --env <ENV> "The environment to list the services for")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-s --skip <SKIP> "A service that would be skipped for the release")
+                        .long_help("A service that would be skipped for the release. Accepts the same values as release create --skip, so a skip list can be checked before it's used.")
+                        .required(false)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                    versions_arg(),
+                    output::format_arg(),
+                    output::no_truncate_arg(),
+                ])
+        )
+        .subcommand(
+            Command::new("preview")
+                .about("Shows which services a release of an environment would bump, without changing anything")
+                .args([
+                    arg!(-e --env <ENV> "The environment to preview the release of")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-s --skip <SKIP> "A service to skip for the release")
+                        .long_help("A service to skip for the release. Accepts the same values as release create --skip.")
+                        .required(false)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"include-disabled" "Include the services that are disabled in the environment")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(-r --"repo-name" [REPO_NAME] "The clone of the releases repository to compare against")
+                        .long_help("The clone of the releases repository to compare against. The manifest left in it by the previous release is read; the clone isn't pulled or changed.")
+                        .required(false)
+                        .default_value("de-releases")
+                        .value_parser(clap::value_parser!(String)),
+                    versions_arg(),
+                ])
+        )
+        .subcommand(
+            Command::new("freeze")
+                .about("Writes the current versions of the services in an environment to a versions file")
+                .args([
+                    arg!(-e --env <ENV> "The environment to freeze the versions of")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-o --out [OUT] "The versions file to write")
+                        .required(false)
+                        .default_value(versions::VERSIONS_FILENAME)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ])
+        )
+        .subcommand(
+            Command::new("ping")
+                .about("Checks that the database is reachable, reporting its latency and version")
+        )
 }
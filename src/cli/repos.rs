@@ -18,6 +18,9 @@ pub fn cli() -> Command {
                     arg!(-r --revision <REVISION> "The revision of the repository to add.")
                         .default_value("main")
                         .value_parser(clap::value_parser!(String)),
+                    arg!(-c --"expected-commit" [COMMIT] "The commit the repository is expected to be at once it's cloned.")
+                        .long_help("The commit the repository is expected to be at once it's cloned, as a full or abbreviated hash. site init fails if a cloned repository is at a different commit.")
+                        .value_parser(clap::value_parser!(String)),
                 ]),
         )
         .subcommand(
@@ -1,6 +1,14 @@
-use clap::{arg, ArgAction, Command};
+use crate::{commit, config_values::format};
+use clap::{arg, Arg, ArgAction, Command};
 use std::path::PathBuf;
 
+fn deployment_arg() -> Arg {
+    arg!(--deployment [NAME] "The deployment in the site to use")
+        .long_help("The deployment in the site to use. A site can hold several deployments that share its defaults and database; each one other than the default keeps its values file in deployments/<NAME> under the site directory.")
+        .default_value("default")
+        .value_parser(clap::value_parser!(String))
+}
+
 pub fn cli() -> Command {
     Command::new("site")
         .about(
@@ -14,10 +22,19 @@ pub fn cli() -> Command {
                     .default_value(".")
                     .value_parser(clap::value_parser!(String)),
                 arg!(-r --"db-repo" [DB_REPO] "The Dolt DB repo to set up and use for initializing the local DB.")
+                    .long_help("The Dolt DB repo to set up and use for initializing the local DB. May be repeated to set up more than one database; the first one is the primary database.")
                     .required(true)
+                    .action(ArgAction::Append)
                     .value_parser(clap::value_parser!(String)),
                 arg!(-n --"db-name" [DB_NAME] "The name of the DB")
+                    .long_help("The name of the DB. Must be repeated once for each --db-repo, in the same order.")
                     .default_value("de_releases")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(String)),
+                arg!(--"render-db" [DB_NAME] "The name of the DB to render the values files from. Defaults to the primary DB.")
+                    .value_parser(clap::value_parser!(String)),
+                arg!(--"db-ref" [REF] "The branch, tag, or commit to check out in the primary DB after cloning it")
+                    .long_help("The branch, tag, or commit to check out in the primary DB after cloning it, so that the site is set up from a known state of the DB. A tag or commit is checked out on a new mgmt-pinned branch. Ignored with --no-db-clone.")
                     .value_parser(clap::value_parser!(String)),
                 arg!(-C --"no-db-clone" "Do not clone the Dolt DB repo")
                     .action(ArgAction::SetTrue)
@@ -25,12 +42,44 @@ pub fn cli() -> Command {
                 arg!(-R --"no-repo-clone" "Do not clone the repos")
                     .action(ArgAction::SetTrue)
                     .value_parser(clap::value_parser!(bool)),
+                arg!(--"no-verify" "Do not check the cloned repos against the commits the database expects")
+                    .long_help("Do not check the cloned repos against the commits the database expects. Normally, a repo with an expected commit recorded in the database must have that commit checked out once it's cloned, or the init fails. Repos without an expected commit are never checked.")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--submodules "Clone the repos' submodules along with them")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--"repo-path-template" [TEMPLATE] "Where to clone each repo under the repos directory")
+                    .long_help("Where to clone each repo under the site's repos directory. {name} is replaced with the repo's name and {org} with the organization in its URL, so {org}/{name} keeps repos with the same name apart. The template is recorded in the site, so the commands run on it later don't need it again. Defaults to {name}, or to the recorded template with --resume.")
+                    .value_parser(clap::value_parser!(String)),
+                arg!(-e --env [ENV] "Only clone the repos for the services in this environment")
+                    .long_help("Only clone the repos for the services in this environment. All of the repos are cloned if this isn't given.")
+                    .value_parser(clap::value_parser!(String)),
                 arg!(-f --force "Overwrite existing files")
+                    .long_help("Delete the site directory and start over if it already exists. Asks for confirmation first unless --yes is given.")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--resume "Pick up where a failed init left off instead of starting over")
+                    .long_help("Pick up where a failed init left off instead of starting over. The existing site directory is kept, a database that was already cloned is reused, and repos that are already checked out from the right URL aren't cloned again. Anything that was only partly cloned is removed and cloned again.")
+                    .conflicts_with("force")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(-y --yes "Do not ask for confirmation before --force deletes the site directory")
                     .action(ArgAction::SetTrue)
                     .value_parser(clap::value_parser!(bool)),
                 arg!(-E --"no-env" "Do not prompt the user for values for an environment")
                     .action(ArgAction::SetTrue)
                     .value_parser(clap::value_parser!(bool)),
+                arg!(--set [ANSWER] "Answer a question about the environment ahead of time, as section.key=value")
+                    .long_help("Answer a question about the environment ahead of time, as section.key=value. May be repeated. Answers can also be read from a file with --answers; --set wins when both answer the same key. Keys that aren't answered keep their defaults, and keys without a default are prompted for.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(String)),
+                arg!(--answers [FILE] "Read answers to the questions about the environment from a file, or from stdin if it's -")
+                    .long_help("Read answers to the questions about the environment from a file, one section.key=value per line, or from stdin if it's -. Blank lines and lines starting with # are skipped.")
+                    .value_parser(clap::value_parser!(String)),
+                arg!(--"no-prompt" "Fail instead of prompting for environment values that weren't answered")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
                 arg!(-D --"no-defaults" "Do not write out the default values to a file in the site directory")
                     .action(ArgAction::SetTrue)
                     .value_parser(clap::value_parser!(bool)),
@@ -43,10 +92,60 @@ pub fn cli() -> Command {
                 arg!(--"values-filename" [VALUES_FILENAME] "The name of the file to write the config values to in the site directory")
                     .default_value("deployment.yaml")
                     .value_parser(clap::value_parser!(String)),
+                deployment_arg(),
+                arg!(-s --section <SECTION> "Only write this section to the values file. May be repeated")
+                    .long_help("Only write this section of the config values to the values file. May be repeated. The top-level settings are in the TopLevel section. Without it, every section is written.")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(String)),
+                arg!(-q --quiet "Only print warnings, errors, and the final summary")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--"post-clone" [COMMAND] "A shell command to run in each repo directory after it's cloned")
+                    .long_help("A shell command to run in each repo directory after it's cloned. The name of the repo is available in the MGMT_REPO_NAME environment variable. Failures are reported at the end of the clone step.")
+                    .value_parser(clap::value_parser!(String)),
+                arg!(--preflight "Check the free space and the remotes before cloning (the default)")
+                    .long_help("Check that there's enough free space for the site, that the database remotes can be reached, and that a sample of the repo remotes can be listed before cloning anything. This is the default; use --no-preflight to skip the checks.")
+                    .action(ArgAction::SetTrue)
+                    .overrides_with("no-preflight")
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--"no-preflight" "Skip the checks run before cloning")
+                    .action(ArgAction::SetTrue)
+                    .overrides_with("preflight")
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--timeout [SECONDS] "The maximum number of seconds the whole init may take")
+                    .long_help("The maximum number of seconds the whole init may take. When it runs out, the databases are shut down and the init fails.")
+                    .value_parser(clap::value_parser!(u64)),
+                arg!(--"clone-concurrency" [N] "The maximum number of repos to clone at once")
+                    .long_help("The maximum number of repos to clone at once. A repo that fails to clone doesn't stop the others; the failures are listed once all of the repos are done.")
+                    .default_value("4")
+                    .value_parser(clap::value_parser!(u64).range(1..)),
+                arg!(--"db-retries" [N] "How many times to retry writing the config values after a deadlock")
+                    .long_help("How many times to retry writing the config values for the environment when the transaction deadlocks, times out waiting for a lock, or conflicts with another client's, which can happen when several sites are initialized against a shared Dolt server. The wait between attempts doubles each time. Values entered at the prompts aren't retried, since they can't be asked for again.")
+                    .default_value("3")
+                    .value_parser(clap::value_parser!(u32)),
+                arg!(--"db-startup-timeout" [SECONDS] "How long to wait for each Dolt server to start accepting connections")
+                    .long_help("How long to wait for each Dolt server to start accepting connections after it's started, in seconds. Connecting is retried with a growing wait in between until then, since a server takes a moment to start, especially on slower machines.")
+                    .default_value("30")
+                    .value_parser(clap::value_parser!(u64).range(1..)),
+                arg!(--"no-progress" "Print plain lines instead of progress bars")
+                    .long_help("Print a line as each step starts and finishes instead of drawing spinners and a progress bar. Progress bars are only drawn when stdout is a terminal, so this is only needed to get plain output in one, such as in CI logs captured from a pseudo-terminal.")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--shallow "Clone only the latest commit of each repo")
+                    .long_help("Clone only the latest commit of each repo instead of its full history, which saves time and disk space. Use --depth to keep more commits. Commands that need the history, such as listing the commits in a release's changelog, warn about the repos that are missing it.")
+                    .action(ArgAction::SetTrue)
+                    .value_parser(clap::value_parser!(bool)),
+                arg!(--depth [N] "Clone only the latest N commits of each repo")
+                    .long_help("Clone only the latest N commits of each repo instead of its full history. Implies --shallow.")
+                    .required(false)
+                    .value_parser(clap::value_parser!(u32).range(1..)),
+                format::format_arg(),
             ]),
         )
         .subcommand(
             Command::new("deploy")
+                .about("Deploys the services in an environment from the site directory")
                 .args([
                     arg!(-d --dir [DIR] "Directory to deploy from")
                         .default_value(".")
@@ -67,6 +166,214 @@ pub fn cli() -> Command {
                     arg!(--"values-filename" [VALUES_FILENAME] "The file containing the configuration values for the environment")
                         .default_value("deployment.yaml")
                         .value_parser(clap::value_parser!(PathBuf)),
+                    deployment_arg(),
+                    arg!(--"repo-path-template" [TEMPLATE] "Where each repo was cloned under the repos directory")
+                        .long_help("Where each repo was cloned under the site's repos directory. Defaults to the template recorded in the site by site init or site import.")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"dry-run" "Print what would be deployed without deploying it")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--set [VALUE] "Override a config value for this deployment, as section.key=value")
+                        .long_help("Override a config value for this deployment, as section.key=value, without editing the values file. May be repeated. The value must be valid for the key's type, and the key must be in the defaults or values file.")
+                        .required(false)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Pulls the latest releases into the site's database")
+                .long_about("Pulls the latest releases into the site's database from the remote it was cloned from, without cloning it again. Fails if the database has uncommitted changes.")
+                .args([
+                    arg!(-d --dir [DIR] "The site directory containing the database")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-n --"db-name" [DB_NAME] "The name of the DB")
+                        .default_value("de_releases")
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Checks the configuration values for an environment for common mistakes")
+                .args([
+                    arg!(-e --env [ENV] "The environment to check")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compares the configuration values of two environments")
+                .long_about("Compares the configuration values of two environments, such as staging and prod before a promotion, and lists the values that differ or are only set in one of them. Values are compared as they're stored, before references to other values are resolved. Secrets are compared but not printed. Exits with a non-zero status if there are any differences, so it can be used as a CI gate.")
+                .args([
+                    arg!(-e --env <ENV> "An environment to compare. Must be given exactly twice")
+                        .action(ArgAction::Append)
+                        .num_args(1)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"only-differences" "Don't list the values that are the same in both environments")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(-d --dir [DIR] "The site directory containing the DB given by --db-name")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-n --"db-name" [DB_NAME] "Compare the environments in the site's own copy of this DB")
+                        .long_help("Compare the environments in the site's own copy of this DB, cloned into the site directory by site init, instead of the database given by --database-url. A temporary read-only Dolt server is started for it and stopped afterwards.")
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Renders the configuration values for an environment into the site directory")
+                .args([
+                    arg!(-d --dir [DIR] "The site directory to write the values file to")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-e --env [ENV] "The environment to render")
+                        .required_unless_present("all-environments")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"all-environments" "Render the values file for every environment")
+                        .long_help("Render the values file for every environment in the database. Each environment's values are written to the values filename with the environment's name added, e.g. deployment-qa.yaml. Every environment is tried, and the command fails at the end if any of them couldn't be rendered.")
+                        .conflicts_with_all(["env", "watch"])
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(-s --section <SECTION> "Only render this section. May be repeated")
+                        .long_help("Only render this section of the config values. May be repeated. The top-level settings are in the TopLevel section. Without it, every section enabled for the environment is rendered.")
+                        .required(false)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"values-filename" [VALUES_FILENAME] "The name of the file to write the config values to in the site directory")
+                        .default_value("deployment.yaml")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    deployment_arg(),
+                    arg!(-o --out [FILE] "The file to write the config values to, instead of the values file in the site directory")
+                        .conflicts_with("all-environments")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-n --"db-name" [DB_NAME] "Render from the site's own copy of this DB")
+                        .long_help("Render from the site's own copy of this DB, cloned into the site directory by site init, instead of the database given by --database-url. A temporary read-only Dolt server is started for it and stopped afterwards. Run this after pulling new config into the site's DB to regenerate the values file without re-running init.")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"always-write" "Write the values file even if its contents haven't changed")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--"redact-secrets" "Replace the secret values with <redacted>")
+                        .long_help("Replace the values marked as secrets in the database, and the values that refer to them, with <redacted>, so that the values file can be committed to the site repo. A redacted values file can't be deployed with site deploy.")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    format::format_arg(),
+                    arg!(-w --watch "Keep running and re-render whenever a new Dolt commit is made")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--interval [SECONDS] "How often to check for new commits in watch mode")
+                        .default_value("2")
+                        .value_parser(clap::value_parser!(u64)),
+                    arg!(--debounce [MILLIS] "How long the HEAD commit must stay the same before re-rendering in watch mode")
+                        .default_value("500")
+                        .value_parser(clap::value_parser!(u64)),
+                ])
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Opens a terminal UI for browsing and editing the configuration values for an environment")
+                .long_about("Opens a terminal UI for browsing and editing the configuration values for an environment. Values are checked against their types as they're edited. Saving writes the changed values to the database and commits them.")
+                .args([
+                    arg!(-e --env [ENV] "The environment to edit")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    commit::message_arg("Edit config values for {env}"),
+                ])
+                .args(commit::author_args()),
+        )
+        .subcommand(
+            Command::new("types")
+                .about("Lists the value types that config values can have")
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Prints a summary of the environments and configuration values in the database")
+                .args([
+                    arg!(-e --env [ENV] "Only report on this environment")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-d --dir [DIR] "The site directory containing the database")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-n --"db-name" [DB_NAME] "The name of the DB")
+                        .default_value("de_releases")
+                        .value_parser(clap::value_parser!(String)),
                 ])
         )
+        .subcommand(
+            Command::new("export")
+                .about("Bundles the site's database, values files, and repo list into a single archive")
+                .args([
+                    arg!(-d --dir [DIR] "The site directory to export")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-n --"db-name" [DB_NAME] "The name of the DB")
+                        .default_value("de_releases")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-o --out <OUT> "The file to write the bundle to")
+                        .long_help("The file to write the bundle to. The bundle is a zstd-compressed tarball, so it should usually end with .tar.zst. The database must not be running while it's exported.")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"defaults-filename" [DEFAULTS_FILENAME] "The file in the site directory containing the default configuration values")
+                        .default_value("defaults.yaml")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"values-filename" [VALUES_FILENAME] "The file in the site directory containing the configuration values for the environment")
+                        .default_value("deployment.yaml")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ])
+        )
+        .subcommand(
+            Command::new("export-values")
+                .about("Writes the configuration values for every environment to a single file")
+                .long_about("Writes the configuration values for every environment in the database to a single document keyed by environment name, for backing up or diffing a whole site. Everything in it is sorted, so exporting the same database twice gives the same file.")
+                .args([
+                    arg!(-o --out [OUT] "The file to write the values to. Defaults to stdout")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--format [FORMAT] "The format to write the values in")
+                        .long_help("The format to write the values in. Defaults to the format matching the file's extension (.yaml, .yml, or .json), then to YAML.")
+                        .value_parser(clap::builder::PossibleValuesParser::new(["yaml", "json"])),
+                ])
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Recreates a site from a bundle written by the export subcommand")
+                .args([
+                    arg!(-b --bundle <BUNDLE> "The bundle file to import")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-d --dir [DIR] "The site directory to import into")
+                        .long_help("The site directory to import into. It's created if it doesn't exist. Importing into a directory left behind by a failed import picks up where it left off.")
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-R --"no-repo-clone" "Do not clone the repos listed in the bundle")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--submodules "Initialize and update the repos' submodules after checking them out")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                ])
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Prints the JSON Schema for the defaults.yaml and deployment.yaml files")
+                .long_about("Prints the JSON Schema for the defaults.yaml and deployment.yaml files, generated from the types mgmt reads them into. Point an editor with YAML language server support at it to validate the files as they're edited.")
+                .args([
+                    arg!(-o --output [FILE] "The file to write the schema to instead of stdout")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ])
+        )
+        .subcommand(
+            Command::new("selftest")
+                .about("Checks that mgmt and Dolt are set up correctly")
+                .long_about("Checks that mgmt and Dolt are set up correctly by creating a throwaway Dolt database in a temporary directory, applying the migrations, setting a couple of config values, and rendering them. Nothing is left behind afterwards. Doesn't use the database given with --database-url.")
+        )
+        .subcommand(
+            Command::new("db")
+                .about("Commands for working with the database")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("ping")
+                        .about("Checks that the database is reachable, reporting its latency and version")
+                )
+        )
 }
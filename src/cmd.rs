@@ -0,0 +1,482 @@
+//! # Command Runner
+//!
+//! A small wrapper around `std::process::Command` for running the external
+//! tools `mgmt` depends on. Errors include the command line that was run so
+//! it's clear which step failed.
+use crate::exit;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// The tools whose executables can be overridden with a `--<tool>-binary`
+/// flag or a `MGMT_<TOOL>` environment variable.
+pub const OVERRIDABLE_PROGRAMS: [&str; 2] = ["dolt", "git"];
+
+// The executables passed in with the --<tool>-binary flags.
+static PROGRAMS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Sets the executables to run in place of the tools' bare names. Only the
+/// first call has any effect.
+///
+/// # Examples
+/// ```ignore
+///     cmd::init_programs([("dolt".to_string(), "/opt/dolt/bin/dolt".to_string())].into());
+/// ```
+pub fn init_programs(programs: HashMap<String, String>) {
+    let _ = PROGRAMS.set(programs);
+}
+
+// Returns the name of the environment variable that overrides a tool's
+// executable, such as MGMT_DOLT.
+fn program_var(name: &str) -> String {
+    format!("MGMT_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// Returns the executable to run for a tool. A path passed in with the tool's
+/// `--<tool>-binary` flag wins, then the `MGMT_<TOOL>` environment variable,
+/// and otherwise the bare name is looked up on the PATH.
+///
+/// # Examples
+/// ```ignore
+///     let output = std::process::Command::new(cmd::program("git")).arg("--version").output()?;
+/// ```
+pub fn program(name: &str) -> String {
+    if !OVERRIDABLE_PROGRAMS.contains(&name) {
+        return name.to_string();
+    }
+    if let Some(program) = PROGRAMS.get().and_then(|p| p.get(name)) {
+        return program.clone();
+    }
+    std::env::var(program_var(name))
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| name.to_string())
+}
+
+// Returns a command line as it would be typed into a shell.
+fn command_line<'a>(program: &'a str, args: impl Iterator<Item = &'a str>) -> String {
+    std::iter::once(program)
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How much of a failed command's stderr is included in its error, in
+/// characters. Tools print why they failed at the end, so that's what's kept.
+pub const MAX_STDERR_LEN: usize = 2000;
+
+/// Returns a command's stderr the way it's included in an error: trimmed,
+/// and cut down to its last `MAX_STDERR_LEN` characters.
+///
+/// # Examples
+/// ```ignore
+///     let message = format!("dolt pull failed: {}", cmd::stderr_excerpt(&output.stderr));
+/// ```
+pub fn stderr_excerpt(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    let len = stderr.chars().count();
+    if len <= MAX_STDERR_LEN {
+        return stderr.to_string();
+    }
+    let tail = stderr
+        .chars()
+        .skip(len - MAX_STDERR_LEN)
+        .collect::<String>();
+    format!("...{}", tail)
+}
+
+/// Stops the commands run with it, such as when an overall timeout runs out
+/// while they're running on the blocking thread pool, where dropping the
+/// future that's waiting on them doesn't stop them. Clones share the same
+/// state.
+///
+/// # Examples
+/// ```ignore
+///     let cancel = cmd::Cancel::default();
+///     let output = Command::new("git").args(["fetch"]).output_unless_canceled(&cancel)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// Kills the commands that are running with this and keeps any more from
+    /// starting.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// How often a running command checks whether it's been canceled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Reads a child's output on its own thread, so that a child filling one pipe
+// doesn't block while the other one is being read.
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+// Like `Command::output`, but kills the child if the command is canceled
+// while it's running.
+fn output_unless_canceled(command: &mut Command, cancel: &Cancel) -> io::Result<Output> {
+    let canceled = || io::Error::new(io::ErrorKind::Interrupted, "the command was canceled");
+    if cancel.is_canceled() {
+        return Err(canceled());
+    }
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancel.is_canceled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(canceled());
+        }
+        std::thread::sleep(CANCEL_POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Extensions for the places that build a `std::process::Command` directly
+/// instead of using `Cmd`.
+pub trait CommandExt {
+    /// Logs the command line at debug level. Call it right before the
+    /// command is run.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let status = Command::new(cmd::program("git")).args(["status"]).logged().status()?;
+    /// ```
+    fn logged(&mut self) -> &mut Self;
+
+    /// Runs the command with its output captured, returning an error that
+    /// includes the command line and its stderr if it can't be run or exits
+    /// unsuccessfully. The captured stdout is logged at debug level.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     Command::new(cmd::program("git")).args(["pull"]).current_dir(dir).checked_output()?;
+    /// ```
+    fn checked_output(&mut self) -> Result<Output>;
+
+    /// Like `output`, but the command is killed if `cancel` is canceled
+    /// while it's running, and isn't started at all if it already was.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let output = Command::new(cmd::program("git")).args(["clone", url]).logged().output_unless_canceled(&cancel)?;
+    /// ```
+    fn output_unless_canceled(&mut self, cancel: &Cancel) -> io::Result<Output>;
+
+    /// Like `checked_output`, but the command is killed if `cancel` is
+    /// canceled while it's running.
+    fn checked_output_unless_canceled(&mut self, cancel: &Cancel) -> Result<Output>;
+}
+
+impl CommandExt for Command {
+    fn logged(&mut self) -> &mut Self {
+        tracing::debug!(
+            command = %command_display(self),
+            dir = ?self.get_current_dir(),
+            "running command"
+        );
+        self
+    }
+
+    fn checked_output(&mut self) -> Result<Output> {
+        let output = self.logged().output();
+        check_output(self, output)
+    }
+
+    fn output_unless_canceled(&mut self, cancel: &Cancel) -> io::Result<Output> {
+        output_unless_canceled(self, cancel)
+    }
+
+    fn checked_output_unless_canceled(&mut self, cancel: &Cancel) -> Result<Output> {
+        let output = output_unless_canceled(self.logged(), cancel);
+        check_output(self, output)
+    }
+}
+
+// Turns the result of running a command into an error that includes the
+// command line and its stderr if it couldn't be run or exited unsuccessfully.
+fn check_output(command: &Command, output: io::Result<Output>) -> Result<Output> {
+    let output = output.with_context(|| {
+        exit::Error::ExternalTool(format!("failed to run `{}`", command_display(command)))
+    })?;
+
+    if !output.status.success() {
+        let dir = command
+            .get_current_dir()
+            .map(|d| format!(" in {}", d.display()))
+            .unwrap_or_default();
+        return Err(exit::Error::ExternalTool(format!(
+            "`{}` failed{} with {}: {}",
+            command_display(command),
+            dir,
+            output.status,
+            stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    tracing::debug!(
+        stdout = %String::from_utf8_lossy(&output.stdout).trim(),
+        "command succeeded"
+    );
+    Ok(output)
+}
+
+// Returns the command line of a std::process::Command.
+fn command_display(command: &Command) -> String {
+    let program = command.get_program().to_string_lossy();
+    let args = command
+        .get_args()
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>();
+    command_line(&program, args.iter().map(|a| &**a))
+}
+
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    program: String,
+    args: Vec<String>,
+    dir: Option<PathBuf>,
+    envs: Vec<(String, String)>,
+    cancel: Option<Cancel>,
+}
+
+impl Cmd {
+    /// Creates a new command that runs the given program. Programs that can
+    /// be overridden are resolved with `program`.
+    pub fn new(program: &str) -> Self {
+        Cmd {
+            program: self::program(program),
+            args: Vec::new(),
+            dir: None,
+            envs: Vec::new(),
+            cancel: None,
+        }
+    }
+
+    /// Creates a new command that runs a script with `sh -c`.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let status = cmd::Cmd::shell("make generate").dir("repos/terrain").status()?;
+    /// ```
+    pub fn shell(script: &str) -> Self {
+        Cmd::new("sh").arg("-c").arg(script)
+    }
+
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_string()));
+        self
+    }
+
+    /// Sets the working directory the command runs in.
+    pub fn dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets an environment variable for the command.
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.envs.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Kills the command if `cancel` is canceled while its output is being
+    /// captured by `output`.
+    pub fn canceled_by(mut self, cancel: &Cancel) -> Self {
+        self.cancel = Some(cancel.clone());
+        self
+    }
+
+    /// Returns the command line as it would be typed into a shell. Used in
+    /// error messages.
+    pub fn display(&self) -> String {
+        command_line(&self.program, self.args.iter().map(|a| a.as_str()))
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        for (key, value) in &self.envs {
+            command.env(key, value);
+        }
+        command.logged();
+        command
+    }
+
+    /// Runs the command with its output going to the terminal and returns
+    /// the exit status.
+    pub fn status(&self) -> Result<ExitStatus> {
+        self.command().status().with_context(|| {
+            exit::Error::ExternalTool(format!("failed to run `{}`", self.display()))
+        })
+    }
+
+    /// Runs the command with its output going to the terminal, returning an
+    /// error if it exits unsuccessfully.
+    pub fn run(&self) -> Result<()> {
+        let status = self.status()?;
+        if !status.success() {
+            return Err(exit::Error::ExternalTool(format!(
+                "`{}` failed with {}",
+                self.display(),
+                status
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Runs the command and returns its captured stdout, returning an error
+    /// containing its stderr if it exits unsuccessfully.
+    pub fn output(&self) -> Result<String> {
+        let mut command = self.command();
+        let output = match &self.cancel {
+            Some(cancel) => output_unless_canceled(&mut command, cancel),
+            None => command.output(),
+        };
+        let output = output.with_context(|| {
+            exit::Error::ExternalTool(format!("failed to run `{}`", self.display()))
+        })?;
+
+        if !output.status.success() {
+            return Err(exit::Error::ExternalTool(format!(
+                "`{}` failed with {}: {}",
+                self.display(),
+                output.status,
+                stderr_excerpt(&output.stderr)
+            ))
+            .into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_var() {
+        assert_eq!(program_var("dolt"), "MGMT_DOLT");
+        assert_eq!(program_var("git"), "MGMT_GIT");
+    }
+
+    #[test]
+    fn test_stderr_excerpt() {
+        assert_eq!(stderr_excerpt(b"  fatal: not found\n"), "fatal: not found");
+        let long = format!("{}fatal: not found", "x".repeat(MAX_STDERR_LEN));
+        let excerpt = stderr_excerpt(long.as_bytes());
+        assert!(excerpt.starts_with("...x"));
+        assert!(excerpt.ends_with("fatal: not found"));
+        assert_eq!(excerpt.chars().count(), MAX_STDERR_LEN + 3);
+    }
+
+    #[test]
+    fn test_checked_output() {
+        let err = Command::new("sh")
+            .args(["-c", "echo oops >&2; exit 3"])
+            .checked_output()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`sh -c echo oops >&2; exit 3` failed with exit status: 3: oops"
+        );
+        let output = Command::new("sh")
+            .args(["-c", "echo ok"])
+            .checked_output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+    }
+
+    #[test]
+    fn test_output_unless_canceled() {
+        let cancel = Cancel::default();
+        let output = Command::new("sh")
+            .args(["-c", "echo ok; echo oops >&2"])
+            .output_unless_canceled(&cancel)
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+
+        let start = std::time::Instant::now();
+        std::thread::spawn({
+            let cancel = cancel.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+            }
+        });
+        let err = Command::new("sleep")
+            .arg("10")
+            .output_unless_canceled(&cancel)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(Cmd::shell("echo ok").canceled_by(&cancel).output().is_err());
+    }
+
+    #[test]
+    fn test_command_line() {
+        assert_eq!(
+            Cmd::new("sh").arg("-c").arg("make generate").display(),
+            "sh -c make generate"
+        );
+        assert_eq!(command_line("git", std::iter::empty()), "git");
+    }
+
+    #[test]
+    fn test_program_only_overrides_known_tools() {
+        assert_eq!(program("sh"), "sh");
+    }
+}
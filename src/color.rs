@@ -0,0 +1,36 @@
+//! # Color
+//!
+//! Controls whether output is colored. Colors are applied with
+//! `console::style`, which checks the setting made here before adding any
+//! escape codes.
+use anyhow::{anyhow, Result};
+
+/// The values accepted by the `--color` flag.
+pub const CHOICES: [&str; 3] = ["auto", "always", "never"];
+
+/// Enables or disables colored output for stdout and stderr. `auto` colors
+/// the output only when it's going to a terminal and the `NO_COLOR`
+/// environment variable isn't set.
+///
+/// # Examples
+/// ```ignore
+///     color::init("auto")?;
+///     println!("{}", console::style("Errors:").red().bold());
+/// ```
+pub fn init(choice: &str) -> Result<()> {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let (stdout, stderr) = match choice {
+        "always" => (true, true),
+        "never" => (false, false),
+        "auto" => (
+            !no_color && console::Term::stdout().is_term(),
+            !no_color && console::Term::stderr().is_term(),
+        ),
+        _ => return Err(anyhow!("unknown color choice: {}", choice)),
+    };
+
+    console::set_colors_enabled(stdout);
+    console::set_colors_enabled_stderr(stderr);
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! # Commit
+//!
+//! The author and message options shared by the commands that commit to git
+//! or Dolt. Without them, commits are made with whatever identity git or Dolt
+//! is configured with, which is often wrong or missing in automation.
+use crate::{exit, git};
+use anyhow::Result;
+use clap::{arg, Arg, ArgMatches};
+use std::fmt;
+
+/// The identity commits are made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}>", self.name, self.email)
+    }
+}
+
+/// Returns the `--author-name` and `--author-email` arguments.
+pub fn author_args() -> [Arg; 2] {
+    [
+        arg!(--"author-name" [NAME] "The name to commit the changes as")
+            .long_help("The name to commit the changes as. Defaults to the GIT_AUTHOR_NAME environment variable, then to git's user.name setting.")
+            .value_parser(clap::value_parser!(String)),
+        arg!(--"author-email" [EMAIL] "The email address to commit the changes as")
+            .long_help("The email address to commit the changes as. Defaults to the GIT_AUTHOR_EMAIL environment variable, then to git's user.email setting.")
+            .value_parser(clap::value_parser!(String)),
+    ]
+}
+
+/// Returns the `--message` argument. The default is used when the argument
+/// isn't given.
+pub fn message_arg(default: &'static str) -> Arg {
+    arg!(-m --message [MESSAGE] "The commit message")
+        .long_help("The commit message. {env}, {date}, {time}, and {service} are replaced with the environment, today's date, the current time, and the services involved.")
+        .default_value(default)
+        .value_parser(clap::value_parser!(String))
+}
+
+// Looks up one half of the author, trying the argument, then the environment
+// variable, then the git setting.
+fn lookup(matches: &ArgMatches, id: &str, var: &str, setting: &str) -> Result<Option<String>> {
+    if let Some(value) = matches.get_one::<String>(id) {
+        return Ok(Some(value.clone()));
+    }
+    if let Some(value) = std::env::var(var).ok().filter(|v| !v.is_empty()) {
+        return Ok(Some(value));
+    }
+    git::config_value(setting)
+}
+
+/// Returns the author selected with the `--author-name` and `--author-email`
+/// arguments, falling back to the environment and then to git's settings.
+/// Returns `None` if no author is configured anywhere, in which case commits
+/// are left to the tools' own defaults.
+pub fn get_author(matches: &ArgMatches) -> Result<Option<Author>> {
+    let name = lookup(matches, "author-name", "GIT_AUTHOR_NAME", "user.name")?;
+    let email = lookup(matches, "author-email", "GIT_AUTHOR_EMAIL", "user.email")?;
+
+    match (name, email) {
+        (Some(name), Some(email)) => Ok(Some(Author { name, email })),
+        (None, None) => Ok(None),
+        (Some(_), None) => Err(exit::Error::Usage(
+            "An author name was found but no email. Use --author-email <email> to specify one."
+                .to_string(),
+        )
+        .into()),
+        (None, Some(_)) => Err(exit::Error::Usage(
+            "An author email was found but no name. Use --author-name <name> to specify one."
+                .to_string(),
+        )
+        .into()),
+    }
+}
+
+fn substitute(template: &str, env: &str, date: &str, time: &str, service: &str) -> String {
+    template
+        .replace("{env}", env)
+        .replace("{date}", date)
+        .replace("{time}", time)
+        .replace("{service}", service)
+}
+
+/// Fills in the `{env}`, `{date}`, `{time}`, and `{service}` placeholders in a
+/// commit message template. Any other text is left alone.
+///
+/// # Examples
+/// ```ignore
+///     let message = commit::render_message("Release {env} on {date}", "qa", "");
+/// ```
+pub fn render_message(template: &str, env: &str, service: &str) -> String {
+    let now = chrono::Local::now();
+    let date = now.format("%Y-%m-%d").to_string();
+    let time = now.format("%H:%M:%S %Z").to_string();
+    substitute(template, env, &date, &time, service)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(
+            substitute(
+                "Release {env} ({service}) on {date} at {time}",
+                "qa",
+                "2024-01-02",
+                "13:04:05 +00:00",
+                "apps"
+            ),
+            "Release qa (apps) on 2024-01-02 at 13:04:05 +00:00"
+        );
+        assert_eq!(
+            substitute("update builds", "qa", "2024-01-02", "13:04:05 +00:00", ""),
+            "update builds"
+        );
+        assert_eq!(
+            substitute("{unknown}", "qa", "2024-01-02", "13:04:05 +00:00", ""),
+            "{unknown}"
+        );
+    }
+
+    #[test]
+    fn test_author_display() {
+        let author = Author {
+            name: "Jane Doe".to_string(),
+            email: "jane@example.org".to_string(),
+        };
+        assert_eq!(author.to_string(), "Jane Doe <jane@example.org>");
+    }
+}
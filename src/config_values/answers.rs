@@ -0,0 +1,226 @@
+//! # Answers
+//!
+//! Answers to the questions `ask_for_info` asks, given ahead of time as
+//! `section.key=value` lines so that an environment can be set up from a
+//! script:
+//!
+//! ```text
+//! echo "DashboardAggregator.Port=8080" | mgmt site init --answers - ...
+//! ```
+//!
+//! Answers are read from the file named by `--answers`, or from stdin when
+//! it's `-`, and from `--set` arguments. When the same key is answered both
+//! ways, `--set` wins. Keys
+//! that aren't answered keep their defaults, and keys without a default are
+//! prompted for, or reported as missing when prompting is turned off.
+use crate::{config_values::value_type::ValueType, db, exit, suggest};
+use anyhow::Result;
+use sqlx::{Postgres, Transaction};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+/// The answers, keyed by section and key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Answers {
+    values: BTreeMap<(String, String), String>,
+}
+
+// Splits a `section.key=value` answer into its parts. The value is everything
+// after the first '=', so it may contain '=' itself.
+fn parse_answer(answer: &str) -> Option<(String, String, String)> {
+    let (name, value) = answer.split_once('=')?;
+    let (section, key) = name.trim().split_once('.')?;
+    if section.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some((section.to_string(), key.to_string(), value.to_string()))
+}
+
+/// Parses `--set section.key=value` arguments into a map keyed by section and
+/// key. A later argument for the same key replaces an earlier one.
+///
+/// # Examples
+/// ```ignore
+///     let overrides = answers::parse_set_args(matches.get_many::<String>("set").unwrap_or_default())?;
+/// ```
+pub fn parse_set_args<'a, I: IntoIterator<Item = &'a String>>(
+    args: I,
+) -> Result<BTreeMap<(String, String), String>> {
+    let mut values = BTreeMap::new();
+    for arg in args {
+        let (section, key, value) = parse_answer(arg).ok_or_else(|| {
+            exit::Error::Usage(format!("--set {}: expected section.key=value", arg))
+        })?;
+        values.insert((section, key), value);
+    }
+    Ok(values)
+}
+
+impl Answers {
+    /// Parses `section.key=value` lines. Blank lines and lines starting with
+    /// '#' are skipped. `source` names where the lines came from in errors.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let answers = Answers::parse("DE.BaseURI=https://de.example.org\n".as_bytes(), "stdin")?;
+    /// ```
+    pub fn parse<R: BufRead>(reader: R, source: &str) -> Result<Self> {
+        let mut answers = Answers::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let (section, key, value) = parse_answer(line).ok_or_else(|| {
+                exit::Error::Usage(format!(
+                    "line {} of {}: expected section.key=value, got '{}'",
+                    i + 1,
+                    source,
+                    line
+                ))
+            })?;
+            answers.values.insert((section, key), value);
+        }
+        Ok(answers)
+    }
+
+    /// Reads answers from a file, or from stdin if the path is `-`. Stdin is
+    /// only read when it's asked for, so that a run whose stdin is left open,
+    /// like under a CI runner, doesn't wait on input that was never going to
+    /// come.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let answers = Answers::from_path("answers.txt")?;
+    /// ```
+    pub fn from_path(path: &str) -> Result<Self> {
+        if path == "-" {
+            return Answers::parse(std::io::stdin().lock(), "stdin");
+        }
+        let file = std::fs::File::open(path)
+            .map_err(|e| exit::Error::Usage(format!("can't read answers from {}: {}", path, e)))?;
+        Answers::parse(std::io::BufReader::new(file), path)
+    }
+
+    /// Parses the values of the `--set` arguments.
+    pub fn from_args<'a, I: IntoIterator<Item = &'a String>>(args: I) -> Result<Self> {
+        Ok(Answers {
+            values: parse_set_args(args)?,
+        })
+    }
+
+    /// Adds the answers in `other`, replacing any answers for the same keys.
+    pub fn merge(&mut self, other: Answers) {
+        self.values.extend(other.values);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns the answer for a key, if there is one.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.values
+            .get(&(section.to_string(), key.to_string()))
+            .map(|v| v.as_str())
+    }
+
+    /// Returns the sections that have at least one answer.
+    pub fn sections(&self) -> Vec<&str> {
+        let mut sections = self
+            .values
+            .keys()
+            .map(|(section, _)| section.as_str())
+            .collect::<Vec<_>>();
+        sections.dedup();
+        sections
+    }
+
+    /// Matches the answers up with the keys in the database, which may differ
+    /// in case, and checks the values against the keys' types. Returns an
+    /// error naming the closest key for any answer that doesn't match one.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let answers = answers.resolve(&mut tx).await?;
+    /// ```
+    pub async fn resolve(self, tx: &mut Transaction<'_, Postgres>) -> Result<Self> {
+        let defaults = db::list_default_config_values(tx, None, None).await?;
+        let names = defaults
+            .iter()
+            .map(|d| format!("{}.{}", d.section, d.key))
+            .collect::<Vec<_>>();
+
+        let mut resolved = Answers::default();
+        for ((section, key), value) in self.values {
+            let default = defaults.iter().find(|d| {
+                d.section.eq_ignore_ascii_case(&section) && d.key.eq_ignore_ascii_case(&key)
+            });
+            let default = match default {
+                Some(default) => default,
+                None => {
+                    let name = format!("{}.{}", section, key);
+                    return Err(exit::Error::Usage(format!(
+                        "unknown config key {}{}",
+                        name,
+                        suggest::did_you_mean(&name, &names)
+                    ))
+                    .into());
+                }
+            };
+
+            let value_type = default.value_type.parse::<ValueType>()?;
+            value_type.validate(&value).map_err(|e| {
+                exit::Error::Usage(format!("{}.{}: {}", default.section, default.key, e))
+            })?;
+
+            resolved
+                .values
+                .insert((default.section.clone(), default.key.clone()), value);
+        }
+
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = "# comment\n\nDashboardAggregator.Port=8080\nDE.BaseURI=https://de.example.org/?a=b\r\n";
+        let answers = Answers::parse(input.as_bytes(), "stdin").unwrap();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers.get("DashboardAggregator", "Port"), Some("8080"));
+        assert_eq!(
+            answers.get("DE", "BaseURI"),
+            Some("https://de.example.org/?a=b")
+        );
+        assert_eq!(answers.sections(), vec!["DE", "DashboardAggregator"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Answers::parse("DE.BaseURI=x\nnonsense\n".as_bytes(), "stdin").unwrap_err();
+        assert!(err.to_string().contains("line 2 of stdin"), "{}", err);
+        assert!(Answers::parse("Port=8080\n".as_bytes(), "stdin").is_err());
+        assert!(Answers::parse(".Port=8080\n".as_bytes(), "stdin").is_err());
+    }
+
+    #[test]
+    fn test_merge_prefers_other() {
+        let mut answers =
+            Answers::parse("DE.BaseURI=stdin\nDE.Subdomain=de\n".as_bytes(), "stdin").unwrap();
+        let set = vec!["DE.BaseURI=flag".to_string()];
+        answers.merge(Answers::from_args(&set).unwrap());
+        assert_eq!(answers.get("DE", "BaseURI"), Some("flag"));
+        assert_eq!(answers.get("DE", "Subdomain"), Some("de"));
+    }
+}
@@ -1,11 +1,12 @@
 use crate::db::{add_env_cfg_value, set_config_value, ConfigurationValue, LoadFromDatabase};
 use anyhow::{Context, Result};
 use dialoguer::{theme::ColorfulTheme, Input};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::{Postgres, Transaction};
 use url::Url;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct CAS {
     #[serde(skip)]
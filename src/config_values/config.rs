@@ -1,14 +1,19 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
 use crate::config_values::{
-    self, agave::Agave, base_urls::BaseURLs, dashboard_aggregator::DashboardAggregator,
-    db::DatabaseConfig, db::QMSDatabaseConfig, de::DE, docker::Docker,
-    elasticsearch::Elasticsearch, email::Email, grouper::Grouper, icat::Icat,
-    infosquito::Infosquito,
+    self, agave::Agave, answers::Answers, base_urls::BaseURLs,
+    dashboard_aggregator::DashboardAggregator, db::DatabaseConfig, db::QMSDatabaseConfig, de::DE,
+    docker::Docker, elasticsearch::Elasticsearch, email::Email, format::Format, grouper::Grouper,
+    icat::Icat, infosquito::Infosquito, interpolate, schema, value_type::ValueType,
 };
 use crate::db::{self, add_env_cfg_value, set_config_value, upsert_environment, LoadFromDatabase};
+use crate::{exit, lint, suggest};
 use anyhow::Context;
 use dialoguer::{console::Style, theme::ColorfulTheme, Input, Select};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres, Transaction};
 
@@ -244,11 +249,123 @@ impl SectionOptions {
             _ => true,
         }
     }
+
+    // Turns on an optional section. Sections that are always included are
+    // left alone.
+    fn enable_section(&mut self, section: &str) {
+        match section {
+            "Admin" => self.include_admin = true,
+            "Analytics" => self.include_analytics = true,
+            "Agave" => self.include_agave = true,
+            "BaseURLs" => self.include_base_urls = true,
+            "CAS" => self.include_cas = true,
+            "Docker" => self.include_docker = true,
+            "InfoSquito" => self.include_infosquito = true,
+            "Intercom" => self.include_intercom = true,
+            "Jaeger" => self.include_jaeger = true,
+            "Jobs" => self.include_jobs = true,
+            "JVMOpts" => self.include_jvmopts = true,
+            "PermanentID" => self.include_permanent_id = true,
+            "QA" => self.include_qa = true,
+            "QMS" => self.include_qms = true,
+            "Unleash" => self.include_unleash = true,
+            _ => {}
+        }
+    }
+}
+
+/// Checks that a value parses as the named value type before it's stored,
+/// returning the type. Fails with an error naming the key if the type isn't
+/// known or the value isn't valid for it. Empty values are accepted for every
+/// type, like `ValueType::validate` does, and so are values that refer to
+/// other values with `${section.key}`, since they can only be checked once
+/// the references are resolved.
+///
+/// # Examples
+/// ```ignore
+///     config::parse_typed_value("DE", "Port", "8080", "int")?;
+///     assert!(config::parse_typed_value("DE", "Port", "abc", "int").is_err());
+/// ```
+pub fn parse_typed_value(
+    section: &str,
+    key: &str,
+    value: &str,
+    value_type: &str,
+) -> anyhow::Result<ValueType> {
+    let parsed = value_type.parse::<ValueType>()?;
+    if interpolate::references(value).is_empty() && parsed.validate(value).is_err() {
+        return Err(exit::Error::Usage(format!(
+            "value '{}' is not a valid {} for key {}.{}",
+            value, parsed, section, key
+        ))
+        .into());
+    }
+    Ok(parsed)
+}
+
+/// Like `parse_typed_value`, but returns the value in the type's canonical
+/// spelling, which is what should be stored. Booleans like "yes" or "0" are
+/// accepted and become "true" or "false"; see `ValueType::normalize`.
+///
+/// # Examples
+/// ```ignore
+///     let value = config::normalize_typed_value("DE", "Debug", "yes", "bool")?;
+///     assert_eq!(value, "true");
+/// ```
+pub fn normalize_typed_value(
+    section: &str,
+    key: &str,
+    value: &str,
+    value_type: &str,
+) -> anyhow::Result<String> {
+    let value = match value_type.parse::<ValueType>() {
+        Ok(parsed) => parsed.normalize(value),
+        Err(_) => value.to_string(),
+    };
+    parse_typed_value(section, key, &value, value_type)?;
+    Ok(value)
+}
+
+/// A required config value that's missing or invalid, as found by
+/// `ConfigValues::validate`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("{section}.{key}: no value is set")]
+    Missing { section: String, key: String },
+
+    #[error("{section}.{key}: {reason}")]
+    Invalid {
+        section: String,
+        key: String,
+        reason: String,
+    },
+}
+
+/// Turns the errors from `ConfigValues::validate` into a single usage error
+/// listing all of them, one per line. `source` says where the values came
+/// from, e.g. the path of a values file.
+pub fn validation_failure(source: &str, errors: &[ValidationError]) -> anyhow::Error {
+    let lines = errors
+        .iter()
+        .map(|e| format!("  {}", e))
+        .collect::<Vec<_>>()
+        .join("\n");
+    exit::Error::Usage(format!(
+        "{} required config value(s) in {} are missing or invalid:\n{}",
+        errors.len(),
+        source,
+        lines
+    ))
+    .into()
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct ConfigValues {
+    // Version of the on-disk format. See the schema module.
+    #[serde(default)]
+    schema_version: u32,
+
     #[serde(skip)]
     section: String,
 
@@ -405,6 +522,7 @@ pub struct ConfigValues {
 impl Default for ConfigValues {
     fn default() -> Self {
         ConfigValues {
+            schema_version: schema::CURRENT_SCHEMA_VERSION,
             section: "TopLevel".to_string(),
             section_options: SectionOptions::default(),
             environment: String::new(),
@@ -880,6 +998,41 @@ impl ConfigValues {
         Self::default()
     }
 
+    /// Reads a values file from disk, migrating it to the current schema
+    /// version if necessary. Files written by a newer version of the tool are
+    /// rejected. The format comes from the file's extension, and files without
+    /// one are read as YAML. The section options are generated from the
+    /// sections present in the file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to open values file {}", path.display()))?;
+        let format = Format::resolve(None, Some(path))?;
+        ConfigValues::parse(&contents, format)
+            .with_context(|| format!("failed to read values file {}", path.display()))
+    }
+
+    /// Parses the contents of a values file in the given format, migrating it
+    /// to the current schema version if necessary.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let cv = ConfigValues::parse("Environment = \"qa\"\n", Format::Toml)?;
+    /// ```
+    pub fn parse(contents: &str, format: Format) -> anyhow::Result<Self> {
+        let doc = format.parse(contents)?;
+        let doc = schema::migrate(doc)?;
+
+        let mut cv: ConfigValues = serde_yaml::from_value(doc)?;
+        cv.set_section_options(cv.generate_section_options());
+
+        Ok(cv)
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
     pub fn set_section_options(&mut self, section_options: SectionOptions) {
         self.section_options = section_options;
     }
@@ -1053,6 +1206,115 @@ impl ConfigValues {
         Ok(new_cv)
     }
 
+    /// Checks that every required value in the included sections is set, that
+    /// the values that are set are valid for their types, and that the
+    /// namespace is a valid Kubernetes namespace, the same as `mgmt site lint`
+    /// does for the database. Which values are required comes from the schema;
+    /// see `schema::required_keys`. Returns all of the problems at once
+    /// instead of stopping at the first.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     values
+    ///         .validate()
+    ///         .map_err(|errors| config::validation_failure("deployment.yaml", &errors))?;
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+
+        // The schema is generated from these types, so it can't really fail
+        // to load, but if it does every value is treated as required.
+        let required = schema::required_keys().ok();
+
+        let values: Vec<db::ConfigurationValue> = self.clone().into();
+        for cv in values {
+            if cv.value.is_empty() {
+                let key = (cv.section, cv.key);
+                if required.as_ref().is_none_or(|r| r.contains(&key)) {
+                    errors.push(ValidationError::Missing {
+                        section: key.0,
+                        key: key.1,
+                    });
+                }
+                continue;
+            }
+            let reason = match cv.value_type.parse::<ValueType>() {
+                Ok(value_type) => value_type.validate(&cv.value).err().map(|e| e.to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(reason) = reason {
+                errors.push(ValidationError::Invalid {
+                    section: cv.section,
+                    key: cv.key,
+                    reason,
+                });
+            }
+        }
+
+        if !self.namespace.is_empty() && !lint::is_valid_namespace(&self.namespace) {
+            errors.push(ValidationError::Invalid {
+                section: "TopLevel".to_string(),
+                key: "Namespace".to_string(),
+                reason: format!("'{}' is not a valid Kubernetes namespace", self.namespace),
+            });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns a copy of the values with the overrides, keyed by section and
+    /// key, replacing the values of those keys. Each override is checked
+    /// against the type of the key it replaces. Fails if a key isn't in the
+    /// values, so that a typo doesn't silently leave the value unchanged.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let overrides = answers::parse_set_args(&["DE.Port=8080".to_string()])?;
+    ///     let values = values.with_overrides(&overrides)?;
+    /// ```
+    pub fn with_overrides(
+        &self,
+        overrides: &BTreeMap<(String, String), String>,
+    ) -> anyhow::Result<ConfigValues> {
+        let mut values: Vec<db::ConfigurationValue> = self.clone().into();
+
+        for ((section, key), value) in overrides {
+            let cv = match values
+                .iter_mut()
+                .find(|cv| &cv.section == section && &cv.key == key)
+            {
+                Some(cv) => cv,
+                None => {
+                    let known = values
+                        .iter()
+                        .map(|cv| format!("{}.{}", cv.section, cv.key))
+                        .collect::<Vec<_>>();
+                    let name = format!("{}.{}", section, key);
+                    let hint = suggest::closest(&name, &known)
+                        .map(|closest| format!(" Did you mean {}?", closest))
+                        .unwrap_or_default();
+                    return Err(exit::Error::Usage(format!(
+                        "--set {}: there's no {} in the loaded config values.{}",
+                        name, name, hint
+                    ))
+                    .into());
+                }
+            };
+            parse_typed_value(section, key, value, &cv.value_type)?;
+            cv.value = value.clone();
+        }
+
+        let mut new_cv = ConfigValues::from(values);
+        let section_options = new_cv.generate_section_options();
+        new_cv.set_section_options(section_options);
+
+        Ok(new_cv)
+    }
+
     pub async fn ask_for_info(&mut self, tx: &mut Transaction<'_, Postgres>) -> anyhow::Result<()> {
         let mut theme = ColorfulTheme::default();
         theme.hint_style = Style::new().yellow();
@@ -1406,6 +1668,292 @@ impl ConfigValues {
         self.section_options = section_options;
         db::upsert_feature_flags(tx, &self.environment, &section_options.into()).await?;
 
+        self.validate()
+            .map_err(|errors| validation_failure("the answers", &errors))?;
+
         Ok(())
     }
+
+    /// Sets up an environment from answers given ahead of time instead of
+    /// asking for each value. The optional sections that have answers are
+    /// turned on, keys that aren't answered keep their defaults, and keys
+    /// without a default are prompted for. When `prompt` is false, missing
+    /// values are reported as an error instead.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let answers = Answers::from_path("-")?.resolve(&mut tx).await?;
+    ///     env_config.apply_answers(&mut tx, &answers, true).await?;
+    /// ```
+    pub async fn apply_answers(
+        &mut self,
+        tx: &mut Transaction<'_, Postgres>,
+        answers: &Answers,
+        prompt: bool,
+    ) -> anyhow::Result<()> {
+        let mut theme = ColorfulTheme::default();
+        theme.hint_style = Style::new().yellow();
+
+        let environment = match answers.get("TopLevel", "Environment") {
+            Some(environment) => environment.to_string(),
+            None if prompt => Input::<String>::with_theme(&theme)
+                .with_prompt("Environment")
+                .interact()?,
+            None => {
+                return Err(exit::Error::Usage(
+                    "No environment was answered. Pass TopLevel.Environment=<env> on stdin or with --set."
+                        .to_string(),
+                )
+                .into())
+            }
+        };
+        let namespace = answers
+            .get("TopLevel", "Namespace")
+            .map(|ns| ns.to_string())
+            .unwrap_or_else(|| environment.clone());
+
+        let env_id = upsert_environment(tx, &environment, &namespace).await?;
+        self.environment = environment.clone();
+        self.namespace = namespace.clone();
+
+        let mut section_options = SectionOptions::default();
+        section_options.include_base_urls = true;
+        for section in answers.sections() {
+            section_options.enable_section(section);
+        }
+
+        let mut missing = Vec::new();
+        for default in db::list_default_config_values(tx, None, None).await? {
+            if !section_options.include_section(&default.section) {
+                continue;
+            }
+
+            let value = match (default.section.as_str(), default.key.as_str()) {
+                ("TopLevel", "Environment") => environment.clone(),
+                ("TopLevel", "Namespace") => namespace.clone(),
+                (section, key) => match answers.get(section, key) {
+                    Some(value) => value.to_string(),
+                    None if !default.value.is_empty() => continue,
+                    None if prompt => {
+                        let value_type = default.value_type.parse::<ValueType>()?;
+                        let answer: String = Input::<String>::with_theme(&theme)
+                            .with_prompt(format!("{}.{}", section, key))
+                            .validate_with(move |v: &String| {
+                                value_type.validate(v).map_err(|e| e.to_string())
+                            })
+                            .interact()?;
+                        answer
+                    }
+                    None => {
+                        missing.push(format!("{}.{}", section, key));
+                        continue;
+                    }
+                },
+            };
+
+            if db::has_config_value(tx, &environment, &default.section, &default.key).await? {
+                db::update_env_cfg_value(
+                    tx,
+                    &environment,
+                    &default.section,
+                    &default.key,
+                    &value,
+                    &default.value_type,
+                )
+                .await?;
+            } else {
+                let cfg_id = set_config_value(
+                    tx,
+                    &default.section,
+                    &default.key,
+                    &value,
+                    &default.value_type,
+                )
+                .await?;
+                add_env_cfg_value(tx, env_id, cfg_id).await?;
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(exit::Error::Usage(format!(
+                "no value was given for these keys, which have no default: {}",
+                missing.join(", ")
+            ))
+            .into());
+        }
+
+        self.section_options = section_options;
+        db::upsert_feature_flags(tx, &self.environment, &section_options.into()).await?;
+
+        self.validate()
+            .map_err(|errors| validation_failure("the answers", &errors))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_typed_value() {
+        assert_eq!(
+            parse_typed_value("DE", "Port", "8080", "int").unwrap(),
+            ValueType::Int
+        );
+        assert_eq!(
+            parse_typed_value("DE", "Debug", "true", "bool").unwrap(),
+            ValueType::Bool
+        );
+        assert_eq!(
+            parse_typed_value("DE", "Ratio", "0.5", "float").unwrap(),
+            ValueType::Float
+        );
+        assert_eq!(
+            parse_typed_value("DE", "Name", "abc", "string").unwrap(),
+            ValueType::String
+        );
+        assert_eq!(
+            parse_typed_value("DE", "Port", "", "int").unwrap(),
+            ValueType::Int
+        );
+        assert_eq!(
+            parse_typed_value("DE", "Port", "${DEDB.Port}", "int").unwrap(),
+            ValueType::Int
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let errors = ConfigValues::default().validate().unwrap_err();
+        assert!(errors.contains(&ValidationError::Missing {
+            section: "TopLevel".to_string(),
+            key: "Environment".to_string(),
+        }));
+        assert!(errors.len() > 1);
+
+        // Only the values the schema requires have to be set.
+        let mut values = ConfigValues::default();
+        let mut section_options = SectionOptions::default();
+        section_options.set_all(true).unwrap();
+        values.set_section_options(section_options);
+        let errors = values.validate().unwrap_err();
+        assert!(errors.contains(&ValidationError::Missing {
+            section: "TopLevel".to_string(),
+            key: "Namespace".to_string(),
+        }));
+        assert!(!errors.contains(&ValidationError::Missing {
+            section: "DE".to_string(),
+            key: "Info.FAQ".to_string(),
+        }));
+        assert!(!errors.contains(&ValidationError::Missing {
+            section: "Agave".to_string(),
+            key: "Key".to_string(),
+        }));
+
+        let values = ConfigValues::from(vec![db::ConfigurationValue {
+            id: 0,
+            section: "TopLevel".to_string(),
+            key: "Namespace".to_string(),
+            value: "Not_A_Namespace".to_string(),
+            value_type: "string".to_string(),
+        }]);
+        let errors = values.validate().unwrap_err();
+        let namespace = errors
+            .iter()
+            .find(|e| matches!(e, ValidationError::Invalid { key, .. } if key == "Namespace"))
+            .unwrap();
+        assert_eq!(
+            namespace.to_string(),
+            "TopLevel.Namespace: 'Not_A_Namespace' is not a valid Kubernetes namespace"
+        );
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let values = ConfigValues::from(vec![
+            db::ConfigurationValue {
+                id: 0,
+                section: "TopLevel".to_string(),
+                key: "Environment".to_string(),
+                value: "qa".to_string(),
+                value_type: "string".to_string(),
+            },
+            db::ConfigurationValue {
+                id: 0,
+                section: "TopLevel".to_string(),
+                key: "Namespace".to_string(),
+                value: "qa".to_string(),
+                value_type: "string".to_string(),
+            },
+        ]);
+
+        let overrides = BTreeMap::from([(
+            ("TopLevel".to_string(), "Namespace".to_string()),
+            "qa-hotfix".to_string(),
+        )]);
+        let overridden = values.with_overrides(&overrides).unwrap();
+        assert_eq!(overridden.namespace(), "qa-hotfix");
+        assert_eq!(overridden.environment, "qa");
+
+        let unknown = BTreeMap::from([(
+            ("TopLevel".to_string(), "Namespce".to_string()),
+            "qa".to_string(),
+        )]);
+        let err = values.with_overrides(&unknown).unwrap_err();
+        assert!(err.to_string().contains("Did you mean TopLevel.Namespace?"));
+    }
+
+    #[test]
+    fn test_parse_typed_value_mismatch() {
+        let err = parse_typed_value("foo", "bar", "abc", "int").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "value 'abc' is not a valid int for key foo.bar"
+        );
+        assert!(parse_typed_value("DE", "Debug", "yes", "bool").is_err());
+        assert!(parse_typed_value("DE", "Ratio", "half", "float").is_err());
+        assert!(parse_typed_value("DE", "Name", "abc", "strnig").is_err());
+    }
+
+    #[test]
+    fn test_normalize_typed_value() {
+        for (value, expected) in [
+            ("true", "true"),
+            ("false", "false"),
+            ("1", "true"),
+            ("0", "false"),
+            ("yes", "true"),
+            ("no", "false"),
+            ("TRUE", "true"),
+            ("No", "false"),
+            ("", ""),
+        ] {
+            assert_eq!(
+                normalize_typed_value("DE", "Debug", value, "bool").unwrap(),
+                expected,
+                "normalizing {:?}",
+                value
+            );
+        }
+        assert_eq!(
+            normalize_typed_value("DE", "Port", "1", "int").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            normalize_typed_value("DE", "Name", "yes", "string").unwrap(),
+            "yes"
+        );
+    }
+
+    #[test]
+    fn test_normalize_typed_value_rejects() {
+        let err = normalize_typed_value("DE", "Debug", "maybe", "bool").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "value 'maybe' is not a valid bool for key DE.Debug"
+        );
+        assert!(normalize_typed_value("DE", "Debug", "y", "bool").is_err());
+    }
 }
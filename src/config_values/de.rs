@@ -1,11 +1,12 @@
 use crate::config_values::amqp::Amqp;
 use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
 use dialoguer::{theme::ColorfulTheme, Input};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::{Postgres, Transaction};
 use url::Url;
 
-#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DESubscriptions {
     #[serde(skip)]
@@ -114,7 +115,7 @@ impl DESubscriptions {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DECoge {
     #[serde(skip)]
@@ -195,7 +196,7 @@ impl Default for DECoge {
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DETools {
     #[serde(skip)]
@@ -328,7 +329,7 @@ impl DETools {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DEToolsAdmin {
     max_cpu_limit: Option<u32>,
@@ -346,7 +347,7 @@ impl Default for DEToolsAdmin {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct Info {
     #[serde(skip)]
@@ -448,7 +449,7 @@ impl Info {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DE {
     #[serde(skip)]
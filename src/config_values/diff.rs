@@ -0,0 +1,143 @@
+//! # Diff
+//!
+//! Compares the config values of two environments, such as staging and prod
+//! before a promotion. Values are compared as they're stored, before any
+//! `${section.key}` references are resolved, so a difference shows up in the
+//! value that actually differs rather than in everything that refers to it.
+use crate::db::ConfigurationValue;
+use std::collections::BTreeMap;
+
+/// How a config value compares between the two environments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Both environments have the same value.
+    Same(String),
+
+    /// Both environments have the value, but it differs.
+    Changed { left: String, right: String },
+
+    /// Only the left environment has the value.
+    OnlyLeft(String),
+
+    /// Only the right environment has the value.
+    OnlyRight(String),
+}
+
+/// A config value along with how it compares between the environments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub change: Change,
+}
+
+impl Entry {
+    /// Returns whether the value differs between the environments.
+    pub fn is_difference(&self) -> bool {
+        !matches!(self.change, Change::Same(_))
+    }
+}
+
+// A key's value in the left and right environments, if it's set in them.
+type Sides<'a> = (Option<&'a str>, Option<&'a str>);
+
+/// Compares two environments' config values, as returned by
+/// `db::get_config_values`. The entries are sorted by section and key.
+///
+/// # Examples
+/// ```ignore
+///     let entries = diff::diff(&staging, &prod);
+///     let differences = entries.iter().filter(|e| e.is_difference()).count();
+/// ```
+pub fn diff(left: &[ConfigurationValue], right: &[ConfigurationValue]) -> Vec<Entry> {
+    let mut values: BTreeMap<(&str, &str), Sides> = BTreeMap::new();
+    for cfg in left {
+        values
+            .entry((cfg.section.as_str(), cfg.key.as_str()))
+            .or_default()
+            .0 = Some(cfg.value.as_str());
+    }
+    for cfg in right {
+        values
+            .entry((cfg.section.as_str(), cfg.key.as_str()))
+            .or_default()
+            .1 = Some(cfg.value.as_str());
+    }
+
+    values
+        .into_iter()
+        .filter_map(|((section, key), values)| {
+            let change = match values {
+                (Some(l), Some(r)) if l == r => Change::Same(l.to_string()),
+                (Some(l), Some(r)) => Change::Changed {
+                    left: l.to_string(),
+                    right: r.to_string(),
+                },
+                (Some(l), None) => Change::OnlyLeft(l.to_string()),
+                (None, Some(r)) => Change::OnlyRight(r.to_string()),
+                (None, None) => return None,
+            };
+            Some(Entry {
+                section: section.to_string(),
+                key: key.to_string(),
+                change,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cfg(section: &str, key: &str, value: &str) -> ConfigurationValue {
+        ConfigurationValue {
+            id: 0,
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: "string".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let left = vec![
+            cfg("DE", "Subdomain", "de"),
+            cfg("DE", "BaseURI", "https://qa.example.org"),
+            cfg("Agave", "Key", "abc"),
+        ];
+        let right = vec![
+            cfg("DE", "BaseURI", "https://example.org"),
+            cfg("DE", "Subdomain", "de"),
+            cfg("VICE", "Namespace", "vice-apps"),
+        ];
+        let entries = diff(&left, &right);
+        let changes = entries
+            .iter()
+            .map(|e| (e.section.as_str(), e.key.as_str(), e.change.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("Agave", "Key", Change::OnlyLeft("abc".to_string())),
+                (
+                    "DE",
+                    "BaseURI",
+                    Change::Changed {
+                        left: "https://qa.example.org".to_string(),
+                        right: "https://example.org".to_string(),
+                    }
+                ),
+                ("DE", "Subdomain", Change::Same("de".to_string())),
+                (
+                    "VICE",
+                    "Namespace",
+                    Change::OnlyRight("vice-apps".to_string())
+                ),
+            ]
+        );
+        assert_eq!(entries.iter().filter(|e| e.is_difference()).count(), 3);
+        assert!(diff(&left, &left).iter().all(|e| !e.is_difference()));
+    }
+}
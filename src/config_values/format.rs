@@ -0,0 +1,192 @@
+//! # Format
+//!
+//! The file formats that values files (`defaults.yaml`, `deployment.yaml`) can
+//! be written and read in. YAML is the canonical format, but JSON and TOML are
+//! accepted for the sake of tools that prefer them. The format is picked from
+//! the file's extension unless it's given explicitly with `--format`.
+use anyhow::{anyhow, Result};
+use clap::{arg, Arg, ArgMatches};
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The names of the supported formats.
+pub const FORMATS: [&str; 3] = ["yaml", "json", "toml"];
+
+/// The formats that values files can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            _ => Err(anyhow!("unknown values file format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Returns the format matching the file's extension, or `None` if the
+    /// extension isn't one of the supported formats.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     assert_eq!(Format::from_path(Path::new("deployment.toml")), Some(Format::Toml));
+    /// ```
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+    }
+
+    /// Returns the explicitly requested format if there is one, then the
+    /// format matching the file's extension, then YAML for files without an
+    /// extension and for stdout. Reading and writing values files both go
+    /// through this, so they always agree on the format. An extension that
+    /// isn't one of the supported formats is an error instead of being
+    /// treated as YAML.
+    pub fn resolve(explicit: Option<Format>, path: Option<&Path>) -> Result<Self> {
+        if let Some(format) = explicit {
+            return Ok(format);
+        }
+        let Some(path) = path.filter(|p| p.extension().is_some()) else {
+            return Ok(Format::default());
+        };
+        Format::from_path(path).ok_or_else(|| {
+            anyhow!(
+                "unrecognized values file extension for {}: use .yaml, .yml, .json, or .toml",
+                path.display()
+            )
+        })
+    }
+
+    /// Returns the file extension for this format, without the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Yaml => "yaml",
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+
+    /// Serializes the value in this format.
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<String> {
+        Ok(match self {
+            Format::Yaml => serde_yaml::to_string(value)?,
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Toml => toml::to_string_pretty(value)?,
+        })
+    }
+
+    /// Parses a document in this format into a YAML value, which is what the
+    /// schema migrations operate on.
+    pub fn parse(&self, contents: &str) -> Result<serde_yaml::Value> {
+        Ok(match self {
+            Format::Yaml => serde_yaml::from_str(contents)?,
+            Format::Json => serde_json::from_str(contents)?,
+            Format::Toml => toml::from_str(contents)?,
+        })
+    }
+}
+
+/// Returns the `--format` argument for the commands that write values files.
+pub fn format_arg() -> Arg {
+    arg!(--format [FORMAT] "The format to write the values in")
+        .long_help("The format to write the values in. Defaults to the format matching the file's extension (.yaml, .yml, .json, or .toml), then to YAML.")
+        .value_parser(clap::builder::PossibleValuesParser::new(FORMATS))
+}
+
+/// Returns the format selected with the `--format` argument, if any.
+pub fn get_format(matches: &ArgMatches) -> Result<Option<Format>> {
+    matches
+        .get_one::<String>("format")
+        .map(|f| f.parse())
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config_values::config::ConfigValues;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            Format::from_path(Path::new("deployment.yaml")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("deployment.YML")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("deployment.json")),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("deployment.toml")),
+            Some(Format::Toml)
+        );
+        assert_eq!(Format::from_path(Path::new("deployment.txt")), None);
+        assert_eq!(Format::from_path(Path::new("deployment")), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let toml = Path::new("deployment.toml");
+        assert_eq!(Format::resolve(None, Some(toml)).unwrap(), Format::Toml);
+        assert_eq!(
+            Format::resolve(Some(Format::Json), Some(toml)).unwrap(),
+            Format::Json
+        );
+        assert_eq!(
+            Format::resolve(None, Some(Path::new("values"))).unwrap(),
+            Format::Yaml
+        );
+        assert_eq!(Format::resolve(None, None).unwrap(), Format::Yaml);
+
+        let txt = Path::new("deployment.txt");
+        assert!(Format::resolve(None, Some(txt)).is_err());
+        assert_eq!(
+            Format::resolve(Some(Format::Yaml), Some(txt)).unwrap(),
+            Format::Yaml
+        );
+    }
+
+    // Writes the default values out in the format, reads them back in, and
+    // checks that writing them again gives the same document.
+    fn round_trip(format: Format) {
+        let mut cv = ConfigValues::default();
+        cv.environment = "qa".to_string();
+        let written = format.serialize(&cv).unwrap();
+
+        let read = ConfigValues::parse(&written, format).unwrap();
+        assert_eq!(read.environment, "qa");
+        assert_eq!(format.serialize(&read).unwrap(), written);
+    }
+
+    #[test]
+    fn test_round_trip_yaml() {
+        round_trip(Format::Yaml);
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        round_trip(Format::Json);
+    }
+
+    #[test]
+    fn test_round_trip_toml() {
+        round_trip(Format::Toml);
+    }
+}
@@ -0,0 +1,279 @@
+//! # Interpolate
+//!
+//! Config values can refer to other config values with `${section.key}`, so
+//! that values derived from others don't have to be kept in sync by hand:
+//!
+//! ```text
+//! DE.BaseURI = https://${DE.Subdomain}.example.org
+//! ```
+//!
+//! The section is everything before the first '.', so keys that contain dots
+//! themselves, like `${DashboardAggregator.Website.URL}`, work as expected.
+//! References are resolved when values are rendered; the database keeps the
+//! template. Only `${section.key}` naming one of the sections is a reference;
+//! anything else in `${…}`, like a shell's `${HOME}`, is written out as is.
+//! `$${` is written out as a literal `${`.
+use crate::{db::ConfigurationValue, exit};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+// A reference to a config value, as (section, key).
+type Reference = (String, String);
+
+// Splits the name in a `${section.key}` reference into its parts. Returns
+// None if the name doesn't look like one, in which case the text isn't a
+// reference at all.
+fn parse_reference(name: &str) -> Option<Reference> {
+    let (section, key) = name.split_once('.')?;
+    let is_name = |s: &str, extra: &[char]| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || extra.contains(&c))
+    };
+    if !is_name(section, &[]) || !is_name(key, &['.', '-']) {
+        return None;
+    }
+    Some((section.to_string(), key.to_string()))
+}
+
+// Parses the `${section.key}` reference at the start of some text, returning
+// it along with the text after it.
+fn leading_reference(text: &str) -> Option<(Reference, &str)> {
+    let body = text.strip_prefix("${")?;
+    let end = body.find('}')?;
+    Some((parse_reference(&body[..end])?, &body[end + 1..]))
+}
+
+struct Resolver<'a> {
+    raw: HashMap<Reference, &'a str>,
+    resolved: HashMap<Reference, String>,
+
+    // The sections the values are in. `${…}` naming any other section is
+    // left alone.
+    sections: HashSet<&'a str>,
+
+    // The references being resolved, innermost last, for cycle detection.
+    stack: Vec<Reference>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, reference: &Reference) -> Result<String> {
+        if let Some(value) = self.resolved.get(reference) {
+            return Ok(value.clone());
+        }
+
+        if let Some(start) = self.stack.iter().position(|r| r == reference) {
+            let cycle = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(reference))
+                .map(|(section, key)| format!("{}.{}", section, key))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(exit::Error::Usage(format!(
+                "config values refer to each other in a cycle: {}",
+                cycle
+            ))
+            .into());
+        }
+
+        let raw = match self.raw.get(reference) {
+            Some(raw) => *raw,
+            None => {
+                let (section, key) = reference;
+                let referrer = match self.stack.last() {
+                    Some((s, k)) => format!(" in {}.{}", s, k),
+                    None => String::new(),
+                };
+                return Err(exit::Error::Usage(format!(
+                    "unresolved reference to {}.{}{}",
+                    section, key, referrer
+                ))
+                .into());
+            }
+        };
+
+        self.stack.push(reference.clone());
+        let value = self.expand(raw);
+        self.stack.pop();
+        let value = value?;
+
+        self.resolved.insert(reference.clone(), value.clone());
+        Ok(value)
+    }
+
+    // Replaces the references in a value with the values they refer to.
+    // Text in `${…}` that isn't a reference is copied as is.
+    fn expand(&mut self, raw: &str) -> Result<String> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(start) = rest.find('$') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start..];
+
+            if let Some(literal) = after.strip_prefix("$${") {
+                out.push_str("${");
+                rest = literal;
+            } else if let Some((reference, after_reference)) = leading_reference(after)
+                .filter(|((section, _), _)| self.sections.contains(section.as_str()))
+            {
+                out.push_str(&self.resolve(&reference)?);
+                rest = after_reference;
+            } else {
+                out.push('$');
+                rest = &after[1..];
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Returns the `(section, key)` of each value a raw value refers to, in the
+/// order they appear. Text in `${…}` that isn't shaped like a reference is
+/// skipped.
+///
+/// # Examples
+/// ```ignore
+///     let refs = interpolate::references("postgres://de:${DEDB.Password}@db");
+///     assert_eq!(refs, vec![("DEDB".to_string(), "Password".to_string())]);
+/// ```
+pub fn references(raw: &str) -> Vec<(String, String)> {
+    let mut refs = Vec::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find('$') {
+        let after = &rest[start..];
+        if let Some(literal) = after.strip_prefix("$${") {
+            rest = literal;
+        } else if let Some((reference, after_reference)) = leading_reference(after) {
+            refs.push(reference);
+            rest = after_reference;
+        } else {
+            rest = &after[1..];
+        }
+    }
+    refs
+}
+
+/// Resolves the `${section.key}` references in the values, looking them up
+/// among the values themselves. Returns an error naming the reference if one
+/// can't be resolved or if references form a cycle.
+///
+/// # Examples
+/// ```ignore
+///     let cfgs = interpolate::interpolate(cfgs)?;
+/// ```
+pub fn interpolate(cfgs: Vec<ConfigurationValue>) -> Result<Vec<ConfigurationValue>> {
+    let mut resolved_values = HashMap::new();
+    {
+        let mut resolver = Resolver {
+            raw: cfgs
+                .iter()
+                .map(|c| ((c.section.clone(), c.key.clone()), c.value.as_str()))
+                .collect(),
+            resolved: HashMap::new(),
+            sections: cfgs.iter().map(|c| c.section.as_str()).collect(),
+            stack: Vec::new(),
+        };
+        for cfg in cfgs.iter().filter(|c| c.value.contains('$')) {
+            let reference = (cfg.section.clone(), cfg.key.clone());
+            let value = resolver.resolve(&reference)?;
+            resolved_values.insert(reference, value);
+        }
+    }
+
+    Ok(cfgs
+        .into_iter()
+        .map(|mut cfg| {
+            if let Some(value) = resolved_values.remove(&(cfg.section.clone(), cfg.key.clone())) {
+                cfg.value = value;
+            }
+            cfg
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cfg(section: &str, key: &str, value: &str) -> ConfigurationValue {
+        ConfigurationValue {
+            id: 0,
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: "string".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let cfgs = interpolate(vec![
+            cfg(
+                "DE",
+                "BaseURI",
+                "https://${DE.Subdomain}.${TopLevel.Domain}",
+            ),
+            cfg("DE", "Subdomain", "de"),
+            cfg("TopLevel", "Domain", "example.org"),
+            cfg(
+                "DashboardAggregator",
+                "Website.URL",
+                "${DE.BaseURI}/dashboard",
+            ),
+            cfg("DE", "Price", "$5 or $${literal}"),
+        ])
+        .unwrap();
+        assert_eq!(cfgs[0].value, "https://de.example.org");
+        assert_eq!(cfgs[3].value, "https://de.example.org/dashboard");
+        assert_eq!(cfgs[4].value, "$5 or ${literal}");
+    }
+
+    #[test]
+    fn test_references() {
+        assert_eq!(
+            references("https://${DE.Subdomain}.${TopLevel.Domain}/$${DE.Literal}"),
+            vec![
+                ("DE".to_string(), "Subdomain".to_string()),
+                ("TopLevel".to_string(), "Domain".to_string()),
+            ]
+        );
+        assert!(references("$5 and ${unterminated").is_empty());
+    }
+
+    #[test]
+    fn test_unresolved_reference() {
+        let err = interpolate(vec![cfg("DE", "BaseURI", "https://${DE.Missing}")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unresolved reference to DE.Missing in DE.BaseURI"
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let err = interpolate(vec![
+            cfg("A", "One", "${A.Two}"),
+            cfg("A", "Two", "${A.Three}"),
+            cfg("A", "Three", "${A.One}"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "config values refer to each other in a cycle: A.One -> A.Two -> A.Three -> A.One"
+        );
+    }
+
+    #[test]
+    fn test_not_a_reference() {
+        let cfgs = interpolate(vec![
+            cfg("A", "One", "${HOME}/bin"),
+            cfg("A", "Two", "${A.Two"),
+            cfg("A", "Three", "`${user.name}` and ${ A.One }"),
+        ])
+        .unwrap();
+        assert_eq!(cfgs[0].value, "${HOME}/bin");
+        assert_eq!(cfgs[1].value, "${A.Two");
+        assert_eq!(cfgs[2].value, "`${user.name}` and ${ A.One }");
+    }
+}
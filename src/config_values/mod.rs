@@ -1,23 +1,30 @@
 pub mod agave;
 pub mod amqp;
+pub mod answers;
 pub mod base_urls;
 pub mod cas;
 pub mod config;
 pub mod dashboard_aggregator;
 pub mod db;
 pub mod de;
+pub mod diff;
 pub mod docker;
 pub mod elasticsearch;
 pub mod email;
+pub mod format;
 pub mod grouper;
 pub mod icat;
 pub mod infosquito;
 pub mod intercom;
+pub mod interpolate;
 pub mod irods;
 pub mod jvmopts;
 pub mod keycloak;
 pub mod misc;
 pub mod qa;
+pub mod schema;
+pub mod secrets;
+pub mod value_type;
 pub mod vice;
 
 // These are features that are truly optional. In other words, they do not need
@@ -0,0 +1,214 @@
+//! # Schema
+//!
+//! Versioning for the on-disk format of the values files (`defaults.yaml`,
+//! `deployment.yaml`) written out by `render_values` and friends. Every file
+//! written by this version of the tool carries a top-level `SchemaVersion`
+//! field. Files are migrated forward to the current version, one version at a
+//! time, before they're deserialized into a `ConfigValues`.
+//!
+//! It also generates a JSON Schema for the current format from the
+//! `ConfigValues` types, so that editors can validate values files as they're
+//! written and the schema can't drift from the types.
+use crate::config_values::config::ConfigValues;
+use anyhow::{anyhow, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashSet;
+
+/// The version of the values file format written by this version of the tool.
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// The name of the top-level field containing the schema version.
+pub const SCHEMA_VERSION_FIELD: &str = "SchemaVersion";
+
+// Files written before the schema version was introduced don't contain the
+// field, so they're treated as version 1.
+const UNVERSIONED_SCHEMA_VERSION: u32 = 1;
+
+/// Returns the schema version recorded in a parsed values file.
+pub fn schema_version(doc: &Value) -> Result<u32> {
+    match doc.get(SCHEMA_VERSION_FIELD) {
+        None => Ok(UNVERSIONED_SCHEMA_VERSION),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("{} must be a positive integer", SCHEMA_VERSION_FIELD)),
+    }
+}
+
+// Version 1 files have no SchemaVersion field. Otherwise the layout is
+// identical, so all that's needed is to record the version.
+fn migrate_v1_to_v2(mut doc: Mapping) -> Result<Mapping> {
+    doc.insert(Value::from(SCHEMA_VERSION_FIELD), Value::from(2));
+    Ok(doc)
+}
+
+/// Migrates a parsed values file to the current schema version. Returns an
+/// error if the file was written by a newer version of the tool or isn't a
+/// YAML mapping.
+///
+/// # Examples
+/// ```ignore
+///     let doc: serde_yaml::Value = serde_yaml::from_str(&contents)?;
+///     let doc = schema::migrate(doc)?;
+///     let cv: ConfigValues = serde_yaml::from_value(doc)?;
+/// ```
+pub fn migrate(doc: Value) -> Result<Value> {
+    let mut version = schema_version(&doc)?;
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "values file has schema version {}, but this version of mgmt only supports up to version {}. Upgrade mgmt to read it.",
+            version,
+            CURRENT_SCHEMA_VERSION
+        ));
+    }
+
+    let mut mapping = match doc {
+        Value::Mapping(m) => m,
+        _ => return Err(anyhow!("values file must contain a YAML mapping")),
+    };
+
+    while version < CURRENT_SCHEMA_VERSION {
+        mapping = match version {
+            1 => migrate_v1_to_v2(mapping)?,
+            v => return Err(anyhow!("no migration available from schema version {}", v)),
+        };
+        version += 1;
+    }
+
+    Ok(Value::Mapping(mapping))
+}
+
+/// Returns the JSON Schema for the current values file format, generated from
+/// the `ConfigValues` types.
+///
+/// Handler for the `mgmt site schema` command.
+///
+/// # Examples
+/// ```ignore
+///     let schema = schema::json_schema()?;
+///     println!("{}", serde_json::to_string_pretty(&schema)?);
+/// ```
+pub fn json_schema() -> Result<serde_json::Value> {
+    let mut schema = schemars::schema_for!(ConfigValues);
+    schema.schema.metadata().title = Some("mgmt values file".to_string());
+    schema.schema.metadata().description = Some(format!(
+        "A defaults.yaml or deployment.yaml file written by mgmt, schema version {}.",
+        CURRENT_SCHEMA_VERSION
+    ));
+    Ok(serde_json::to_value(schema)?)
+}
+
+/// Returns the config values that must be set, as (section, key): the ones
+/// the JSON Schema requires all the way down from the top of the file. The
+/// top-level settings are in the TopLevel section, and keys in nested
+/// mappings are joined with dots, like Website.URL.
+///
+/// # Examples
+/// ```ignore
+///     let required = schema::required_keys()?;
+///     assert!(required.contains(&("TopLevel".to_string(), "Environment".to_string())));
+/// ```
+pub fn required_keys() -> Result<HashSet<(String, String)>> {
+    let schema = json_schema()?;
+    let mut keys = HashSet::new();
+    for (name, property) in required_properties(&schema, &schema) {
+        if property.get("properties").is_some() {
+            collect_required(&schema, property, &name, "", &mut keys);
+        } else {
+            keys.insert(("TopLevel".to_string(), name));
+        }
+    }
+    Ok(keys)
+}
+
+// Adds the required leaf properties under a section's object schema to keys,
+// with their names prefixed by the ones of the objects they're nested in.
+fn collect_required(
+    root: &serde_json::Value,
+    object: &serde_json::Value,
+    section: &str,
+    prefix: &str,
+    keys: &mut HashSet<(String, String)>,
+) {
+    for (name, property) in required_properties(root, object) {
+        let key = format!("{}{}", prefix, name);
+        if property.get("properties").is_some() {
+            collect_required(root, property, section, &format!("{}.", key), keys);
+        } else {
+            keys.insert((section.to_string(), key));
+        }
+    }
+}
+
+// Returns the required properties of an object schema, with their $refs
+// resolved against the root schema's definitions.
+fn required_properties<'a>(
+    root: &'a serde_json::Value,
+    object: &'a serde_json::Value,
+) -> Vec<(String, &'a serde_json::Value)> {
+    let object = resolve(root, object);
+    object["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str())
+        .filter(|name| *name != SCHEMA_VERSION_FIELD)
+        .filter_map(|name| {
+            let property = object["properties"].get(name)?;
+            Some((name.to_string(), resolve(root, property)))
+        })
+        .collect()
+}
+
+fn resolve<'a>(
+    root: &'a serde_json::Value,
+    schema: &'a serde_json::Value,
+) -> &'a serde_json::Value {
+    match schema["$ref"]
+        .as_str()
+        .and_then(|r| r.strip_prefix("#/definitions/"))
+    {
+        Some(name) => resolve(root, &root["definitions"][name]),
+        None => schema,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let v1: Value = serde_yaml::from_str("Environment: qa\n").unwrap();
+        assert_eq!(schema_version(&v1).unwrap(), 1);
+
+        let migrated = migrate(v1).unwrap();
+        assert_eq!(schema_version(&migrated).unwrap(), CURRENT_SCHEMA_VERSION);
+        assert_eq!(migrated.get("Environment"), Some(&Value::from("qa")));
+
+        let future: Value = serde_yaml::from_str("SchemaVersion: 99\n").unwrap();
+        assert!(migrate(future).is_err());
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = json_schema().unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        assert!(properties.contains_key("Environment"));
+        assert!(properties.contains_key("DE"));
+        assert!(properties.contains_key(SCHEMA_VERSION_FIELD));
+        assert!(!properties.contains_key("Section"));
+    }
+
+    #[test]
+    fn test_required_keys() {
+        let required = required_keys().unwrap();
+        let key = |section: &str, key: &str| (section.to_string(), key.to_string());
+        assert!(required.contains(&key("TopLevel", "Environment")));
+        assert!(required.contains(&key("TopLevel", "Namespace")));
+        assert!(required.iter().any(|(section, _)| section == "DE"));
+        assert!(!required.contains(&key("DE", "BaseURI")));
+        assert!(!required.iter().any(|(section, _)| section == "Admin"));
+    }
+}
@@ -0,0 +1,178 @@
+//! # Secrets
+//!
+//! Some config values, like passwords and API keys, are secrets that
+//! shouldn't end up in a values file that gets committed to a site repo.
+//! Which values are secrets is recorded on their defaults in the database, so
+//! that it's managed in one place for every environment. A rendered values
+//! file can have the secrets replaced with `PLACEHOLDER`; the full-fidelity
+//! render that's used for deploying is still the default.
+//!
+//! A value that refers to a secret with `${section.key}`, like a connection
+//! URL that includes a password, is treated as a secret too, since the secret
+//! ends up in it once it's interpolated.
+use crate::{config_values::interpolate, db::ConfigurationValue};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// What a redacted secret is replaced with.
+pub const PLACEHOLDER: &str = "<redacted>";
+
+// A config value, as (section, key).
+type Key = (String, String);
+
+/// The config values to redact from a render.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Redactions {
+    keys: BTreeSet<Key>,
+}
+
+impl Redactions {
+    /// Returns the values to redact from `cfgs`, which must be the raw values
+    /// from before they're interpolated: the ones in `secrets` and the ones
+    /// that refer to them, directly or through other values.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let secrets = db::list_secret_keys(&mut tx).await?;
+    ///     let redactions = Redactions::new(&cfgs, &secrets);
+    /// ```
+    pub fn new(cfgs: &[ConfigurationValue], secrets: &HashSet<Key>) -> Self {
+        let refs: HashMap<Key, Vec<Key>> = cfgs
+            .iter()
+            .map(|c| {
+                (
+                    (c.section.clone(), c.key.clone()),
+                    interpolate::references(&c.value),
+                )
+            })
+            .collect();
+
+        let mut keys: BTreeSet<Key> = refs
+            .keys()
+            .filter(|k| secrets.contains(*k))
+            .cloned()
+            .collect();
+
+        // Keep adding the values that refer to a redacted one until there
+        // aren't any more.
+        loop {
+            let tainted = refs
+                .iter()
+                .filter(|(k, r)| !keys.contains(*k) && r.iter().any(|r| keys.contains(r)))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>();
+            if tainted.is_empty() {
+                break;
+            }
+            keys.extend(tainted);
+        }
+
+        Redactions { keys }
+    }
+
+    /// Returns whether there's nothing to redact.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns whether the value for the section and key is redacted.
+    pub fn contains(&self, section: &str, key: &str) -> bool {
+        self.keys.contains(&(section.to_string(), key.to_string()))
+    }
+
+    /// Replaces the redacted values in serialized config values with
+    /// `PLACEHOLDER`. Values that aren't set are left alone.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let mut value = serde_yaml::to_value(&cv)?;
+    ///     redactions.apply(&mut value);
+    /// ```
+    pub fn apply(&self, value: &mut serde_yaml::Value) {
+        for (section, key) in &self.keys {
+            if let Some(node) = lookup(value, section, key) {
+                if !node.is_null() {
+                    *node = PLACEHOLDER.into();
+                }
+            }
+        }
+    }
+}
+
+// Finds the node a config value is serialized to. The top-level settings are
+// at the root, each section is a mapping under its name, and a key with dots
+// in it, like Website.URL, is nested a level for each one.
+fn lookup<'a>(
+    value: &'a mut serde_yaml::Value,
+    section: &str,
+    key: &str,
+) -> Option<&'a mut serde_yaml::Value> {
+    let mut node = if section == "TopLevel" {
+        value
+    } else {
+        value.get_mut(section)?
+    };
+    for part in key.split('.') {
+        node = node.get_mut(part)?;
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cfg(section: &str, key: &str, value: &str) -> ConfigurationValue {
+        ConfigurationValue {
+            id: 0,
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: "string".to_string(),
+        }
+    }
+
+    fn key(section: &str, key: &str) -> Key {
+        (section.to_string(), key.to_string())
+    }
+
+    #[test]
+    fn test_redactions() {
+        let cfgs = vec![
+            cfg("DEDB", "Password", "hunter2"),
+            cfg("DEDB", "URI", "postgres://de:${DEDB.Password}@db"),
+            cfg("DE", "DBURI", "${DEDB.URI}/de"),
+            cfg("DE", "BaseURI", "https://de.example.org"),
+        ];
+        let secrets = HashSet::from([key("DEDB", "Password")]);
+        let redactions = Redactions::new(&cfgs, &secrets);
+        assert!(redactions.contains("DEDB", "Password"));
+        assert!(redactions.contains("DEDB", "URI"));
+        assert!(redactions.contains("DE", "DBURI"));
+        assert!(!redactions.contains("DE", "BaseURI"));
+        assert!(Redactions::new(&cfgs, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(
+            "Environment: qa\n\
+             Agave:\n  Key: abc\n  Secret: shh\n\
+             DashboardAggregator:\n  Website:\n    URL: https://example.org\n\
+             VICE: null\n",
+        )
+        .unwrap();
+        let redactions = Redactions {
+            keys: BTreeSet::from([
+                key("Agave", "Secret"),
+                key("DashboardAggregator", "Website.URL"),
+                key("VICE", "ClientSecret"),
+            ]),
+        };
+        redactions.apply(&mut value);
+        assert_eq!(value["Agave"]["Key"], "abc");
+        assert_eq!(value["Agave"]["Secret"], PLACEHOLDER);
+        assert_eq!(value["DashboardAggregator"]["Website"]["URL"], PLACEHOLDER);
+        assert!(value["VICE"].is_null());
+        assert_eq!(value["Environment"], "qa");
+    }
+}
@@ -0,0 +1,115 @@
+//! # Value Types
+//!
+//! The types that a configuration value can have. These mirror the rows in
+//! the `config_value_types` table.
+use anyhow::{anyhow, Result};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Int,
+    BigInt,
+    Bool,
+    Float,
+    Json,
+    Yaml,
+    Xml,
+    Csv,
+    Tsv,
+}
+
+impl ValueType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Int => "int",
+            ValueType::BigInt => "bigint",
+            ValueType::Bool => "bool",
+            ValueType::Float => "float",
+            ValueType::Json => "json",
+            ValueType::Yaml => "yaml",
+            ValueType::Xml => "xml",
+            ValueType::Csv => "csv",
+            ValueType::Tsv => "tsv",
+        }
+    }
+
+    /// Checks that a value can be parsed as this type. Empty values are
+    /// accepted for every type, since they signal that a value hasn't been
+    /// set yet rather than that it's malformed.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     ValueType::Int.validate("5432")?;
+    ///     assert!(ValueType::Bool.validate("yes").is_err());
+    /// ```
+    pub fn validate(&self, value: &str) -> Result<()> {
+        if value.is_empty() {
+            return Ok(());
+        }
+
+        let ok = match self {
+            ValueType::Int => value.parse::<i32>().is_ok(),
+            ValueType::BigInt => value.parse::<i64>().is_ok(),
+            ValueType::Bool => value.parse::<bool>().is_ok(),
+            ValueType::Float => value.parse::<f64>().is_ok(),
+            ValueType::Json => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+            ValueType::Yaml => serde_yaml::from_str::<serde_yaml::Value>(value).is_ok(),
+            ValueType::String | ValueType::Xml | ValueType::Csv | ValueType::Tsv => true,
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(anyhow!("'{}' is not a valid {} value", value, self))
+        }
+    }
+
+    /// Returns the canonical spelling of a value of this type. Booleans
+    /// written as true/false, 1/0, or yes/no in any case become "true" or
+    /// "false"; every other value is returned as it is, including ones that
+    /// aren't valid, so that `validate` can report them.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     assert_eq!(ValueType::Bool.normalize("Yes"), "true");
+    ///     assert_eq!(ValueType::Int.normalize("5432"), "5432");
+    /// ```
+    pub fn normalize(&self, value: &str) -> String {
+        if *self == ValueType::Bool {
+            match value.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" => return "true".to_string(),
+                "false" | "0" | "no" => return "false".to_string(),
+                _ => {}
+            }
+        }
+        value.to_string()
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "string" => Ok(ValueType::String),
+            "int" => Ok(ValueType::Int),
+            "bigint" => Ok(ValueType::BigInt),
+            "bool" => Ok(ValueType::Bool),
+            "float" => Ok(ValueType::Float),
+            "json" => Ok(ValueType::Json),
+            "yaml" => Ok(ValueType::Yaml),
+            "xml" => Ok(ValueType::Xml),
+            "csv" => Ok(ValueType::Csv),
+            "tsv" => Ok(ValueType::Tsv),
+            _ => Err(anyhow!("unknown value type: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -2,6 +2,9 @@
 //!
 //! Contains the functions needed for loading configs and secrets in mgmt.
 
+#[cfg(feature = "kubernetes")]
+use crate::kubectl;
+use crate::{config_values::config::ConfigValues, handlers::templates};
 use anyhow::{Context, Result};
 use duct::cmd;
 use std::fs;
@@ -172,9 +175,47 @@ pub fn generate_all(defaults_path: &str) -> Result<bool> {
     Ok(success)
 }
 
+/// Renders each of the templates with the config values, writing the ones
+/// under a `secrets` directory to `secrets_dir` and the rest to `cfg_dir`.
+/// Each template is paired with the name of the file it's rendered to.
+///
+/// # Examples
+/// ```ignore
+/// let templates = vec![(PathBuf::from("repos/de-releases/templates/configs/jobservices.yml"), "jobservices.yml".to_string())];
+/// mgmt::configs::render_values(&values, &templates, &cfg_dir, &secrets_dir)?;
+/// ```
+pub fn render_values(
+    values: &ConfigValues,
+    templates: &[(PathBuf, String)],
+    cfg_dir: &Path,
+    secrets_dir: &Path,
+) -> Result<()> {
+    let context = tera::Context::from_serialize(values)?;
+    fs::create_dir_all(cfg_dir)?;
+    fs::create_dir_all(secrets_dir)?;
+
+    let mut tera = templates::new_tera();
+    for (template_path, render_path) in templates {
+        let is_secret = template_path
+            .components()
+            .any(|c| c.as_os_str() == "secrets");
+        let out_path = if is_secret { secrets_dir } else { cfg_dir }.join(render_path);
+
+        let contents = fs::read_to_string(template_path)
+            .with_context(|| format!("failed to read the template {}", template_path.display()))?;
+        tera.add_raw_template(render_path, &contents)?;
+        let out_file = fs::File::create(&out_path)?;
+        tera.render_to(render_path, &context, out_file)
+            .with_context(|| format!("failed to render {}", template_path.display()))?;
+    }
+
+    Ok(())
+}
+
 /// Load the configuration values at a given path for a provided namespace
 /// and environment.
-pub fn load_configs(ns: &str, configmap_name: &str, cfg_dir: &PathBuf) -> Result<bool> {
+#[cfg(feature = "kubernetes")]
+pub fn load_configs(ns: &str, configmap_name: &str, cfg_dir: &Path) -> Result<bool> {
     Ok(cmd!(
         "kubectl",
         "-n",
@@ -199,22 +240,13 @@ pub fn load_configs(ns: &str, configmap_name: &str, cfg_dir: &PathBuf) -> Result
     .success())
 }
 
-pub fn load_secrets(ns: &str, secrets_dir: &PathBuf) -> Result<bool> {
+#[cfg(feature = "kubernetes")]
+pub fn load_secrets(ns: &str, secrets_dir: &Path) -> Result<bool> {
     let contents = fs::read_dir(secrets_dir)?;
     for entry in contents.into_iter() {
         let entry = entry?;
         if entry.metadata()?.is_file() {
-            let path = entry.path();
-            cmd!(
-                "kubectl",
-                "-n",
-                ns,
-                "apply",
-                "-f",
-                path.to_str()
-                    .context("failed to get the absolute path to the secret file")?
-            )
-            .run()?;
+            kubectl::apply(&entry.path(), ns)?;
         }
     }
 
@@ -231,4 +263,61 @@ mod test {
         let result = values_path(env).unwrap();
         assert_eq!(result, "config_values/prod.yaml");
     }
+
+    #[test]
+    fn test_render_values_with_overrides() {
+        let dir = std::env::temp_dir().join(format!("mgmt-configs-test-{}", std::process::id()));
+        let templates_dir = dir.join("templates");
+        fs::create_dir_all(templates_dir.join("secrets")).unwrap();
+        fs::write(templates_dir.join("ns.txt"), "{{ Namespace }}").unwrap();
+        fs::write(
+            templates_dir.join("secrets").join("env.yaml"),
+            "env: {{ Environment }}",
+        )
+        .unwrap();
+        let templates = vec![
+            (templates_dir.join("ns.txt"), "ns.txt".to_string()),
+            (
+                templates_dir.join("secrets").join("env.yaml"),
+                "env.yaml".to_string(),
+            ),
+        ];
+        let (cfg_dir, secrets_dir) = (dir.join("configs"), dir.join("configs").join("secrets"));
+
+        let values = ConfigValues::from(vec![
+            crate::db::ConfigurationValue {
+                id: 0,
+                section: "TopLevel".to_string(),
+                key: "Environment".to_string(),
+                value: "qa".to_string(),
+                value_type: "string".to_string(),
+            },
+            crate::db::ConfigurationValue {
+                id: 0,
+                section: "TopLevel".to_string(),
+                key: "Namespace".to_string(),
+                value: "qa".to_string(),
+                value_type: "string".to_string(),
+            },
+        ]);
+        render_values(&values, &templates, &cfg_dir, &secrets_dir).unwrap();
+        assert_eq!(fs::read_to_string(cfg_dir.join("ns.txt")).unwrap(), "qa");
+        assert_eq!(
+            fs::read_to_string(secrets_dir.join("env.yaml")).unwrap(),
+            "env: qa"
+        );
+
+        let overrides = std::collections::BTreeMap::from([(
+            ("TopLevel".to_string(), "Namespace".to_string()),
+            "qa-hotfix".to_string(),
+        )]);
+        let overridden = values.with_overrides(&overrides).unwrap();
+        render_values(&overridden, &templates, &cfg_dir, &secrets_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(cfg_dir.join("ns.txt")).unwrap(),
+            "qa-hotfix"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -2,13 +2,17 @@
 //!
 //! This module contains the functions that can be reused across the mgmt
 //! commands to deploy the Discovery Environment.
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use sqlx::{Pool, Postgres, Transaction};
-use std::path::PathBuf;
-use std::process::Command;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
-use crate::handlers::templates;
-use crate::{configs, db, ops};
+use crate::handlers::{releases, templates};
+use crate::{cmd::Cmd, configs, db, exit, kubectl, manifests, ops, versions, warnings::Warnings};
 
 #[derive(Debug, Clone)]
 pub struct DeploymentOptions {
@@ -30,6 +34,9 @@ pub struct DeploymentOptions {
     // The name of the services to NOT deploy.
     pub skips: Vec<String>,
 
+    // Whether to deploy the services that are disabled in the environment.
+    pub include_disabled: bool,
+
     // The base directory for the configurations
     pub configdir: PathBuf,
 
@@ -47,40 +54,238 @@ pub struct DeploymentOptions {
 
     // List of services to deploy before the rest.
     pub pre_deploy: Vec<String>,
+
+    // The file pinning the versions of the services, if there is one.
+    pub versions: Option<PathBuf>,
+
+    // The maximum number of services to deploy at once.
+    pub concurrency: usize,
+
+    // Whether to keep going and exit successfully when services fail to
+    // deploy. The failures are still reported.
+    pub continue_on_error: bool,
+
+    // Whether to wait for the services' rollouts to finish after deploying
+    // them, and how long to wait in total.
+    pub wait: bool,
+    pub wait_timeout: Duration,
+
+    // The directory to write the manifests to before they're applied, if
+    // there is one.
+    pub manifest_out: Option<PathBuf>,
+
+    // Whether to stop after rendering, without applying anything to the
+    // cluster.
+    pub dry_run: bool,
 }
 
 async fn get_services(
     tx: &mut Transaction<'_, Postgres>,
     env: &str,
     skips: &[String],
+    include_disabled: bool,
 ) -> Result<Vec<db::Service>> {
-    let mut services = db::get_services(tx, &env).await?;
-    services.retain(|service| !skips.contains(&service.name));
-    Ok(services)
+    let services = db::get_services_for_env(tx, &env).await?;
+    Ok(ops::active_services(services, env, skips, include_disabled))
 }
 
-async fn get_namespace(tx: &mut Transaction<'_, Postgres>, env: &str) -> Result<String> {
-    Ok(db::get_namespace(tx, &env).await?)
+/// Returns the path to the build file for a service. Releases covering more
+/// than one environment keep each environment's build files in their own
+/// subdirectory, which takes precedence over the shared builds directory.
+pub fn build_file_path(releases_dir: &Path, env: &str, svc_name: &str) -> PathBuf {
+    let builds_dir = releases_dir.join("builds");
+    let env_svc_json = builds_dir.join(env).join(format!("{}.json", svc_name));
+    if env_svc_json.exists() {
+        env_svc_json
+    } else {
+        builds_dir.join(format!("{}.json", svc_name))
+    }
 }
 
-pub fn deploy_service(releases_dir: &PathBuf, ns: &str, svc: &db::Service) -> Result<bool> {
-    let svc_json = releases_dir
-        .join("builds")
-        .join(format!("{}.json", svc.name));
+/// Deploys a service with skaffold, returning what skaffold printed. The
+/// error includes skaffold's stderr if the deployment fails.
+pub fn deploy_service(releases_dir: &Path, env: &str, ns: &str, svc_name: &str) -> Result<String> {
+    let svc_json = build_file_path(releases_dir, env, svc_name);
+    skaffold_deploy_cmd(&svc_json, ns)?.output()
+}
+
+/// Deploys a service with skaffold from the directory its skaffold config is
+/// in, such as the service's repo in a site directory.
+///
+/// # Examples
+/// ```ignore
+///     let out = deploy::deploy_service_from(Path::new("repos/apps"), &svc_json, "prod")?;
+/// ```
+pub fn deploy_service_from(dir: &Path, svc_json: &Path, ns: &str) -> Result<String> {
+    skaffold_deploy_cmd(svc_json, ns)?.dir(dir).output()
+}
 
-    Ok(Command::new("skaffold")
+fn skaffold_deploy_cmd(svc_json: &Path, ns: &str) -> Result<Cmd> {
+    Ok(Cmd::new("skaffold").args([
+        "deploy",
+        "--namespace",
+        ns,
+        "--build-artifacts",
+        svc_json
+            .to_str()
+            .context("couldn't get service json path")?,
+        "--force",
+    ]))
+}
+
+/// Renders the manifests skaffold would deploy for a service, without
+/// deploying them.
+pub fn render_service(releases_dir: &Path, env: &str, ns: &str, svc_name: &str) -> Result<String> {
+    let svc_json = build_file_path(releases_dir, env, svc_name);
+
+    Cmd::new("skaffold")
         .args([
-            "deploy",
+            "render",
             "--namespace",
             ns,
             "--build-artifacts",
             svc_json
                 .to_str()
                 .context("couldn't get service json path")?,
-            "--force",
         ])
-        .status()?
-        .success())
+        .output()
+}
+
+/// Writes the manifests a deployment applies to `out_dir`: the secret holding
+/// the rendered configs, each of the rendered secrets, and the manifests for
+/// each service, named after the service.
+fn write_manifests(
+    out_dir: &Path,
+    releases_dir: &Path,
+    env: &str,
+    ns: &str,
+    env_configdir: &Path,
+    secrets_dir: &Path,
+    services: &[&db::Service],
+) -> Result<()> {
+    manifests::create_dir(out_dir)?;
+
+    if env_configdir.exists() {
+        let configs = kubectl::secret_manifest("service-configs", env_configdir, ns)?;
+        manifests::write(out_dir, "service-configs", &configs, true)?;
+    }
+
+    if secrets_dir.exists() {
+        for entry in std::fs::read_dir(secrets_dir)? {
+            let entry = entry?;
+            if entry.metadata()?.is_file() {
+                let contents = std::fs::read_to_string(entry.path())?;
+                let name = entry.file_name();
+                let name = name.to_str().context("couldn't get the secret file name")?;
+                manifests::write(out_dir, name, &contents, true)?;
+            }
+        }
+    }
+
+    for svc in services {
+        let rendered = render_service(releases_dir, env, ns, &svc.name)
+            .with_context(|| format!("failed to render the manifests for {}", svc.name))?;
+        manifests::write(out_dir, &svc.name, &rendered, false)?;
+    }
+
+    println!("Wrote the manifests to {}", out_dir.display());
+    Ok(())
+}
+
+/// Deploys the services, running at most `concurrency` deployments at once.
+/// Each service's skaffold output is printed when its deployment finishes so
+/// that the output of different services doesn't get interleaved. Returns the
+/// result for each service, sorted by service name.
+///
+/// # Examples
+/// ```ignore
+///     let results = deploy::deploy_services(&releases_dir, "qa", "qa", &services, 3).await?;
+/// ```
+pub async fn deploy_services(
+    releases_dir: &Path,
+    env: &str,
+    ns: &str,
+    services: &[&db::Service],
+    concurrency: usize,
+) -> Result<Vec<(String, Result<()>)>> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for svc in services {
+        let semaphore = Arc::clone(&semaphore);
+        let (releases_dir, env, ns, name) = (
+            releases_dir.to_path_buf(),
+            env.to_string(),
+            ns.to_string(),
+            svc.name.clone(),
+        );
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            println!("Deploying {}...", name);
+
+            let svc_name = name.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                deploy_service(&releases_dir, &env, &ns, &svc_name)
+            })
+            .await?;
+
+            match &result {
+                Ok(output) => {
+                    println!("==> {}", name);
+                    print!("{}", output);
+                    println!("Deployed {}", name);
+                }
+                Err(e) => println!("Failed to deploy {}: {:#}", name, e),
+            }
+
+            anyhow::Ok((name, result.map(|_| ())))
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined??);
+    }
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(results)
+}
+
+// Prints whether each service was deployed, in the order of the results.
+fn print_deploy_report(results: &[(String, Result<()>)]) {
+    println!("\nDeployment results:");
+    for (name, result) in results {
+        match result {
+            Ok(_) => println!("\t{}: deployed", name),
+            Err(e) => println!("\t{}: failed: {:#}", name, e),
+        }
+    }
+}
+
+/// Waits for the rollout of each service's deployment to finish, sharing the
+/// timeout between them. Returns the names of the services whose rollouts
+/// didn't finish in time or failed.
+pub fn wait_for_rollouts(ns: &str, services: &[&db::Service], timeout: Duration) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let mut not_ready = Vec::new();
+
+    for svc in services {
+        // Once the deadline has passed, each of the remaining services still
+        // gets checked with the shortest timeout kubectl allows.
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        println!("Waiting for {} to roll out...", svc.name);
+        match kubectl::rollout_status(&svc.name, ns, remaining) {
+            Ok(_) => println!("{} is ready", svc.name),
+            Err(e) => {
+                println!("{} is not ready: {:#}", svc.name, e);
+                not_ready.push(svc.name.clone());
+            }
+        }
+    }
+
+    not_ready
 }
 
 pub async fn deploy(
@@ -98,26 +303,69 @@ pub async fn deploy(
 
     let mut tx = pool.begin().await?;
 
+    // The environment has to exist before anything is cloned or deployed.
+    let environment = ops::require_environment(&mut tx, env).await?;
+
+    // Catch typos in the service names before anything is cloned or deployed.
+    let known = db::get_services(&mut tx, env)
+        .await?
+        .into_iter()
+        .map(|svc| svc.name)
+        .collect::<Vec<_>>();
+    ops::validate_skips(
+        &opts.skips,
+        &BTreeMap::from([(env.to_string(), known.clone())]),
+    )?;
+    ops::validate_service_names("--pre-deploy", &opts.pre_deploy, &known)?;
+    let pins = versions::load(opts.versions.as_deref(), &known)?;
+
     let ro = ops::ReleaseOpts {
-        env: env.to_string(),
+        envs: vec![env.to_string()],
         repo_name,
         repo_url: release_repo_url.to_string(),
         repo_branch: release_repo_branch.to_string(),
         no_tag: true,
         increment_field: "patch".to_string(),
+        force: false,
         no_clone: false,
         no_push: true,
         no_commit: true,
         no_fail: true,
         skips: opts.skips.clone(),
+        include_disabled: opts.include_disabled,
+        versions: opts.versions.clone(),
+        repos_dir: PathBuf::from("repos"),
+        author: None,
+        message: String::new(),
     };
-    ops::setup_release_dir(&ro)?;
+    let (_, builds_dir, services_dir) = ops::setup_release_dir(&ro)?;
 
-    let namespace = get_namespace(&mut tx, &env).await?;
+    let namespace = environment.namespace;
     println!("namespace: {}", namespace);
 
     // Get all of the services in the environments.
-    let all_services = get_services(&mut tx, &env, &opts.skips).await?;
+    let all_services = get_services(&mut tx, &env, &opts.skips, opts.include_disabled).await?;
+
+    // Pinned services get the build file for their pinned version, which is
+    // written to the environment's builds directory so that it's used instead
+    // of the one in the release.
+    for svc in &all_services {
+        if let Some(version) = pins.version_for(&svc.name) {
+            let repo = db::get_repo_by_id(&mut tx, svc.repo_id).await?;
+            let env_builds_dir = builds_dir.join(env);
+            std::fs::create_dir_all(&env_builds_dir)?;
+
+            println!("Downloading {} {} (pinned)", svc.name, version);
+            releases::process_release_tarball(
+                &releases::get_repo_url(&repo)?,
+                &svc.name,
+                Some(version),
+                &env_builds_dir,
+                &services_dir.join(env).join(&svc.name),
+            )
+            .await?;
+        }
+    }
 
     // From the list of all services, get those that should be deployed
     // first.
@@ -182,6 +430,38 @@ pub async fn deploy(
         templates::render_db(&mut tx, &env, &release_repo_dir, &env_configdir).await?;
     }
 
+    // Save the manifests before anything is applied, so that they're there
+    // to look at even if applying them fails.
+    if let Some(out_dir) = &opts.manifest_out {
+        let all = pre_deploy_services
+            .iter()
+            .chain(services.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        write_manifests(
+            out_dir,
+            release_repo_dir,
+            env,
+            &namespace,
+            &env_configdir,
+            &secrets_dir,
+            &all,
+        )?;
+    }
+
+    if opts.dry_run {
+        println!("Dry run, so nothing was applied to {}.", namespace);
+        tx.commit().await?;
+        return Ok(true);
+    }
+
+    // Make sure the namespace exists before anything is applied to it.
+    if !(opts.no_load_configs && opts.no_load_secrets && opts.no_deploy)
+        && kubectl::ensure_namespace(&namespace)?
+    {
+        println!("Created namespace {}", namespace);
+    }
+
     // Load the configs.
     if !opts.no_load_configs {
         configs::load_configs(&namespace, "service-configs", &env_configdir)?;
@@ -193,17 +473,78 @@ pub async fn deploy(
     }
 
     // Deploy the services.
+    let mut warnings = Warnings::default();
     if !opts.no_deploy {
-        pre_deploy_services.iter().for_each(|svc| {
-            deploy_service(&release_repo_dir, &namespace, svc).expect("failed to deploy service");
-        });
+        let mut results = deploy_services(
+            release_repo_dir,
+            env,
+            &namespace,
+            &pre_deploy_services,
+            opts.concurrency,
+        )
+        .await?;
 
-        services.iter().for_each(|svc| {
-            deploy_service(&release_repo_dir, &namespace, svc).expect("failed to deploy service");
-        });
+        // The rest of the services may depend on the ones deployed first.
+        if results.iter().all(|(_, r)| r.is_ok()) || opts.continue_on_error {
+            results.extend(
+                deploy_services(
+                    release_repo_dir,
+                    env,
+                    &namespace,
+                    &services,
+                    opts.concurrency,
+                )
+                .await?,
+            );
+        } else {
+            println!("Not deploying the rest of the services because a pre-deploy service failed.");
+        }
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        print_deploy_report(&results);
+
+        let failed = results
+            .iter()
+            .filter(|(_, r)| r.is_err())
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        if !failed.is_empty() && !opts.continue_on_error {
+            return Err(exit::Error::ExternalTool(format!(
+                "{} service(s) failed to deploy: {}",
+                failed.len(),
+                failed.join(", ")
+            ))
+            .into());
+        }
+        for (name, result) in &results {
+            if let Err(e) = result {
+                warnings.warn(format!("{} failed to deploy: {:#}", name, e));
+            }
+        }
+
+        if opts.wait {
+            let deployed = pre_deploy_services
+                .iter()
+                .chain(services.iter())
+                .filter(|svc| !failed.contains(&svc.name.as_str()))
+                .copied()
+                .collect::<Vec<_>>();
+            let not_ready = wait_for_rollouts(&namespace, &deployed, opts.wait_timeout);
+            if !not_ready.is_empty() {
+                warnings.print_summary();
+                return Err(anyhow!(
+                    "{} service(s) were not ready within {} seconds: {}",
+                    not_ready.len(),
+                    opts.wait_timeout.as_secs(),
+                    not_ready.join(", ")
+                ));
+            }
+            println!("All {} service(s) are ready.", deployed.len());
+        }
     }
 
     tx.commit().await?;
+    warnings.print_summary();
+    warnings.check()?;
 
     Ok(true)
 }
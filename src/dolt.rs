@@ -1,36 +1,841 @@
 //! # Dolt
 //!
 //! This module contains functions for interacting with Dolt.
+use crate::{
+    cmd::{self, CommandExt},
+    commit::Author,
+    db, exit,
+};
 use anyhow::{Context, Result};
-use duct::{cmd, Handle};
+use duct::Handle;
+use sqlx::{Pool, Postgres};
+use std::net::TcpListener;
+use std::path::Path;
 use std::process::Command;
+use std::time::{Duration, Instant};
 
-/// Uses Dolt to clone a repository from the remote repository.
+/// Uses Dolt to clone a repository from the remote repository. Fails with
+/// the command line and Dolt's error output if the clone fails. Dolt is killed
+/// if `cancel` is canceled before the clone finishes.
 ///
 /// # Examples
 /// ```ignore
-///     let result = dolt::clone("discoenv/de_releases", "db/de_releases").unwrap();
-///     assert_eq!(result, true);
+///     dolt::clone("discoenv/de_releases", "db/de_releases", &cmd::Cancel::default())?;
 /// ```
-pub fn clone(dolt_repo: &str, db_dir: &str) -> Result<bool> {
-    Ok(Command::new("dolt")
+pub fn clone(dolt_repo: &str, db_dir: &str, cancel: &cmd::Cancel) -> Result<()> {
+    Command::new(cmd::program("dolt"))
         .args(["clone", dolt_repo, db_dir])
-        .status()
-        .context("Failed to clone dolt repo")?
-        .success())
+        .checked_output_unless_canceled(cancel)?;
+    Ok(())
 }
 
-/// Uses Dolt to start up the database in the background.
+/// Like `clone`, but the error only names the repository instead of the
+/// whole command line, so that it reads better under a progress spinner.
+/// Fails with Dolt's error output if the clone fails.
 ///
 /// # Examples
 /// ```ignore
-///    let handle = dolt::start("db/de_releases").unwrap();
+///     dolt::clone_quietly("cyverse/de_releases", "site/de_releases", &cmd::Cancel::default())?;
 /// ```
-pub fn start(db_dir: &str) -> Result<Handle> {
-    Ok(cmd!("dolt", "sql-server")
+pub fn clone_quietly(dolt_repo: &str, db_dir: &str, cancel: &cmd::Cancel) -> Result<()> {
+    let output = Command::new(cmd::program("dolt"))
+        .args(["clone", dolt_repo, db_dir])
+        .logged()
+        .output_unless_canceled(cancel)
+        .with_context(|| exit::Error::ExternalTool("Failed to clone dolt repo".to_string()))?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "failed to clone {}: {}",
+            dolt_repo,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// The branch that's created when a database is pinned to a tag or a commit.
+pub const PINNED_BRANCH: &str = "mgmt-pinned";
+
+// Returns whether Dolt's error output says that a ref doesn't exist.
+fn is_ref_not_found(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    [
+        "could not find",
+        "not found",
+        "did not match",
+        "does not exist",
+    ]
+    .iter()
+    .any(|m| stderr.contains(m))
+}
+
+// Returns whether a ref names a branch in a Dolt database, either a local one
+// or one on a remote that checking it out would start tracking.
+fn is_branch(db_dir: &str, reference: &str) -> Result<bool> {
+    let output = Command::new(cmd::program("dolt"))
+        .args(["branch", "--list", "--all"])
+        .current_dir(db_dir)
+        .logged()
+        .output()
+        .with_context(|| exit::Error::ExternalTool("Failed to list dolt branches".to_string()))?;
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "failed to list the branches in {}: {}",
+            db_dir,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+        let branch = line.trim_start_matches(['*', ' ']).trim_end();
+        branch == reference
+            || branch
+                .strip_prefix("remotes/")
+                .and_then(|b| b.split_once('/'))
+                .is_some_and(|(_, b)| b == reference)
+    }))
+}
+
+/// Checks out a branch, tag, or commit in a Dolt database. Dolt can't check
+/// out anything but a branch directly, so anything that `dolt branch` doesn't
+/// list is checked out on a `PINNED_BRANCH` branch that starts at it. The branch is moved if it
+/// already exists, so pinning a database again works. Fails with a usage
+/// error if the ref doesn't exist, and with Dolt's error output otherwise.
+///
+/// # Examples
+/// ```ignore
+///     dolt::checkout("site/de_releases", "v2024.05")?;
+/// ```
+pub fn checkout(db_dir: &str, reference: &str) -> Result<()> {
+    let run = |args: &[&str]| {
+        Command::new(cmd::program("dolt"))
+            .arg("checkout")
+            .args(args)
+            .current_dir(db_dir)
+            .logged()
+            .output()
+            .with_context(|| exit::Error::ExternalTool("Failed to check out dolt ref".to_string()))
+    };
+
+    let output = if is_branch(db_dir, reference)? {
+        run(&[reference])?
+    } else {
+        run(&["-B", PINNED_BRANCH, reference])?
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if is_ref_not_found(&stderr) {
+            return Err(exit::Error::Usage(format!(
+                "the ref {} wasn't found in the database in {}. Check the --db-ref.",
+                reference, db_dir
+            ))
+            .into());
+        }
+        return Err(exit::Error::ExternalTool(format!(
+            "failed to check out {} in {}: {}",
+            reference,
+            db_dir,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Returns whether a directory holds a usable Dolt database: one with a
+/// `.dolt` directory and at least one commit. A clone that was interrupted
+/// partway through fails this check.
+///
+/// # Examples
+/// ```ignore
+///     if !dolt::is_database("site/de_releases") {
+///         dolt::clone_quietly("cyverse/de_releases", "site/de_releases", &cancel)?;
+///     }
+/// ```
+pub fn is_database(db_dir: &str) -> bool {
+    Path::new(db_dir).join(".dolt").is_dir() && local_head_commit(db_dir).is_ok()
+}
+
+/// Uses Dolt to create an empty database in an existing directory. The name
+/// and email are recorded as the author of the initial commit, so that the
+/// database can be created without a global Dolt identity.
+///
+/// # Examples
+/// ```ignore
+///     dolt::init("/tmp/selftest/mgmt_selftest", "mgmt", "mgmt@localhost")?;
+/// ```
+pub fn init(db_dir: &str, name: &str, email: &str) -> Result<()> {
+    Command::new(cmd::program("dolt"))
+        .args(["init", "--name", name, "--email", email])
+        .current_dir(db_dir)
+        .checked_output()?;
+    Ok(())
+}
+
+/// The port the Dolt SQL server listens on by default, and the first one
+/// tried when looking for a free port.
+pub const DEFAULT_PORT: u16 = 3306;
+
+/// The last port tried when looking for a free port.
+pub const LAST_PORT: u16 = 3400;
+
+/// Returns the first port from `first` through `LAST_PORT` that nothing is
+/// listening on.
+///
+/// # Examples
+/// ```ignore
+///     let port = dolt::free_port(dolt::DEFAULT_PORT)?;
+/// ```
+pub fn free_port(first: u16) -> Result<u16> {
+    (first..=LAST_PORT)
+        .find(|port| TcpListener::bind(("127.0.0.1", *port)).is_ok())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "couldn't find a free port between {} and {} for the dolt server",
+                first,
+                LAST_PORT
+            )
+        })
+}
+
+/// How long to wait for a freshly started server to accept connections when
+/// `--db-startup-timeout` isn't given.
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The first and longest waits between attempts to connect to a server that
+// isn't ready yet.
+const STARTUP_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_STARTUP_BACKOFF: Duration = Duration::from_secs(2);
+
+// Returns how long to wait after a failed attempt to connect, doubling with
+// each attempt up to MAX_STARTUP_BACKOFF.
+fn startup_backoff(attempt: u32) -> Duration {
+    STARTUP_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_STARTUP_BACKOFF)
+}
+
+/// A Dolt SQL server started by `start`. The server is stopped when this is
+/// dropped, so that an error partway through a command doesn't leave it
+/// running after `mgmt` exits.
+pub struct RunningServer {
+    handle: Option<Handle>,
+    port: u16,
+}
+
+impl RunningServer {
+    /// Returns the port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the URL for connecting to a database served by the server.
+    pub fn url(&self, db_name: &str) -> String {
+        format!("mysql://root@127.0.0.1:{}/{}", self.port, db_name)
+    }
+
+    /// Connects to a database served by the server.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let server = dolt::start("site/de_releases", None, false)?;
+    ///     let pool = server.connect("de_releases").await?;
+    /// ```
+    pub async fn connect(&self, db_name: &str) -> Result<Pool<Postgres>> {
+        let url = self.url(db_name);
+        db::pool_options(&url)
+            .connect(&url)
+            .await
+            .with_context(|| format!("Failed to connect to the dolt server on port {}", self.port))
+    }
+
+    /// Connects to a database served by the server, retrying with
+    /// exponential backoff until the server accepts connections. A server
+    /// that was just started usually needs a moment before it does. Fails if
+    /// the server exits or still isn't ready once `timeout` has passed.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let server = dolt::start("site/de_releases", None, false)?;
+    ///     let pool = server
+    ///         .connect_when_ready("de_releases", dolt::DEFAULT_STARTUP_TIMEOUT)
+    ///         .await?;
+    /// ```
+    pub async fn connect_when_ready(
+        &self,
+        db_name: &str,
+        timeout: Duration,
+    ) -> Result<Pool<Postgres>> {
+        let url = self.url(db_name);
+        let deadline = Instant::now() + timeout;
+        let mut attempt = 0;
+        loop {
+            let err = match db::pool_options(&url).connect(&url).await {
+                Ok(pool) => return Ok(pool),
+                Err(e) => e,
+            };
+
+            // duct reports a server that exited with an error as an error,
+            // so anything but "still running" means it's gone.
+            let exited = match &self.handle {
+                Some(handle) => !matches!(handle.try_wait(), Ok(None)),
+                None => false,
+            };
+            if exited {
+                return Err(exit::Error::ExternalTool(format!(
+                    "the Dolt server on port {} exited before it became ready: {}",
+                    self.port, err
+                ))
+                .into());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(exit::Error::ExternalTool(format!(
+                    "the Dolt server on port {} never became ready within {} seconds: {}. Use --db-startup-timeout to wait longer.",
+                    self.port,
+                    timeout.as_secs(),
+                    err
+                ))
+                .into());
+            }
+            tracing::debug!(port = self.port, attempt, error = %err, "Dolt server isn't ready yet");
+            tokio::time::sleep(startup_backoff(attempt).min(deadline - now)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Stops the server, returning an error if it couldn't be stopped.
+    /// Dropping the server stops it too, but ignores any error.
+    pub fn stop(mut self) -> Result<()> {
+        if let Some(handle) = self.handle.take() {
+            handle.kill().context("Failed to stop the dolt server")?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.kill();
+        }
+    }
+}
+
+/// Starts a temporary read-only server for the Dolt database in a directory,
+/// such as the copy site init clones into a site, and connects to it. This
+/// lets the commands that only read run against that copy instead of the
+/// database given by --database-url. The server is stopped when it's dropped.
+///
+/// # Examples
+/// ```ignore
+///     let (server, pool) = dolt::connect_read_only(Path::new("site/de_releases")).await?;
+///     ops::list_values(&pool, "qa", None).await?;
+///     server.stop()?;
+/// ```
+pub async fn connect_read_only(db_dir: &Path) -> Result<(RunningServer, Pool<Postgres>)> {
+    let not_a_database = || {
+        exit::Error::Usage(format!(
+            "{} isn't a Dolt database. Run site init to clone it first.",
+            db_dir.display()
+        ))
+    };
+    let db_dir = db_dir.canonicalize().map_err(|_| not_a_database())?;
+    let (Some(db_dir_str), Some(db_name)) = (
+        db_dir.to_str(),
+        db_dir.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return Err(anyhow::anyhow!(
+            "failed to get database directory {} as a string",
+            db_dir.display()
+        ));
+    };
+    if !is_database(db_dir_str) {
+        return Err(not_a_database().into());
+    }
+
+    let server = start(db_dir_str, None, true)?;
+    let pool = server
+        .connect_when_ready(db_name, DEFAULT_STARTUP_TIMEOUT)
+        .await?;
+    Ok((server, pool))
+}
+
+/// Uses Dolt to start up the database in the background. It listens on the
+/// given port, or on the first free port starting at `DEFAULT_PORT` if none
+/// is given. If `read_only` is true, the server is started with Dolt's
+/// `--readonly` flag and rejects any statement that would modify the
+/// database.
+///
+/// # Examples
+/// ```ignore
+///    let server = dolt::start("db/de_releases", None, false)?;
+///    let pool = server.connect("de_releases").await?;
+/// ```
+pub fn start(db_dir: &str, port: Option<u16>, read_only: bool) -> Result<RunningServer> {
+    let port = match port {
+        Some(port) => port,
+        None => free_port(DEFAULT_PORT)?,
+    };
+
+    let mut args = vec![
+        "sql-server".to_string(),
+        "--port".to_string(),
+        port.to_string(),
+    ];
+    if read_only {
+        args.push("--readonly".to_string());
+    }
+
+    let program = cmd::program("dolt");
+    tracing::debug!(
+        command = %format!("{} {}", program, args.join(" ")),
+        dir = db_dir,
+        "running command"
+    );
+    let handle = duct::cmd(program, args)
         .dir(db_dir)
         .stderr_to_stdout()
         .stdout_capture()
         .start()
-        .context("Failed to start dolt server")?)
+        .with_context(|| exit::Error::ExternalTool("Failed to start dolt server".to_string()))?;
+
+    Ok(RunningServer {
+        handle: Some(handle),
+        port,
+    })
+}
+
+/// Returns the hash of the most recent commit in the Dolt database the pool
+/// is connected to, as reported by the `dolt_log` system table.
+///
+/// # Examples
+/// ```ignore
+///     let head = dolt::head_commit(&pool).await?;
+/// ```
+pub async fn head_commit(pool: &Pool<Postgres>) -> Result<String> {
+    sqlx::query_scalar::<_, String>("SELECT commit_hash FROM dolt_log LIMIT 1")
+        .fetch_one(pool)
+        .await
+        .context("Failed to get the HEAD commit from dolt_log")
+}
+
+/// Stages all of the changes in the Dolt database the pool is connected to
+/// and commits them with the given message. If an author is passed in, it's
+/// used instead of the server's configured identity.
+///
+/// # Examples
+/// ```ignore
+///     dolt::commit(&pool, "Remove orphaned config values", None).await?;
+/// ```
+pub async fn commit(pool: &Pool<Postgres>, message: &str, author: Option<&Author>) -> Result<()> {
+    let query = match author {
+        Some(author) => sqlx::query("SELECT DOLT_COMMIT('-Am', $1, '--author', $2)")
+            .bind(message)
+            .bind(author.to_string()),
+        None => sqlx::query("SELECT DOLT_COMMIT('-Am', $1)").bind(message),
+    };
+    query
+        .execute(pool)
+        .await
+        .context("Failed to commit the changes to the dolt database")?;
+    Ok(())
+}
+
+// Runs a query against a Dolt database directory with `dolt sql` and returns
+// the result as CSV. `what` describes the query for error messages.
+fn local_query(db_dir: &str, query: &str, what: &str) -> Result<String> {
+    let output = Command::new(cmd::program("dolt"))
+        .args(["sql", "-r", "csv", "-q", query])
+        .current_dir(db_dir)
+        .logged()
+        .output()
+        .with_context(|| exit::Error::ExternalTool(format!("Failed to {}", what)))?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "dolt sql failed in {}: {}",
+            db_dir,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Returns the hash of the most recent commit in a Dolt database directory.
+/// Unlike `head_commit`, this doesn't need a running server.
+///
+/// # Examples
+/// ```ignore
+///     let head = dolt::local_head_commit("site/de_releases")?;
+/// ```
+pub fn local_head_commit(db_dir: &str) -> Result<String> {
+    let output = local_query(
+        db_dir,
+        "SELECT commit_hash FROM dolt_log LIMIT 1",
+        "get the HEAD commit of the dolt database",
+    )?;
+
+    // The first line of the output is the CSV header.
+    output
+        .lines()
+        .nth(1)
+        .map(|line| line.trim().to_string())
+        .context("dolt did not return a HEAD commit")
+}
+
+/// A table with uncommitted changes, along with how it changed, such as
+/// "modified" or "new table".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStatus {
+    pub table: String,
+    pub status: String,
+}
+
+/// The uncommitted changes in a Dolt database's working set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoltStatus {
+    pub staged: Vec<TableStatus>,
+    pub unstaged: Vec<TableStatus>,
+}
+
+impl DoltStatus {
+    /// Returns whether there are any uncommitted changes, staged or not.
+    pub fn is_dirty(&self) -> bool {
+        !self.staged.is_empty() || !self.unstaged.is_empty()
+    }
+}
+
+// Parses the CSV output of a query against the dolt_status system table. The
+// staged column is printed as either 1/0 or true/false depending on the
+// version of Dolt.
+fn parse_status(csv: &str) -> Result<DoltStatus> {
+    let mut status = DoltStatus::default();
+    for line in csv.lines().skip(1).filter(|l| !l.trim().is_empty()) {
+        let fields = line.trim().splitn(3, ',').collect::<Vec<_>>();
+        let [table, staged, change] = fields[..] else {
+            return Err(anyhow::anyhow!(
+                "unexpected line in the dolt status output: {}",
+                line
+            ));
+        };
+
+        let table = TableStatus {
+            table: table.to_string(),
+            status: change.to_string(),
+        };
+        match staged {
+            "1" | "true" => status.staged.push(table),
+            _ => status.unstaged.push(table),
+        }
+    }
+    Ok(status)
+}
+
+/// Returns the uncommitted changes in a Dolt database directory. Like
+/// `local_head_commit`, this doesn't need a running server.
+///
+/// # Examples
+/// ```ignore
+///     if dolt::status("site/de_releases")?.is_dirty() {
+///         println!("The database has uncommitted changes");
+///     }
+/// ```
+pub fn status(db_dir: &str) -> Result<DoltStatus> {
+    let output = local_query(
+        db_dir,
+        "SELECT table_name, staged, status FROM dolt_status ORDER BY table_name",
+        "get the status of the dolt database",
+    )?;
+    parse_status(&output)
+}
+
+/// Pulls the latest commits from a Dolt database's remote into an existing
+/// clone of it. Fails without pulling if the database has uncommitted
+/// changes, since merging into a dirty working set can leave it half-merged.
+/// If the pull itself fails, such as on a merge conflict, the merge is
+/// aborted so that the database is left the way it was.
+///
+/// # Examples
+/// ```ignore
+///     dolt::pull("site/de_releases")?;
+/// ```
+pub fn pull(db_dir: &str) -> Result<()> {
+    let status = status(db_dir)?;
+    if status.is_dirty() {
+        let tables = status
+            .staged
+            .iter()
+            .chain(status.unstaged.iter())
+            .map(|t| t.table.as_str())
+            .collect::<Vec<_>>();
+        return Err(exit::Error::Usage(format!(
+            "the database in {} has uncommitted changes to {}. Commit or discard them before pulling.",
+            db_dir,
+            tables.join(", ")
+        ))
+        .into());
+    }
+
+    let output = Command::new(cmd::program("dolt"))
+        .arg("pull")
+        .current_dir(db_dir)
+        .logged()
+        .output()
+        .with_context(|| exit::Error::ExternalTool("Failed to pull dolt repo".to_string()))?;
+
+    if !output.status.success() {
+        // There's nothing to abort if the pull failed before merging, so the
+        // result doesn't matter.
+        let _ = Command::new(cmd::program("dolt"))
+            .args(["merge", "--abort"])
+            .current_dir(db_dir)
+            .logged()
+            .output();
+
+        return Err(exit::Error::ExternalTool(format!(
+            "dolt pull failed in {}: {}",
+            db_dir,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let csv =
+            "table_name,staged,status\nconfig_values,1,modified\nenvironments,false,new table\n";
+        let status = parse_status(csv).unwrap();
+        assert!(status.is_dirty());
+        assert_eq!(
+            status.staged,
+            vec![TableStatus {
+                table: "config_values".to_string(),
+                status: "modified".to_string(),
+            }]
+        );
+        assert_eq!(
+            status.unstaged,
+            vec![TableStatus {
+                table: "environments".to_string(),
+                status: "new table".to_string(),
+            }]
+        );
+
+        assert!(!parse_status("table_name,staged,status\n")
+            .unwrap()
+            .is_dirty());
+        assert!(parse_status("table_name,staged,status\noops\n").is_err());
+    }
+
+    #[test]
+    fn test_running_server_stops_on_drop() {
+        let handle = duct::cmd("sleep", ["30"]).start().unwrap();
+        let pid = handle.pids()[0];
+        let server = RunningServer {
+            handle: Some(handle),
+            port: DEFAULT_PORT,
+        };
+        assert!(std::path::Path::new(&format!("/proc/{}", pid)).exists());
+
+        // Stands in for an error partway through a command.
+        let failed: Result<()> = {
+            let _server = server;
+            Err(anyhow::anyhow!("init failed"))
+        };
+        assert!(failed.is_err());
+        assert!(!std::path::Path::new(&format!("/proc/{}", pid)).exists());
+    }
+
+    #[test]
+    fn test_free_port() {
+        let port = free_port(DEFAULT_PORT).unwrap();
+        let _listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        assert!(free_port(port).unwrap() > port);
+        assert!(free_port(LAST_PORT + 1).is_err());
+    }
+
+    #[test]
+    fn test_startup_backoff() {
+        assert_eq!(startup_backoff(0), STARTUP_BACKOFF);
+        assert_eq!(startup_backoff(1), STARTUP_BACKOFF * 2);
+        assert_eq!(startup_backoff(3), STARTUP_BACKOFF * 8);
+        assert_eq!(startup_backoff(10), MAX_STARTUP_BACKOFF);
+        assert_eq!(startup_backoff(u32::MAX), MAX_STARTUP_BACKOFF);
+    }
+
+    #[test]
+    fn test_is_ref_not_found() {
+        assert!(is_ref_not_found("error: could not find v9.9"));
+        assert!(is_ref_not_found("branch not found: nope"));
+        assert!(!is_ref_not_found("permission denied"));
+    }
+
+    #[test]
+    fn test_is_database() {
+        let dir = std::env::temp_dir().join(format!("mgmt-dolt-partial-{}", std::process::id()));
+        assert!(!is_database(&dir.to_string_lossy()));
+
+        // A directory without a .dolt directory, like the start of an
+        // interrupted clone, isn't a database.
+        std::fs::create_dir_all(dir.join("tables")).unwrap();
+        assert!(!is_database(&dir.to_string_lossy()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Needs the dolt binary, so it's ignored by default. Run with
+    // `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_checkout() {
+        let dir = std::env::temp_dir().join(format!("mgmt-dolt-test-{}", std::process::id()));
+        let origin = dir.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+
+        let dolt = |dir: &std::path::Path, args: &[&str]| {
+            let status = Command::new(cmd::program("dolt"))
+                .args(args)
+                .current_dir(dir)
+                .logged()
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "dolt {:?} failed", args);
+        };
+        dolt(
+            &origin,
+            &["init", "--name", "Test", "--email", "test@example.org"],
+        );
+        dolt(
+            &origin,
+            &["sql", "-q", "create table t (id int primary key)"],
+        );
+        dolt(&origin, &["commit", "-Am", "add t"]);
+        dolt(&origin, &["tag", "v1"]);
+        dolt(&origin, &["sql", "-q", "insert into t values (1)"]);
+        dolt(&origin, &["commit", "-Am", "add a row"]);
+        dolt(&origin, &["branch", "release"]);
+
+        // Dolt only clones from remotes, so the database is pushed to a file
+        // remote first.
+        let url = format!("file://{}", dir.join("remote").display());
+        dolt(&origin, &["remote", "add", "origin", &url]);
+        dolt(&origin, &["push", "origin", "main"]);
+        dolt(&origin, &["push", "origin", "release"]);
+        dolt(&origin, &["push", "origin", "v1"]);
+
+        let clone = dir.join("clone");
+        let clone_str = clone.to_string_lossy();
+        clone_quietly(&url, &clone_str, &cmd::Cancel::default()).unwrap();
+
+        checkout(&clone_str, "v1").unwrap();
+        assert!(is_database(&clone_str));
+
+        // Pinning again moves the pinned branch instead of failing.
+        checkout(&clone_str, "v1").unwrap();
+        let count = Command::new(cmd::program("dolt"))
+            .args(["sql", "-r", "csv", "-q", "select count(*) as n from t"])
+            .current_dir(&clone)
+            .logged()
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&count.stdout).trim(), "n\n0");
+
+        // A branch that's only on the remote is checked out as itself.
+        checkout(&clone_str, "release").unwrap();
+        assert!(is_branch(&clone_str, "release").unwrap());
+        assert!(!is_branch(&clone_str, "v1").unwrap());
+
+        let err = checkout(&clone_str, "no-such-ref").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<exit::Error>(),
+            Some(exit::Error::Usage(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Needs the dolt binary, so it's ignored by default. Run with
+    // `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_read_only_server() {
+        let dir = std::env::temp_dir().join(format!("mgmt-dolt-read-only-{}", std::process::id()));
+        let db_dir = dir.join("de_releases");
+        std::fs::create_dir_all(&db_dir).unwrap();
+        let db_dir_str = db_dir.to_string_lossy().to_string();
+        init(&db_dir_str, "Test", "test@example.org").unwrap();
+        let status = Command::new(cmd::program("dolt"))
+            .args(["sql", "-q", "create table t (id int primary key)"])
+            .current_dir(&db_dir)
+            .logged()
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let server = start(&db_dir_str, Some(free_port(DEFAULT_PORT).unwrap()), true).unwrap();
+        let pool = server
+            .connect_when_ready("de_releases", DEFAULT_STARTUP_TIMEOUT)
+            .await
+            .unwrap();
+        sqlx::query("SELECT COUNT(*) FROM t")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(sqlx::query("INSERT INTO t VALUES (1)")
+            .execute(&pool)
+            .await
+            .is_err());
+        pool.close().await;
+        server.stop().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Needs the dolt binary, so it's ignored by default. Run with
+    // `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_clone_bogus_url() {
+        let dir = std::env::temp_dir().join(format!("mgmt-dolt-bogus-{}", std::process::id()));
+        let url = format!("file://{}", dir.join("missing").display());
+        let clone_dir = dir.join("clone").to_string_lossy().to_string();
+
+        // Dolt's own complaint comes last, so the message shouldn't end with
+        // an empty one.
+        let message = clone(&url, &clone_dir, &cmd::Cancel::default())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("dolt clone"), "{}", message);
+        assert!(
+            !message.ends_with(':') && !message.ends_with(": "),
+            "{}",
+            message
+        );
+
+        let message = clone_quietly(&url, &clone_dir, &cmd::Cancel::default())
+            .unwrap_err()
+            .to_string();
+        let prefix = format!("failed to clone {}: ", url);
+        assert!(message.len() > prefix.len(), "{}", message);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
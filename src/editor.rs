@@ -0,0 +1,426 @@
+//! # Editor
+//!
+//! A terminal UI for browsing and editing the configuration values of an
+//! environment, for when answering every prompt in `ask_for_info` is more
+//! than the change calls for. The editor only collects the edits; they're
+//! written to the database and committed once it's closed, so nothing is
+//! changed if it's quit without saving.
+use crate::{
+    config_values::{config::SectionOptions, value_type::ValueType},
+    db,
+};
+use anyhow::{Context, Result};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    Frame, Terminal,
+};
+use sqlx::{Postgres, Transaction};
+
+/// A configuration key along with the value the environment has for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value_type: ValueType,
+
+    /// The value as it's been edited. Starts out as `original`.
+    pub value: String,
+
+    /// The environment's value when the editor was opened, or the default
+    /// value if the environment doesn't have one.
+    pub original: String,
+
+    /// Whether the environment has its own value, rather than falling back to
+    /// the default.
+    pub is_set: bool,
+
+    /// Keys without a default need a value from the environment.
+    pub required: bool,
+}
+
+impl Entry {
+    /// Returns whether the value has been changed in the editor.
+    pub fn is_modified(&self) -> bool {
+        self.value != self.original
+    }
+
+    /// Checks a new value for the entry against its type and whether it's
+    /// required.
+    pub fn check(&self, value: &str) -> Result<()> {
+        if self.required && value.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{}.{} has no default, so it needs a value",
+                self.section,
+                self.key
+            ));
+        }
+        self.value_type.validate(value)
+    }
+}
+
+/// Loads an entry for each default key in the sections enabled for the
+/// environment, filled in with the environment's values where it has them.
+///
+/// # Examples
+/// ```ignore
+///     let opts = SectionOptions::new_from_db(&pool, "qa").await?;
+///     let entries = editor::load_entries(&mut tx, "qa", &opts).await?;
+/// ```
+pub async fn load_entries(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    opts: &SectionOptions,
+) -> Result<Vec<Entry>> {
+    let values = db::list_config_values(tx, Some(env), None, None).await?;
+    let defaults = db::list_default_config_values(tx, None, None).await?;
+
+    defaults
+        .into_iter()
+        .filter(|d| opts.include_section(&d.section))
+        .map(|d| {
+            let value_type = d.value_type.parse::<ValueType>()?;
+            let set = values
+                .iter()
+                .find(|v| v.section == d.section && v.key == d.key);
+            let original = set.map_or_else(|| d.value.clone(), |v| v.value.clone());
+            Ok(Entry {
+                required: d.value.is_empty(),
+                is_set: set.is_some(),
+                value: original.clone(),
+                original,
+                section: d.section,
+                key: d.key,
+                value_type,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Browse,
+    Editing { input: String },
+}
+
+// What the event loop should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Continue,
+    Save,
+    Quit,
+}
+
+struct Editor {
+    env: String,
+    entries: Vec<Entry>,
+    selected: usize,
+    mode: Mode,
+    status: String,
+
+    // Set after q is pressed with unsaved changes, so a second q confirms.
+    confirm_quit: bool,
+}
+
+impl Editor {
+    fn new(env: &str, entries: Vec<Entry>) -> Self {
+        Editor {
+            env: env.to_string(),
+            entries,
+            selected: 0,
+            mode: Mode::Browse,
+            status: String::from("Enter: edit  s: save  q: quit"),
+            confirm_quit: false,
+        }
+    }
+
+    fn modified_count(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_modified()).count()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Action::Quit;
+        }
+
+        match &mut self.mode {
+            Mode::Browse => self.handle_browse_key(key.code),
+            Mode::Editing { input } => match key.code {
+                KeyCode::Esc => {
+                    self.mode = Mode::Browse;
+                    self.status = String::from("Edit cancelled");
+                    Action::Continue
+                }
+                KeyCode::Enter => {
+                    let input = input.clone();
+                    let entry = &mut self.entries[self.selected];
+                    match entry.check(&input) {
+                        Ok(()) => {
+                            entry.value = input;
+                            self.status = format!("{} unsaved change(s)", self.modified_count());
+                            self.mode = Mode::Browse;
+                        }
+                        Err(e) => self.status = e.to_string(),
+                    }
+                    Action::Continue
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    Action::Continue
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    Action::Continue
+                }
+                _ => Action::Continue,
+            },
+        }
+    }
+
+    fn handle_browse_key(&mut self, code: KeyCode) -> Action {
+        let confirm_quit = std::mem::take(&mut self.confirm_quit);
+
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') if self.selected + 1 < self.entries.len() => {
+                self.selected += 1;
+            }
+            KeyCode::Enter => {
+                if let Some(entry) = self.entries.get(self.selected) {
+                    // The input is a single line, so multi-line values would
+                    // get mangled.
+                    if entry.value.contains('\n') {
+                        self.status = format!(
+                            "{}.{} is a multi-line value; use `mgmt configs values set` to change it",
+                            entry.section, entry.key
+                        );
+                    } else {
+                        self.mode = Mode::Editing {
+                            input: entry.value.clone(),
+                        };
+                        self.status = String::from("Enter: accept  Esc: cancel");
+                    }
+                }
+            }
+            KeyCode::Char('s') => {
+                if self.modified_count() > 0 {
+                    return Action::Save;
+                }
+                self.status = String::from("Nothing to save");
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.modified_count() == 0 || confirm_quit {
+                    return Action::Quit;
+                }
+                self.confirm_quit = true;
+                self.status = format!(
+                    "{} unsaved change(s). Press q again to quit without saving, or s to save",
+                    self.modified_count()
+                );
+            }
+            _ => {}
+        }
+
+        Action::Continue
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(3)])
+            .split(frame.size());
+
+        let rows = self.entries.iter().map(|entry| {
+            let marker = if entry.is_modified() { "*" } else { " " };
+            let required = if entry.required { "yes" } else { "" };
+            let style = if entry.is_modified() {
+                Style::default().fg(Color::Yellow)
+            } else if !entry.is_set {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                format!("{}{}", marker, entry.section),
+                entry.key.clone(),
+                entry.value_type.to_string(),
+                required.to_string(),
+                entry.value.replace('\n', "\\n"),
+            ])
+            .style(style)
+        });
+
+        let widths = [
+            Constraint::Length(24),
+            Constraint::Length(28),
+            Constraint::Length(7),
+            Constraint::Length(8),
+            Constraint::Min(10),
+        ];
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec!["Section", "Key", "Type", "Required", "Value"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Configuration values for {}", self.env)),
+            )
+            .widths(&widths)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let mut state = TableState::default();
+        state.select(Some(self.selected));
+        frame.render_stateful_widget(table, chunks[0], &mut state);
+
+        let footer = match &self.mode {
+            Mode::Browse => {
+                Paragraph::new(self.status.clone()).block(Block::default().borders(Borders::ALL))
+            }
+            Mode::Editing { input } => {
+                let entry = &self.entries[self.selected];
+                Paragraph::new(input.clone()).block(Block::default().borders(Borders::ALL).title(
+                    format!(
+                        "{}.{} ({}) - {}",
+                        entry.section, entry.key, entry.value_type, self.status
+                    ),
+                ))
+            }
+        };
+        frame.render_widget(footer, chunks[1]);
+    }
+}
+
+// Runs the event loop until the user saves or quits.
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    editor: &mut Editor,
+) -> Result<Action> {
+    loop {
+        terminal.draw(|frame| editor.draw(frame))?;
+
+        if let Event::Key(key) = event::read()? {
+            // Windows reports key releases too.
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match editor.handle_key(key) {
+                Action::Continue => {}
+                action => return Ok(action),
+            }
+        }
+    }
+}
+
+/// Opens the editor on the entries and waits for the user to save or quit.
+/// Returns the entries that were changed if the user saved, or `None` if they
+/// quit without saving.
+///
+/// # Examples
+/// ```ignore
+///     if let Some(changes) = editor::run("qa", entries)? {
+///         for entry in changes {
+///             ops::set_value(&pool, "qa", &entry.section, &entry.key, &entry.value, entry.value_type.as_str(), "jdoe").await?;
+///         }
+///     }
+/// ```
+pub fn run(env: &str, entries: Vec<Entry>) -> Result<Option<Vec<Entry>>> {
+    let mut editor = Editor::new(env, entries);
+
+    enable_raw_mode().context("failed to put the terminal into raw mode")?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = event_loop(&mut terminal, &mut editor);
+
+    // Put the terminal back before reporting anything, even if the event loop
+    // failed.
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    match result? {
+        Action::Save => Ok(Some(
+            editor
+                .entries
+                .into_iter()
+                .filter(|e| e.is_modified())
+                .collect(),
+        )),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(value_type: ValueType, value: &str, required: bool) -> Entry {
+        Entry {
+            section: "DE".to_string(),
+            key: "Port".to_string(),
+            value_type,
+            value: value.to_string(),
+            original: value.to_string(),
+            is_set: true,
+            required,
+        }
+    }
+
+    fn press(editor: &mut Editor, code: KeyCode) -> Action {
+        editor.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_str(editor: &mut Editor, s: &str) {
+        for c in s.chars() {
+            press(editor, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(entry(ValueType::Int, "", false).check("8080").is_ok());
+        assert!(entry(ValueType::Int, "", false).check("eighty").is_err());
+        assert!(entry(ValueType::Int, "", false).check("").is_ok());
+        assert!(entry(ValueType::Int, "", true).check("").is_err());
+    }
+
+    #[test]
+    fn test_edit_rejects_invalid_values() {
+        let mut editor = Editor::new("qa", vec![entry(ValueType::Int, "8080", false)]);
+        press(&mut editor, KeyCode::Enter);
+        press(&mut editor, KeyCode::Backspace);
+        type_str(&mut editor, "x");
+        press(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.mode, Mode::Editing { .. }));
+        assert_eq!(editor.entries[0].value, "8080");
+
+        press(&mut editor, KeyCode::Backspace);
+        type_str(&mut editor, "1");
+        press(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.mode, Mode::Browse);
+        assert_eq!(editor.entries[0].value, "8081");
+        assert_eq!(press(&mut editor, KeyCode::Char('s')), Action::Save);
+    }
+
+    #[test]
+    fn test_quit_with_unsaved_changes() {
+        let mut editor = Editor::new("qa", vec![entry(ValueType::String, "a", false)]);
+        assert_eq!(press(&mut editor, KeyCode::Char('q')), Action::Quit);
+
+        editor.entries[0].value = "b".to_string();
+        assert_eq!(press(&mut editor, KeyCode::Char('q')), Action::Continue);
+        assert_eq!(press(&mut editor, KeyCode::Char('q')), Action::Quit);
+    }
+}
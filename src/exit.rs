@@ -0,0 +1,127 @@
+//! # Exit Codes
+//!
+//! `mgmt` exits with a code that tells scripts and pipelines what kind of
+//! failure happened:
+//!
+//! | Code | Meaning                                        |
+//! |------|------------------------------------------------|
+//! | 0    | Success                                        |
+//! | 1    | Any error that doesn't fall into another group |
+//! | 2    | Bad usage, or input that failed validation     |
+//! | 3    | An external tool such as git or dolt failed    |
+//! | 4    | The database couldn't be reached               |
+//! | 5    | Drift or differences were found                |
+//!
+//! Errors are categorized by wrapping them in an `exit::Error`, either
+//! directly or as context, anywhere along the way to `main`.
+use crate::db;
+
+pub const SUCCESS: i32 = 0;
+pub const GENERIC: i32 = 1;
+pub const USAGE: i32 = 2;
+pub const EXTERNAL_TOOL: i32 = 3;
+pub const DB_CONNECTION: i32 = 4;
+pub const DRIFT: i32 = 5;
+
+/// An error that maps to an exit code other than the generic one.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Bad usage, or input that failed validation.
+    #[error("{0}")]
+    Usage(String),
+
+    /// An external tool failed or couldn't be run.
+    #[error("{0}")]
+    ExternalTool(String),
+
+    /// Drift or differences were found.
+    #[error("{0}")]
+    Drift(String),
+}
+
+impl Error {
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => USAGE,
+            Error::ExternalTool(_) => EXTERNAL_TOOL,
+            Error::Drift(_) => DRIFT,
+        }
+    }
+}
+
+// Finds an error of type T in the error or any of its causes. downcast_ref
+// finds errors that were added as context, while the chain finds the ones
+// that were wrapped by a context.
+fn find<T>(err: &anyhow::Error) -> Option<&T>
+where
+    T: std::error::Error + Send + Sync + 'static,
+{
+    err.downcast_ref::<T>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<T>()))
+}
+
+// Returns whether a sqlx error means the database couldn't be reached, as
+// opposed to a query failing.
+fn is_connection_error(err: &sqlx::Error) -> bool {
+    matches!(
+        err,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Configuration(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+    )
+}
+
+/// Returns the exit code for an error.
+///
+/// # Examples
+/// ```ignore
+///     if let Err(e) = run().await {
+///         eprintln!("Error: {:?}", e);
+///         std::process::exit(exit::code(&e));
+///     }
+/// ```
+pub fn code(err: &anyhow::Error) -> i32 {
+    if let Some(e) = find::<Error>(err) {
+        return e.code();
+    }
+
+    if find::<db::Error>(err).is_some() {
+        return DB_CONNECTION;
+    }
+
+    match find::<sqlx::Error>(err) {
+        Some(e) if is_connection_error(e) => DB_CONNECTION,
+        _ => GENERIC,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code(&anyhow::anyhow!("oops")), GENERIC);
+        assert_eq!(code(&Error::Usage("bad".to_string()).into()), USAGE);
+        assert_eq!(code(&Error::Drift("diff".to_string()).into()), DRIFT);
+        assert_eq!(code(&sqlx::Error::PoolTimedOut.into()), DB_CONNECTION);
+        assert_eq!(code(&sqlx::Error::RowNotFound.into()), GENERIC);
+    }
+
+    #[test]
+    fn test_code_through_context() {
+        let err = Err::<(), _>(Error::ExternalTool("git failed".to_string()))
+            .context("while cloning")
+            .unwrap_err();
+        assert_eq!(code(&err), EXTERNAL_TOOL);
+
+        let err = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+            .context(Error::ExternalTool("failed to run `dolt`".to_string()))
+            .context("while starting the database")
+            .unwrap_err();
+        assert_eq!(code(&err), EXTERNAL_TOOL);
+    }
+}
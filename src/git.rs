@@ -1,53 +1,70 @@
 //! # Git
 //!
 //! This module provides functions for interacting with git.
+use crate::{
+    cmd::{self, CommandExt},
+    commit::Author,
+    exit,
+};
 use anyhow::{Context, Result};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-pub fn add(repodir: &PathBuf, path: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn add(repodir: &PathBuf, path: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["add", "--all", path])
         .current_dir(repodir)
-        .status()
-        .context("git add failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
-pub fn commit(repodir: &PathBuf, msg: &str) -> Result<bool> {
-    Ok(Command::new("git")
+/// Commits the staged changes. If an author is passed in, it's used as both
+/// the author and the committer instead of git's configured identity.
+///
+/// # Examples
+/// ```ignore
+///     mgmt::git::commit(&PathBuf::from("de-releases"), "update builds", None)?;
+/// ```
+pub fn commit(repodir: &PathBuf, msg: &str, author: Option<&Author>) -> Result<()> {
+    let mut command = Command::new(cmd::program("git"));
+    if let Some(author) = author {
+        command.args([
+            "-c",
+            &format!("user.name={}", author.name),
+            "-c",
+            &format!("user.email={}", author.email),
+        ]);
+    }
+
+    command
         .args(["commit", "-m", msg])
         .current_dir(repodir)
-        .status()
-        .context("git commit failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
-pub fn checkout(repodir: &PathBuf, branch: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn checkout(repodir: &PathBuf, branch: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["checkout", branch])
         .current_dir(repodir)
-        .status()
-        .context("git checkout failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
-pub fn push(repodir: &PathBuf, remote: &str, gref: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn push(repodir: &PathBuf, remote: &str, gref: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["push", remote, gref])
         .current_dir(repodir)
-        .status()
-        .context("git push failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
 pub fn list_tags(repodir: &PathBuf, remote: &str) -> Result<Vec<String>> {
-    let output = Command::new("git")
+    let output = Command::new(cmd::program("git"))
         .args(["ls-remote", "--tags", remote])
         .current_dir(repodir)
-        .output()
-        .context("git ls-remote failed")?;
+        .checked_output()?;
 
     let tags = String::from_utf8(output.stdout)
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
@@ -63,33 +80,30 @@ pub fn list_tags(repodir: &PathBuf, remote: &str) -> Result<Vec<String>> {
     Ok(tags)
 }
 
-pub fn tag(repodir: &PathBuf, tag: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn tag(repodir: &PathBuf, tag: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["tag", tag])
         .current_dir(repodir)
-        .status()
-        .context("git tag failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
-pub fn push_tags(repodir: &PathBuf, remote: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn push_tags(repodir: &PathBuf, remote: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["push", remote, "--tags"])
         .current_dir(repodir)
-        .status()
-        .context("git push --tags failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
 /// Uses git to fetch a submodule from the remote repository.
 ///
 /// # Examples
 /// ```ignore
-///     let result = mgmt::git::fetch_submodule("repos/terrain").unwrap();
-///     assert_eq!(result, true);
+///     mgmt::git::fetch_submodule("repos/terrain")?;
 /// ```
-pub fn fetch_submodule(submodule_path: &str) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn fetch_submodule(submodule_path: &str) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args([
             "submodule",
             "update",
@@ -98,47 +112,409 @@ pub fn fetch_submodule(submodule_path: &str) -> Result<bool> {
             "--recursive",
             submodule_path,
         ])
-        .status()
-        .context("error fetching submodule")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
-pub fn pull(repodir: &PathBuf) -> Result<bool> {
-    Ok(Command::new("git")
+pub fn pull(repodir: &PathBuf) -> Result<()> {
+    Command::new(cmd::program("git"))
         .args(["pull"])
         .current_dir(repodir)
-        .status()
-        .context("git pull failed")?
-        .success())
+        .checked_output()?;
+    Ok(())
+}
+
+/// Uses git to clone a repository from the remote repository. If
+/// `recurse_submodules` is true, the repository's submodules are cloned too.
+/// Fails with git's error output if the clone fails.
+///
+/// # Examples
+/// ```ignore
+///    mgmt::git::clone("https://github.com/cyverse-de/terrain", "repos/terrain", false)?;
+/// ```
+pub fn clone(url: &str, path: &str, recurse_submodules: bool) -> Result<()> {
+    let mut args = vec!["clone"];
+    if recurse_submodules {
+        args.push("--recurse-submodules");
+    }
+    args.extend([url, path]);
+
+    Command::new(cmd::program("git"))
+        .args(args)
+        .checked_output()?;
+    Ok(())
+}
+
+/// Uses git to clone a repository with a branch or tag checked out, instead of
+/// the remote's default branch. Fails with git's error output if the clone
+/// fails, such as when the reference doesn't exist, and removes anything the
+/// failed clone left behind.
+///
+/// # Examples
+/// ```ignore
+///    mgmt::git::clone_ref("https://github.com/cyverse-de/terrain", "repos/terrain", "v3.1.0", false)?;
+/// ```
+pub fn clone_ref(url: &str, path: &str, reference: &str, recurse_submodules: bool) -> Result<()> {
+    clone_quietly(
+        url,
+        path,
+        Some(reference),
+        recurse_submodules,
+        None,
+        &cmd::Cancel::default(),
+    )
 }
 
-/// Uses git to clone a repository from the remote repository.
+/// Uses git to clone only the last `depth` commits of a repository's default
+/// branch, which is much faster than a full clone for repos with a long
+/// history. Operations that need the full history can call `unshallow` first.
 ///
 /// # Examples
 /// ```ignore
-///    let result = mgmt::git::clone("https://github.com/cyverse-de/terrain", "repos/terrain").unwrap();
+///    mgmt::git::clone_shallow("https://github.com/cyverse-de/terrain", "repos/terrain", 1)?;
 /// ```
-pub fn clone(url: &str, path: &str) -> Result<bool> {
-    Ok(Command::new("git")
-        .args(["clone", url, path])
-        .status()
-        .context("error cloning repository")?
-        .success())
+pub fn clone_shallow(url: &str, path: &str, depth: u32) -> Result<()> {
+    clone_quietly(url, path, None, false, Some(depth), &cmd::Cancel::default())
+}
+
+/// Like `clone_ref`, but clones the remote's default branch if no reference
+/// is given, and only the last `depth` commits if a depth is given. Git's
+/// output is captured instead of going to the terminal, so several repos can
+/// be cloned at once without their progress getting mixed together. Git is
+/// killed if `cancel` is canceled before the clone finishes.
+///
+/// # Examples
+/// ```ignore
+///    mgmt::git::clone_quietly("https://github.com/cyverse-de/terrain", "repos/terrain", None, false, None, &cmd::Cancel::default())?;
+/// ```
+pub fn clone_quietly(
+    url: &str,
+    path: &str,
+    reference: Option<&str>,
+    recurse_submodules: bool,
+    depth: Option<u32>,
+    cancel: &cmd::Cancel,
+) -> Result<()> {
+    let existed = Path::new(path).exists();
+
+    let mut args = vec!["clone".to_string()];
+    if let Some(reference) = reference {
+        args.extend(["--branch".to_string(), reference.to_string()]);
+    }
+    if let Some(depth) = depth {
+        args.push(format!("--depth={}", depth));
+    }
+    if recurse_submodules {
+        args.push("--recurse-submodules".to_string());
+        if depth.is_some() {
+            args.push("--shallow-submodules".to_string());
+        }
+    }
+    args.extend(["--".to_string(), url.to_string(), path.to_string()]);
+
+    let output = Command::new(cmd::program("git"))
+        .args(args)
+        .logged()
+        .output_unless_canceled(cancel)
+        .with_context(|| exit::Error::ExternalTool("error cloning repository".to_string()))?;
+
+    if !output.status.success() {
+        if !existed && Path::new(path).exists() {
+            let _ = std::fs::remove_dir_all(path);
+        }
+        let at = reference.map(|r| format!(" at {}", r)).unwrap_or_default();
+        return Err(exit::Error::ExternalTool(format!(
+            "failed to clone {}{}: {}",
+            url,
+            at,
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Returns whether a repository is a shallow clone, which is missing some of
+/// its history.
+///
+/// # Examples
+/// ```ignore
+///     let shallow = mgmt::git::is_shallow(&PathBuf::from("repos/terrain"))?;
+/// ```
+pub fn is_shallow(repodir: &PathBuf) -> Result<bool> {
+    let output = Command::new(cmd::program("git"))
+        .args(["rev-parse", "--is-shallow-repository"])
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git rev-parse failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "git rev-parse failed in {}: {}",
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+/// Fetches the rest of the history of a shallow clone, along with its tags.
+///
+/// # Examples
+/// ```ignore
+///     mgmt::git::unshallow(&PathBuf::from("de-releases"))?;
+/// ```
+pub fn unshallow(repodir: &PathBuf) -> Result<()> {
+    let output = Command::new(cmd::program("git"))
+        .args(["fetch", "--unshallow", "--tags"])
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git fetch --unshallow failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "failed to fetch the full history of {}: {}",
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Initializes and updates all of a repository's submodules, including
+/// nested ones, to the commits recorded in the repository.
+///
+/// # Examples
+/// ```ignore
+///    mgmt::git::update_submodules(&PathBuf::from("repos/terrain"))?;
+/// ```
+pub fn update_submodules(repodir: &PathBuf) -> Result<()> {
+    Command::new(cmd::program("git"))
+        .args(["submodule", "update", "--init", "--recursive"])
+        .current_dir(repodir)
+        .checked_output()?;
+    Ok(())
+}
+
+/// Returns the URL of the origin remote for a repository.
+///
+/// # Examples
+/// ```ignore
+///     let url = mgmt::git::remote_url(&PathBuf::from("repos/terrain"))?;
+/// ```
+pub fn remote_url(repodir: &PathBuf) -> Result<String> {
+    let output = Command::new(cmd::program("git"))
+        .args(["remote", "get-url", "origin"])
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git remote get-url failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "git remote get-url failed in {}: {}",
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the full hash of the commit a revision, such as a branch, a tag,
+/// or `HEAD`, points to in a repository.
+///
+/// # Examples
+/// ```ignore
+///     let commit = mgmt::git::rev_parse(&PathBuf::from("repos/terrain"), "v1.2.0")?;
+/// ```
+pub fn rev_parse(repodir: &PathBuf, rev: &str) -> Result<String> {
+    let output = Command::new(cmd::program("git"))
+        .args(["rev-parse", "--verify", "--end-of-options"])
+        .arg(format!("{}^{{commit}}", rev))
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git rev-parse failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "git rev-parse {} failed in {}: {}",
+            rev,
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns the hash of the commit currently checked out in a repository.
+///
+/// # Examples
+/// ```ignore
+///     let commit = mgmt::git::head_commit(&PathBuf::from("repos/terrain"))?;
+/// ```
+pub fn head_commit(repodir: &PathBuf) -> Result<String> {
+    rev_parse(repodir, "HEAD")
+}
+
+// The shortest abbreviated hash accepted as an expected commit, which is
+// what git itself abbreviates to by default.
+const MIN_COMMIT_LEN: usize = 7;
+
+// Returns whether a full commit hash matches an expected one, which may be
+// abbreviated.
+fn commit_matches(actual: &str, expected: &str) -> bool {
+    let expected = expected.trim().to_lowercase();
+    expected.len() >= MIN_COMMIT_LEN
+        && expected.chars().all(|c| c.is_ascii_hexdigit())
+        && actual.to_lowercase().starts_with(&expected)
+}
+
+/// Checks that the commit checked out in a repository is the expected one,
+/// which may be a full or an abbreviated hash. Fails with an error naming
+/// both commits if it isn't, and with a usage error if the expected commit
+/// isn't a hash of at least 7 characters.
+///
+/// # Examples
+/// ```ignore
+///     mgmt::git::verify_head(&PathBuf::from("repos/terrain"), "3f2c1a9")?;
+/// ```
+pub fn verify_head(repodir: &PathBuf, expected: &str) -> Result<()> {
+    let expected = expected.trim();
+    if expected.len() < MIN_COMMIT_LEN || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(exit::Error::Usage(format!(
+            "the expected commit for {} is '{}', which isn't a commit hash of at least {} characters",
+            repodir.display(),
+            expected,
+            MIN_COMMIT_LEN
+        ))
+        .into());
+    }
+
+    let actual = head_commit(repodir)?;
+    if !commit_matches(&actual, expected) {
+        return Err(exit::Error::Drift(format!(
+            "{} is checked out at {}, but the database expects {}",
+            repodir.display(),
+            actual,
+            expected
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// A commit as reported by `git log`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub author: String,
+    pub date: String,
+    pub subject: String,
+}
+
+// The fields of each commit are separated by the unit separator character,
+// which doesn't show up in names or subjects.
+const LOG_FORMAT: &str = "--format=%h%x1f%an%x1f%ad%x1f%s";
+
+// Parses the output of `git log` run with LOG_FORMAT.
+fn parse_log(output: &str) -> Vec<CommitInfo> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\u{1f}');
+            Some(CommitInfo {
+                hash: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the commits that are reachable from `to` but not from `from`,
+/// newest first, the same as `git log from..to`. Both can be any revision git
+/// understands, such as tags or commit hashes.
+///
+/// # Examples
+/// ```ignore
+///     let commits = mgmt::git::log_between(&PathBuf::from("repos/terrain"), "v1.0.0", "v1.1.0")?;
+/// ```
+pub fn log_between(repodir: &PathBuf, from: &str, to: &str) -> Result<Vec<CommitInfo>> {
+    let output = Command::new(cmd::program("git"))
+        .args([
+            "log",
+            LOG_FORMAT,
+            "--date=short",
+            &format!("{}..{}", from, to),
+        ])
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git log failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "git log {}..{} failed in {}: {}",
+            from,
+            to,
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(parse_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Returns the value of a git setting, such as `user.name`, or `None` if it
+/// isn't set.
+///
+/// # Examples
+/// ```ignore
+///     let name = mgmt::git::config_value("user.name")?;
+/// ```
+pub fn config_value(key: &str) -> Result<Option<String>> {
+    let output = Command::new(cmd::program("git"))
+        .args(["config", "--get", key])
+        .logged()
+        .output()
+        .with_context(|| exit::Error::ExternalTool("git config failed".to_string()))?;
+
+    // git config exits with 1 when the setting isn't there.
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || value.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(value))
 }
 
 /// Uses git to update a submodule from the remote repository.
 ///
 /// # Examples
 /// ```ignore
-///   let result = mgmt::git::update_submodule("repos/terrain").unwrap();
+///   mgmt::git::update_submodule("repos/terrain")?;
 /// ```
-pub fn update_submodule(submodule_path: &str) -> Result<bool> {
+pub fn update_submodule(submodule_path: &str) -> Result<()> {
     fetch_submodule(submodule_path)?;
-    Ok(Command::new("git")
+    Command::new(cmd::program("git"))
         .args(["add", submodule_path])
-        .status()
-        .context("error updating submodule")?
-        .success())
+        .checked_output()?;
+    Ok(())
 }
 
 /// Uses git to look for changes to the remote repository.
@@ -149,10 +525,10 @@ pub fn update_submodule(submodule_path: &str) -> Result<bool> {
 ///     assert_eq!(result, true);
 /// ```
 fn staged_changes(repodir: &PathBuf) -> Result<bool> {
-    let output = Command::new("git")
+    let output = Command::new(cmd::program("git"))
         .arg("status")
         .current_dir(repodir)
-        .output()?;
+        .checked_output()?;
 
     let found = String::from_utf8(output.stdout)
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
@@ -161,6 +537,38 @@ fn staged_changes(repodir: &PathBuf) -> Result<bool> {
     Ok(!found)
 }
 
+/// Returns the paths under `paths` that have uncommitted changes, including
+/// untracked files, as listed by `git status --porcelain`.
+///
+/// # Examples
+/// ```ignore
+///     let changed = mgmt::git::uncommitted_changes(&PathBuf::from("de-releases"), &["builds"])?;
+/// ```
+pub fn uncommitted_changes(repodir: &PathBuf, paths: &[&str]) -> Result<Vec<String>> {
+    let output = Command::new(cmd::program("git"))
+        .args(["status", "--porcelain", "--untracked-files=all", "--"])
+        .args(paths)
+        .current_dir(repodir)
+        .logged()
+        .output()
+        .context("git status failed")?;
+
+    if !output.status.success() {
+        return Err(exit::Error::ExternalTool(format!(
+            "git status failed in {}: {}",
+            repodir.display(),
+            cmd::stderr_excerpt(&output.stderr)
+        ))
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| path.to_string())
+        .collect())
+}
+
 /// Uses git to add and commit changes to the remote repository.
 ///
 /// # Examples
@@ -174,8 +582,133 @@ pub fn check_in_changes(project_path: &str) -> Result<bool> {
     add(&curr_dir, "builds")?;
     if staged_changes(&curr_dir)? {
         let msg = format!("update builds for the {} project", &project_path);
-        commit(&curr_dir, &msg)?;
+        commit(&curr_dir, &msg, None)?;
     };
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let output = "abc1234\u{1f}Jane Doe\u{1f}2024-01-02\u{1f}Fix the thing\n\
+                      def5678\u{1f}John Roe\u{1f}2024-01-01\u{1f}Add a: feature\n";
+        let commits = parse_log(output);
+        assert_eq!(commits.len(), 2);
+        assert_eq!(
+            commits[0],
+            CommitInfo {
+                hash: "abc1234".to_string(),
+                author: "Jane Doe".to_string(),
+                date: "2024-01-02".to_string(),
+                subject: "Fix the thing".to_string(),
+            }
+        );
+        assert_eq!(commits[1].subject, "Add a: feature");
+        assert!(parse_log("").is_empty());
+    }
+
+    #[test]
+    fn test_commit_matches() {
+        let full = "3f2c1a9d8e7b6a5f4e3d2c1b0a9f8e7d6c5b4a39";
+        assert!(commit_matches(full, full));
+        assert!(commit_matches(full, "3F2C1A9"));
+        assert!(commit_matches(full, " 3f2c1a9d "));
+        assert!(!commit_matches(full, "3f2c1a"));
+        assert!(!commit_matches(full, "3f2c1a8"));
+        assert!(!commit_matches(full, "main"));
+    }
+
+    #[test]
+    fn test_clone_shallow() {
+        let dir = std::env::temp_dir().join(format!("mgmt-git-test-{}", std::process::id()));
+        let origin = dir.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+
+        let git = |args: &[&str]| {
+            let status = Command::new(cmd::program("git"))
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.org"])
+                .args(args)
+                .current_dir(&origin)
+                .logged()
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "--quiet"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "first"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "second"]);
+
+        // Local clones ignore --depth unless they go through a URL.
+        let url = format!("file://{}", origin.display());
+        let shallow = dir.join("shallow");
+        clone_shallow(&url, &shallow.to_string_lossy(), 1).unwrap();
+        assert!(is_shallow(&shallow).unwrap());
+        assert!(!is_shallow(&origin).unwrap());
+
+        unshallow(&shallow).unwrap();
+        assert!(!is_shallow(&shallow).unwrap());
+
+        // The clone is at the same commit as the fixture repo.
+        let head = rev_parse(&origin, "HEAD").unwrap();
+        assert_eq!(head.len(), 40);
+        assert_eq!(head_commit(&shallow).unwrap(), head);
+        verify_head(&shallow, &head).unwrap();
+        verify_head(&shallow, &head[..7].to_uppercase()).unwrap();
+
+        let first = rev_parse(&origin, "HEAD~1").unwrap();
+        let err = verify_head(&shallow, &first).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<exit::Error>(),
+            Some(exit::Error::Drift(_))
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} is checked out at {}, but the database expects {}",
+                shallow.display(),
+                head,
+                first
+            )
+        );
+        assert!(verify_head(&shallow, "abc").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_clone_bogus_url() {
+        let dir = std::env::temp_dir().join(format!("mgmt-git-bogus-{}", std::process::id()));
+        let url = format!("file://{}", dir.join("missing").display());
+        let path = dir.join("clone").to_string_lossy().to_string();
+
+        let err = clone(&url, &path, false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<exit::Error>(),
+            Some(exit::Error::ExternalTool(_))
+        ));
+        let message = err.to_string();
+        assert!(message.contains("git clone"), "{}", message);
+        assert!(message.contains("fatal:"), "{}", message);
+
+        let message = clone_quietly(&url, &path, None, false, None, &cmd::Cancel::default())
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("fatal:"), "{}", message);
+
+        // Outside of a repo, git complains about that instead.
+        std::fs::create_dir_all(&dir).unwrap();
+        let err = checkout(&dir, "main").unwrap_err();
+        assert!(
+            err.to_string().contains("fatal: not a git repository"),
+            "{}",
+            err
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
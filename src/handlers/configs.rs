@@ -1,5 +1,5 @@
 use crate::{
-    config_values::config::{self, ConfigValues},
+    config_values::{config, format},
     db, ops,
 };
 use anyhow::{anyhow, Result};
@@ -90,6 +90,22 @@ async fn defaults_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()
     Ok(())
 }
 
+async fn defaults_secret(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let section = sub_m.get_one::<String>("section").ok_or_else(|| {
+        anyhow!("No section specified. Use --section <section> to specify a section.")
+    })?;
+
+    let key = sub_m
+        .get_one::<String>("key")
+        .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
+
+    let secret = !sub_m.get_flag("not-secret");
+
+    ops::set_default_secret(&pool, &section, &key, secret).await?;
+
+    Ok(())
+}
+
 async fn defaults_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let section = match sub_m.get_one::<String>("section") {
         Some(section) => Some(section.as_str()),
@@ -108,7 +124,13 @@ async fn defaults_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
 
 async fn defaults_render(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let output_file = sub_m.get_one::<PathBuf>("file").cloned();
-    ops::render_default_values(&pool, output_file).await?;
+    let format = format::get_format(sub_m)?;
+    let always_write = sub_m.get_flag("always-write");
+    if !ops::render_default_values(&pool, output_file.clone(), format, always_write).await? {
+        if let Some(file) = output_file {
+            println!("{} is unchanged", file.display());
+        }
+    }
 
     Ok(())
 }
@@ -123,6 +145,7 @@ pub async fn defaults(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         ("get", sub_m) => defaults_get(&pool, &sub_m).await,
         ("delete", sub_m) => defaults_delete(&pool, &sub_m).await,
         ("list", sub_m) => defaults_list(&pool, &sub_m).await,
+        ("secret", sub_m) => defaults_secret(&pool, &sub_m).await,
         ("render", sub_m) => defaults_render(&pool, &sub_m).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
@@ -151,7 +174,21 @@ async fn values_set(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         .get_one::<String>("type")
         .ok_or_else(|| anyhow!("No type specified. Use --type <type> to specify a type."))?;
 
-    ops::set_value(&pool, &environment, &section, &key, &value, &value_type).await?;
+    let actor = sub_m
+        .get_one::<String>("actor")
+        .cloned()
+        .unwrap_or_else(db::default_actor);
+
+    ops::set_value(
+        &pool,
+        &environment,
+        &section,
+        &key,
+        &value,
+        &value_type,
+        &actor,
+    )
+    .await?;
 
     Ok(())
 }
@@ -230,7 +267,41 @@ async fn values_render(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
     };
 
     let opts = config::SectionOptions::new_from_db(&pool, &environment).await?;
-    ops::render_values(&pool, &environment, &opts, output_file).await?;
+    let format = format::get_format(sub_m)?;
+    let always_write = sub_m.get_flag("always-write");
+    let redact_secrets = sub_m.get_flag("redact-secrets");
+    if let Some(output_dir) = sub_m.get_one::<PathBuf>("split-sections") {
+        if !ops::render_values_split(
+            &pool,
+            &environment,
+            &opts,
+            output_dir,
+            format,
+            always_write,
+            redact_secrets,
+        )
+        .await?
+        {
+            println!("{} is unchanged", output_dir.display());
+        }
+        return Ok(());
+    }
+
+    if !ops::render_values(
+        &pool,
+        &environment,
+        &opts,
+        output_file.clone(),
+        format,
+        always_write,
+        redact_secrets,
+    )
+    .await?
+    {
+        if let Some(file) = output_file {
+            println!("{} is unchanged", file.display());
+        }
+    }
 
     Ok(())
 }
@@ -246,25 +317,9 @@ async fn values_import(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
         )
     })?;
 
-    ops::import_yaml_file(&pool, path.to_path_buf(), environment).await?;
-
-    println!(
-        "Imported values from {} for the {} environment.",
-        path.display(),
-        environment
-    );
+    let dry_run = sub_m.get_flag("dry-run");
 
-    // Now get all of the newly import values from the database.
-    let mut tx = pool.begin().await?;
-    let imported_cfgs: ConfigValues =
-        db::list_config_values(&mut tx, Some(&environment), None, None)
-            .await?
-            .into();
-    let new_ops = imported_cfgs.generate_section_options();
-    db::upsert_feature_flags(&mut tx, &environment, &new_ops.into()).await?;
-    tx.commit().await?;
-
-    println!("Set up feature flags for the {} environment.", environment);
+    ops::import_yaml_file(&pool, path.to_path_buf(), environment, dry_run).await?;
 
     Ok(())
 }
@@ -284,3 +339,19 @@ pub async fn values(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
+
+pub async fn backup(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let path = sub_m
+        .get_one::<PathBuf>("out")
+        .ok_or_else(|| anyhow!("No file specified. Use --out <file> to specify a file."))?;
+
+    ops::export_all(pool, path).await
+}
+
+pub async fn restore(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let path = sub_m
+        .get_one::<PathBuf>("file")
+        .ok_or_else(|| anyhow!("No file specified. Use --file <file> to specify a file."))?;
+
+    ops::import_all(pool, path).await
+}
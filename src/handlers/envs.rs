@@ -1,4 +1,4 @@
-use crate::{db, ops};
+use crate::{commit, db, dolt, exit, ops, output};
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
 use sqlx::{Pool, Postgres, Transaction};
@@ -65,27 +65,221 @@ async fn env_create(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-async fn env_list(pool: &Pool<Postgres>) -> Result<()> {
+#[derive(tabled::Tabled, serde::Serialize)]
+struct EnvRow {
+    name: String,
+}
+
+async fn env_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let format = output::get_format(sub_m)?;
+    let max_width = output::get_max_width(sub_m);
+
     let mut tx = pool.begin().await?;
     let envs = db::list_envs(&mut tx).await?;
     tx.commit().await?;
-    for env in envs {
-        println!("{}", env);
+
+    let rows = envs
+        .into_iter()
+        .map(|name| EnvRow { name })
+        .collect::<Vec<_>>();
+    output::print(&rows, format, max_width)?;
+
+    Ok(())
+}
+
+async fn env_show(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let env = sub_m.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment specified. Use --env <env> to specify an environment.")
+    })?;
+    let format = output::get_format(sub_m)?;
+    let max_width = output::get_max_width(sub_m);
+    let wide = sub_m.get_flag("wide");
+
+    let mut tx = pool.begin().await?;
+    ops::require_environment(&mut tx, env).await?;
+    if wide {
+        let cfgs = db::list_config_values_provenance(&mut tx, env).await?;
+        tx.commit().await?;
+        output::print(&cfgs, format, max_width)?;
+    } else {
+        let cfgs = db::list_config_values(&mut tx, Some(env), None, None).await?;
+        tx.commit().await?;
+        output::print(&cfgs, format, max_width)?;
+    }
+
+    Ok(())
+}
+
+async fn env_history(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let env = sub_m.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment specified. Use --env <env> to specify an environment.")
+    })?;
+    let limit = *sub_m
+        .get_one::<i64>("limit")
+        .ok_or_else(|| anyhow!("No limit specified. Use --limit <n> to specify a limit."))?;
+    let offset = *sub_m
+        .get_one::<i64>("offset")
+        .ok_or_else(|| anyhow!("No offset specified. Use --offset <n> to specify an offset."))?;
+
+    let mut tx = pool.begin().await?;
+    let environment = ops::require_environment(&mut tx, env).await?;
+    let commits = db::config_history(&mut tx, environment.id, limit, offset).await?;
+    tx.commit().await?;
+
+    if sub_m.get_flag("oneline") {
+        for commit in &commits {
+            println!("{}", oneline(commit));
+        }
+    } else {
+        output::print(
+            &commits,
+            output::get_format(sub_m)?,
+            output::get_max_width(sub_m),
+        )?;
     }
 
     Ok(())
 }
 
+// Formats a commit as its abbreviated hash and the first line of its message,
+// like `git log --oneline`.
+fn oneline(commit: &db::ConfigCommit) -> String {
+    let hash = commit.commit_hash.get(..8).unwrap_or(&commit.commit_hash);
+    let subject = commit.message.lines().next().unwrap_or_default();
+    format!("{} {}", hash, subject)
+}
+
 async fn env_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let env = sub_m
         .get_one::<String>("env")
         .ok_or_else(|| anyhow!("No name specified. Use --env <env> to specify a name."))?;
 
     let mut tx = pool.begin().await?;
-    db::delete_env(&mut tx, &env).await?;
+    ops::require_environment(&mut tx, &env).await?;
+    let unlinked = db::delete_environment(&mut tx, &env).await?;
+    tx.commit().await?;
+
+    println!(
+        "Deleted environment: {} ({} config value(s) unlinked)",
+        env, unlinked
+    );
+
+    Ok(())
+}
+
+async fn env_rename(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let env = sub_m.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment specified. Use --env <env> to specify an environment.")
+    })?;
+    let to = sub_m
+        .get_one::<String>("to")
+        .ok_or_else(|| anyhow!("No new name specified. Use --to <name> to specify a name."))?;
+    let author = commit::get_author(sub_m)?;
+    let template = sub_m.get_one::<String>("message").ok_or_else(|| {
+        anyhow!("No commit message specified. Use --message <message> to specify a message.")
+    })?;
+
+    let mut tx = pool.begin().await?;
+    ops::require_environment(&mut tx, env).await?;
+    if db::get_environment(&mut tx, to).await?.is_some() {
+        return Err(exit::Error::Usage(format!(
+            "Environment {} already exists. Delete it or pick another name.",
+            to
+        ))
+        .into());
+    }
+    db::rename_environment(&mut tx, env, to).await?;
     tx.commit().await?;
+    println!("Renamed environment {} to {}", env, to);
 
-    println!("Deleted environment: {}", env);
+    let message = commit::render_message(template, env, "").replace("{to}", to);
+    dolt::commit(pool, &message, author.as_ref()).await?;
+    println!("Committed the changes to the database.");
+
+    Ok(())
+}
+
+async fn env_key_move(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let key = sub_m
+        .get_one::<String>("key")
+        .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
+    let from = sub_m.get_one::<String>("from").ok_or_else(|| {
+        anyhow!("No section specified. Use --from <section> to specify the section to move from.")
+    })?;
+    let to = sub_m.get_one::<String>("to").ok_or_else(|| {
+        anyhow!("No section specified. Use --to <section> to specify the section to move to.")
+    })?;
+    let author = commit::get_author(sub_m)?;
+    let template = sub_m.get_one::<String>("message").ok_or_else(|| {
+        anyhow!("No commit message specified. Use --message <message> to specify a message.")
+    })?;
+
+    let mut tx = pool.begin().await?;
+    let moved = db::move_config_value(&mut tx, key, from, to).await?;
+    tx.commit().await?;
+
+    if moved == 0 {
+        println!("There are no values for {}.{} to move.", from, key);
+        return Ok(());
+    }
+    println!(
+        "Moved {} value(s) from {}.{} to {}.{}",
+        moved, from, key, to, key
+    );
+
+    let message = commit::render_message(template, "", "")
+        .replace("{key}", key)
+        .replace("{from}", from)
+        .replace("{to}", to);
+    dolt::commit(pool, &message, author.as_ref()).await?;
+    println!("Committed the changes to the database.");
+
+    Ok(())
+}
+
+async fn env_key_handler(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let key_cmd = sub_m
+        .subcommand()
+        .ok_or_else(|| anyhow::anyhow!("bad command"))?;
+
+    match key_cmd {
+        ("move", sub_m) => env_key_move(&pool, &sub_m).await,
+        (name, _) => unreachable!("Bad subcommand: {name}"),
+    }
+}
+
+async fn env_gc(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let dry_run = sub_m.get_flag("dry-run");
+    let author = commit::get_author(sub_m)?;
+    let template = sub_m.get_one::<String>("message").ok_or_else(|| {
+        anyhow!("No commit message specified. Use --message <message> to specify a message.")
+    })?;
+
+    let mut tx = pool.begin().await?;
+    let orphans = db::find_orphaned_config_values(&mut tx).await?;
+
+    if dry_run {
+        tx.commit().await?;
+        for (id, section, key) in &orphans {
+            println!("{}: {}.{}", id, section, key);
+        }
+        println!(
+            "{} orphaned config value(s) would be deleted.",
+            orphans.len()
+        );
+        return Ok(());
+    }
+
+    let deleted = db::delete_orphaned_config_values(&mut tx).await?;
+    tx.commit().await?;
+    println!("Deleted {} orphaned config value(s).", deleted);
+
+    if deleted > 0 {
+        let message =
+            commit::render_message(template, "", "").replace("{count}", &deleted.to_string());
+        dolt::commit(pool, &message, author.as_ref()).await?;
+        println!("Committed the changes to the database.");
+    }
 
     Ok(())
 }
@@ -130,17 +324,59 @@ async fn env_services_remove(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Resul
     Ok(())
 }
 
+async fn env_services_set_enabled(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    enabled: bool,
+) -> Result<()> {
+    let env = sub_m.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment specified. Use --env <env> to specify an environment.")
+    })?;
+
+    let services = sub_m
+        .get_many::<String>("service")
+        .unwrap_or_default()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+
+    let mut tx = pool.begin().await?;
+    let known = db::get_services(&mut tx, &env)
+        .await?
+        .into_iter()
+        .map(|svc| svc.name)
+        .collect::<Vec<_>>();
+    ops::validate_service_names("--service", &services, &known)?;
+
+    let env_id = db::get_env_id(&mut tx, &env).await?;
+    for svc in &services {
+        db::set_service_enabled(&mut tx, env_id, svc, enabled).await?;
+        println!(
+            "{} {} in {}",
+            if enabled { "Enabled" } else { "Disabled" },
+            svc,
+            env
+        );
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
 async fn env_services_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let env = sub_m.get_one::<String>("env").ok_or_else(|| {
         anyhow!("No environment specified. Use --env <env> to specify an environment.")
     })?;
 
     let mut tx = pool.begin().await?;
-    let services = db::get_services(&mut tx, &env).await?;
+    let services = db::get_services_for_env(&mut tx, &env).await?;
     tx.commit().await?;
 
     for svc in services {
-        println!("{}", svc.name);
+        if svc.enabled {
+            println!("{}", svc.name);
+        } else {
+            println!("{} (disabled)", svc.name);
+        }
     }
 
     Ok(())
@@ -154,6 +390,8 @@ async fn env_services_handler(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Resu
     match services_cmd {
         ("add", sub_m) => env_services_add(&pool, &sub_m).await,
         ("delete", sub_m) => env_services_remove(&pool, &sub_m).await,
+        ("enable", sub_m) => env_services_set_enabled(&pool, &sub_m, true).await,
+        ("disable", sub_m) => env_services_set_enabled(&pool, &sub_m, false).await,
         ("list", sub_m) => env_services_list(&pool, &sub_m).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
@@ -219,9 +457,14 @@ pub async fn env(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     match create_cmd {
         ("populate", _) => Ok(ops::populate_env(&pool, "de").await?),
         ("create", sub_m) => env_create(&pool, &sub_m).await,
-        ("list", _) => env_list(&pool).await,
+        ("list", sub_m) => env_list(&pool, &sub_m).await,
+        ("show", sub_m) => env_show(&pool, &sub_m).await,
         ("delete", sub_m) => env_delete(&pool, &sub_m).await,
+        ("rename", sub_m) => env_rename(&pool, &sub_m).await,
+        ("history", sub_m) => env_history(&pool, &sub_m).await,
+        ("gc", sub_m) => env_gc(&pool, &sub_m).await,
         ("service", sub_m) => env_services_handler(&pool, &sub_m).await,
+        ("key", sub_m) => env_key_handler(&pool, &sub_m).await,
         ("feature-flags", sub_m) => env_feature_flags_handler(&pool, &sub_m).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
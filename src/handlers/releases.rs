@@ -1,10 +1,17 @@
-use crate::{db, deploy, git, ops};
+use crate::changelog::{Changelog, ServiceChange, CHANGELOG_FILENAME};
+#[cfg(feature = "kubernetes")]
+use crate::deploy;
+use crate::{commit, db, exit, git, ops, output, versions};
 use anyhow::{anyhow, Context, Result};
 use clap::ArgMatches;
 use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "kubernetes")]
+use std::time::Duration;
 use tar::Archive;
 use thiserror::Error;
 use url::Url;
@@ -18,6 +25,64 @@ enum VersionTagError {
     ParseError(String),
 }
 
+/// The name of the file in the releases repository that lists the services
+/// included in a release, grouped by environment.
+const MANIFEST_FILENAME: &str = "manifest.yaml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReleaseManifest {
+    environments: BTreeMap<String, Vec<ManifestService>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestService {
+    name: String,
+
+    // Path to the build file, relative to the releases repository.
+    build: PathBuf,
+
+    // The tag of the service's GitHub release. Manifests written before the
+    // version was recorded don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+
+impl ReleaseManifest {
+    // Reads the manifest left in the releases repository by the previous
+    // release, if there is one.
+    fn read(repo_dir: &Path) -> Result<Option<Self>> {
+        let path = repo_dir.join(MANIFEST_FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(Some(serde_yaml::from_str(&contents).with_context(
+            || format!("failed to parse {}", path.display()),
+        )?))
+    }
+
+    // Returns the version of a service in an environment.
+    fn version_for(&self, env: &str, service: &str) -> Option<&str> {
+        self.environments
+            .get(env)?
+            .iter()
+            .find(|svc| svc.name == service)?
+            .version
+            .as_deref()
+    }
+}
+
+// A row in the output of the services subcommand.
+#[derive(tabled::Tabled, Serialize)]
+struct ServiceRow {
+    service: String,
+    repo: String,
+    revision: String,
+    pinned: bool,
+    skipped: bool,
+}
+
 /// Returns the latest release version from a list of tags.
 ///
 /// # Examples
@@ -61,27 +126,24 @@ fn latest_release_version(tags: &[String]) -> Result<semver::Version, VersionTag
 /// # Examples
 /// ```ignore
 /// let opts = ops::ReleaseOpts {
-///   env: "dev".to_string(),
+///   envs: vec!["dev".to_string()],
 ///   repo_name: "de-releases".to_string(),
 ///   repo_url: "https://github.com/cyverse-de/de-releases".to_string(),
 /// }
 ///
-/// let (repo_dir, builds_dir, services_dir) = ops::setup_release_dir(&opts).unwrap();
+/// let tuples = get_service_repos(&mut tx, &opts, "dev").await?;
 /// ```
 async fn get_service_repos(
     tx: &mut sqlx::Transaction<'_, Postgres>,
     opts: &ops::ReleaseOpts,
+    env: &str,
 ) -> Result<Vec<(db::Service, db::Repository)>> {
-    let services = db::get_services(tx, &opts.env)
-        .await?
-        .into_iter()
-        .filter(|s| {
-            !opts
-                .skips
-                .iter()
-                .any(|skipped_service| s.name.eq(skipped_service))
-        })
-        .collect::<Vec<_>>();
+    let services = ops::active_services(
+        db::get_services_for_env(tx, env).await?,
+        env,
+        &opts.skips,
+        opts.include_disabled,
+    );
 
     //// For each service, get the repository from the database.
     let mut tuples: Vec<(db::Service, db::Repository)> = Vec::new();
@@ -101,7 +163,7 @@ async fn get_service_repos(
 /// ```ignore
 /// let repo_url = mgmt::get_repo_url(&repo).unwrap();
 /// ```
-fn get_repo_url(repo: &db::Repository) -> Result<String> {
+pub(crate) fn get_repo_url(repo: &db::Repository) -> Result<String> {
     let mut repo_url = repo.url.clone();
 
     if !repo_url.ends_with("/") {
@@ -124,21 +186,64 @@ fn get_service_dir(services_dir: &PathBuf, service: &db::Service) -> Result<Path
     Ok(service_dir)
 }
 
-/// Downloads the deploy-info.tar.gz file from the latest release of the repository and unpacks it
+// Returns the release tag from a GitHub release page URL, which looks like
+// <repo>/releases/tag/<tag>.
+fn release_tag(url: &Url) -> Option<String> {
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    let i = segments.iter().position(|s| *s == "tag")?;
+    segments.get(i + 1).map(|tag| tag.to_string())
+}
+
+// Looks up the tag of a repository's latest release. GitHub redirects
+// <repo>/releases/latest to the page for the release's tag, so the redirect
+// isn't followed and the tag is read from where it points. Returns None if
+// there's no redirect to a release page, such as for a repo without releases.
+async fn latest_release_tag(repo_url: &Url) -> Result<Option<String>> {
+    let latest_url = repo_url.join("releases/latest")?;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let resp = client.get(latest_url.clone()).send().await?;
+    if !resp.status().is_redirection() {
+        return Ok(None);
+    }
+
+    Ok(resp
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| latest_url.join(location).ok())
+        .and_then(|location| release_tag(&location)))
+}
+
+/// Downloads the deploy-info.tar.gz file from a release of the repository and unpacks it
 /// into the service directory. Then moves the build.json file from the service directory into the
-/// builds directory.
+/// builds directory. The latest release is used unless a version is passed in. Returns the tag
+/// of the release that was used, if it could be determined.
 ///
 /// # Examples
 /// ```ignore
-/// mgmt::process_release_tarball(&repo_url, &service_name, &builds_dir, &service_dir).await?;
+/// mgmt::process_release_tarball(&repo_url, &service_name, None, &builds_dir, &service_dir).await?;
 /// ```
-async fn process_release_tarball(
+pub(crate) async fn process_release_tarball(
     repo_url: &str,
     service_name: &str,
+    version: Option<&str>,
     builds_dir: &PathBuf,
     service_dir: &PathBuf,
-) -> Result<()> {
-    let tarball_url = Url::parse(&repo_url)?.join("releases/latest/download/deploy-info.tar.gz")?;
+) -> Result<Option<String>> {
+    // The latest release's tag is looked up first and its tarball downloaded
+    // by tag, so that the tag that's recorded is the one that was deployed.
+    let repo_url = Url::parse(repo_url)?;
+    let tag = match version {
+        Some(version) => Some(version.to_string()),
+        None => latest_release_tag(&repo_url).await?,
+    };
+    let release_path = match &tag {
+        Some(tag) => format!("releases/download/{}/deploy-info.tar.gz", tag),
+        None => "releases/latest/download/deploy-info.tar.gz".to_string(),
+    };
+    let tarball_url = repo_url.join(&release_path)?;
     let tarball_url_str = tarball_url.as_str();
     let tarball_resp = reqwest::get(tarball_url.clone()).await?;
     let tarball_status = tarball_resp.status();
@@ -161,7 +266,7 @@ async fn process_release_tarball(
     println!("Moving {} to {}", build_json_path.display(), to.display());
     fs::rename(build_json_path, to)?;
 
-    Ok(())
+    Ok(tag)
 }
 
 /// Returns the new version number for the release.
@@ -204,6 +309,78 @@ fn get_new_version_number(repodir: &PathBuf, opts: &ops::ReleaseOpts) -> Result<
     Ok(new_version)
 }
 
+// Works out how a service changed since the previous release. The commits in
+// between are listed if the service's repository is cloned in the repos
+// directory.
+fn service_change(
+    repos_dir: &Path,
+    previous: Option<&ReleaseManifest>,
+    env: &str,
+    service: &str,
+    repo_name: &str,
+    to: &str,
+) -> ServiceChange {
+    let from = previous
+        .and_then(|manifest| manifest.version_for(env, service))
+        .map(|v| v.to_string());
+    let repo_dir = repos_dir.join(repo_name);
+
+    let commits = match &from {
+        Some(from)
+            if from != to && repo_dir.is_dir() && git::is_shallow(&repo_dir).unwrap_or(false) =>
+        {
+            println!(
+                "Not listing the commits for {}: {} is a shallow clone. Run 'git fetch --unshallow' in it to list them.",
+                service,
+                repo_dir.display()
+            );
+            None
+        }
+        Some(from) if from != to && repo_dir.is_dir() => {
+            match git::log_between(&repo_dir, from, to) {
+                Ok(commits) => Some(commits),
+                Err(e) => {
+                    println!("Unable to list the commits for {}: {}", service, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    ServiceChange {
+        service: service.to_string(),
+        from,
+        to: to.to_string(),
+        commits,
+    }
+}
+
+// Fails if the releases repository has uncommitted changes in the files a
+// release writes, which means a previous release stopped partway through,
+// unless --force was passed.
+fn check_partial_release(opts: &ops::ReleaseOpts) -> Result<()> {
+    let repo_dir = PathBuf::from(&opts.repo_name);
+    if opts.force || !repo_dir.join(".git").exists() {
+        return Ok(());
+    }
+
+    let changed = git::uncommitted_changes(
+        &repo_dir,
+        &["builds", "services", MANIFEST_FILENAME, CHANGELOG_FILENAME],
+    )?;
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    Err(exit::Error::Usage(format!(
+        "{} contains a partial release with uncommitted changes to: {}. Commit or discard them, or use --force to overwrite them.",
+        repo_dir.display(),
+        changed.join(", ")
+    ))
+    .into())
+}
+
 /// Creates a release in the releases repository.
 /// Clones the releases repository (default is 'de-releases') if no-clone is false.
 /// For each repository, grabs the build JSON file from the github release.
@@ -216,47 +393,131 @@ fn get_new_version_number(repodir: &PathBuf, opts: &ops::ReleaseOpts) -> Result<
 async fn create_release(pool: &Pool<Postgres>, opts: &ops::ReleaseOpts) -> Result<()> {
     let mut tx = pool.begin().await?;
 
+    // Catch typos in the skips before anything is cloned or downloaded.
+    let mut env_services = BTreeMap::new();
+    for env in &opts.envs {
+        let names = db::get_services(&mut tx, env)
+            .await?
+            .into_iter()
+            .map(|svc| svc.name)
+            .collect::<Vec<_>>();
+        env_services.insert(env.clone(), names);
+    }
+    ops::validate_skips(&opts.skips, &env_services)?;
+
+    // The same pins apply to every environment in the release.
+    let all_services = env_services.values().flatten().cloned().collect::<Vec<_>>();
+    let pins = versions::load(opts.versions.as_deref(), &all_services)?;
+
+    // Don't pull over or mix in what an earlier release left behind.
+    check_partial_release(opts)?;
+
     // Clone the releases repo (default is 'de-releases') if no-clone is false.
     println!("Setting up release directory...");
     let (repo_dir, builds_dir, services_dir) = ops::setup_release_dir(opts)?;
     println!("Done setting up release directory.");
 
-    // Get a list of the services included in the environment, filter out the skipped services:
-    println!("\nGetting service repositories...");
-    let tuples = get_service_repos(&mut tx, &opts).await?;
-    println!("Done getting service repositories.");
-
+    let multi_env = opts.envs.len() > 1;
+    let previous = ReleaseManifest::read(&repo_dir)?;
+    let mut manifest = ReleaseManifest::default();
+    let mut changelog = Changelog::default();
     let mut process_failures: Vec<String> = Vec::new();
 
-    println!("");
+    for env in &opts.envs {
+        // Releases for more than one environment keep each environment's
+        // files in their own subdirectories.
+        let (env_builds_dir, env_services_dir) = if multi_env {
+            let dirs = (builds_dir.join(env), services_dir.join(env));
+            fs::create_dir_all(&dirs.0)?;
+            fs::create_dir_all(&dirs.1)?;
+            dirs
+        } else {
+            (builds_dir.clone(), services_dir.clone())
+        };
 
-    ////// For each repository, grab the build JSON file from the github release.
-    for (service, repo) in tuples {
-        let repo_url = get_repo_url(&repo)?;
-        let service_dir = get_service_dir(&services_dir, &service)?;
-        let service_name = service.name;
+        // Get a list of the services included in the environment, filter out the skipped services:
+        println!("\nGetting service repositories for {}...", env);
+        let tuples = get_service_repos(&mut tx, opts, env).await?;
+        println!("Done getting service repositories.");
 
-        println!("Downloading release tarball for {}", service_name);
-        match process_release_tarball(&repo_url, &service_name, &builds_dir, &service_dir).await {
-            Ok(_) => {
-                println!("Processed release tarball for {}\n", service_name);
-            }
+        println!("");
 
-            Err(e) => {
-                let msg = format!(
-                    "Failed to process release tarball for {}: {}",
-                    service_name, e
-                );
-
-                println!("{}", msg);
+        let manifest_services = manifest.environments.entry(env.clone()).or_default();
 
-                process_failures.push(msg);
+        ////// For each repository, grab the build JSON file from the github release.
+        for (service, repo) in tuples {
+            let repo_url = get_repo_url(&repo)?;
+            let service_dir = get_service_dir(&env_services_dir, &service)?;
+            let service_name = service.name;
 
-                continue;
+            let version = pins.version_for(&service_name);
+            match version {
+                Some(version) => println!(
+                    "Downloading release tarball for {} {} (pinned)",
+                    service_name, version
+                ),
+                None => println!("Downloading release tarball for {}", service_name),
             }
-        };
+            match process_release_tarball(
+                &repo_url,
+                &service_name,
+                version,
+                &env_builds_dir,
+                &service_dir,
+            )
+            .await
+            {
+                Ok(tag) => {
+                    println!("Processed release tarball for {}\n", service_name);
+                    if let Some(tag) = &tag {
+                        changelog.add(
+                            env,
+                            service_change(
+                                &opts.repos_dir,
+                                previous.as_ref(),
+                                env,
+                                &service_name,
+                                &repo.name,
+                                tag,
+                            ),
+                        );
+                    }
+                    let build = env_builds_dir.join(format!("{}.json", service_name));
+                    manifest_services.push(ManifestService {
+                        name: service_name,
+                        build: build
+                            .strip_prefix(&repo_dir)
+                            .unwrap_or(&build)
+                            .to_path_buf(),
+                        version: tag,
+                    });
+                }
+
+                Err(e) => {
+                    let msg = format!(
+                        "Failed to process release tarball for {} in {}: {}",
+                        service_name, env, e
+                    );
+
+                    println!("{}", msg);
+
+                    process_failures.push(msg);
+
+                    continue;
+                }
+            };
+        }
     }
 
+    let manifest_path = repo_dir.join(MANIFEST_FILENAME);
+    fs::write(&manifest_path, serde_yaml::to_string(&manifest)?)?;
+    println!("Wrote release manifest to {}", manifest_path.display());
+
+    let changelog_path = repo_dir.join(CHANGELOG_FILENAME);
+    let title = format!("Changes for {}", opts.envs.join(", "));
+    fs::write(&changelog_path, changelog.render(&title))?;
+    println!("Wrote changelog to {}", changelog_path.display());
+
     if !process_failures.is_empty() {
         println!("\nThe following errors occurred while processing the release tarballs:");
         process_failures.iter().for_each(|failure| {
@@ -300,15 +561,39 @@ async fn create_release(pool: &Pool<Postgres>, opts: &ops::ReleaseOpts) -> Resul
         )?;
         println!("Done adding changes in the services directory.");
 
+        git::add(&repo_dir, MANIFEST_FILENAME)?;
+        git::add(&repo_dir, CHANGELOG_FILENAME)?;
+
         if !opts.no_tag {
+            // A tag pushed from a shallow clone can fail to reach the remote,
+            // so the full history is fetched first.
+            if git::is_shallow(&repo_dir)? {
+                println!(
+                    "\n{} is a shallow clone, fetching its full history...",
+                    repo_dir.display()
+                );
+                git::unshallow(&repo_dir)?;
+            }
+
             println!("\nAdding tag v{}...", latest_version);
             git::tag(&repo_dir, &format!("v{}", latest_version))?;
             println!("Done adding tag v{}.", latest_version);
         }
 
         if !opts.no_commit {
+            let services = manifest
+                .environments
+                .values()
+                .flatten()
+                .map(|svc| svc.name.as_str())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = commit::render_message(&opts.message, &opts.envs.join(", "), &services);
+
             println!("\nCommitting changes...");
-            git::commit(&repo_dir, "update builds")?;
+            git::commit(&repo_dir, &message, opts.author.as_ref())?;
             println!("Done committing changes.");
         }
 
@@ -333,9 +618,30 @@ async fn create_release(pool: &Pool<Postgres>, opts: &ops::ReleaseOpts) -> Resul
 }
 
 pub async fn create(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
-    let env = matches.get_one::<String>("env").ok_or_else(|| {
-        anyhow!("No environment provided. Use --env <env> to specify an environment.")
-    })?;
+    let mut envs = matches
+        .get_many::<String>("env")
+        .unwrap_or_default()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+
+    // Blank lines and lines starting with # are ignored in the env file.
+    if let Some(env_file) = matches.get_one::<PathBuf>("env-file") {
+        let contents = fs::read_to_string(env_file)
+            .with_context(|| format!("failed to read {}", env_file.display()))?;
+        envs.extend(
+            contents
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string()),
+        );
+    }
+
+    if envs.is_empty() {
+        return Err(anyhow!(
+            "No environment provided. Use --env <env> to specify an environment."
+        ));
+    }
 
     let repo_name = matches.get_one::<String>("repo-name").ok_or_else(|| {
         anyhow!("No repository provided. Use --repo-name <repo_name> to specify a repository.")
@@ -358,6 +664,7 @@ pub async fn create(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
     let no_push = matches.get_flag("no-push");
     let no_commit = matches.get_flag("no-commit");
     let no_tag = matches.get_flag("no-tag");
+    let force = matches.get_flag("force");
 
     let increment_field = matches.get_one::<String>("increment-field").ok_or_else(|| {
                 anyhow!("No increment field provided. Use --increment-field <field> to specify an increment field.")
@@ -369,8 +676,21 @@ pub async fn create(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
         .map(|s| s.to_string())
         .collect::<Vec<_>>();
 
+    let include_disabled = matches.get_flag("include-disabled");
+
+    let versions = versions::resolve_path(matches.get_one::<PathBuf>("versions"));
+
+    let repos_dir = matches.get_one::<PathBuf>("repos-dir").ok_or_else(|| {
+        anyhow!("No repos directory provided. Use --repos-dir <dir> to specify a directory.")
+    })?;
+
+    let author = commit::get_author(matches)?;
+    let message = matches.get_one::<String>("message").ok_or_else(|| {
+        anyhow!("No commit message provided. Use --message <message> to specify a message.")
+    })?;
+
     let opts = ops::ReleaseOpts {
-        env: env.to_string(),
+        envs,
         repo_name: repo_name.to_string(),
         repo_url: repo_url.to_string(),
         repo_branch: repo_branch.to_string(),
@@ -380,7 +700,13 @@ pub async fn create(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
         no_commit,
         no_tag,
         skips,
+        include_disabled,
         increment_field: increment_field.to_string(),
+        force,
+        versions,
+        repos_dir: repos_dir.clone(),
+        author,
+        message: message.to_string(),
     };
 
     create_release(&pool, &opts).await?;
@@ -388,6 +714,7 @@ pub async fn create(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "kubernetes")]
 pub async fn deploy(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
     let env = matches.get_one::<String>("env").ok_or_else(|| {
         anyhow!("No environment provided. Use --env <env> to specify an environment.")
@@ -430,6 +757,29 @@ pub async fn deploy(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
         .map(|s| s.to_string())
         .collect::<Vec<_>>();
 
+    let include_disabled = matches.get_flag("include-disabled");
+
+    let versions = versions::resolve_path(matches.get_one::<PathBuf>("versions"));
+
+    let concurrency = matches
+        .get_one::<u64>("concurrency")
+        .map(|n| *n as usize)
+        .ok_or_else(|| {
+            anyhow!("No concurrency provided. Use --concurrency <n> to specify how many services to deploy at once.")
+        })?;
+    let continue_on_error = matches.get_flag("continue-on-error");
+
+    let wait = matches.get_flag("wait");
+    let wait_timeout = matches
+        .get_one::<u64>("wait-timeout")
+        .map(|secs| Duration::from_secs(*secs))
+        .ok_or_else(|| {
+            anyhow!("No wait timeout provided. Use --wait-timeout <seconds> to specify a timeout.")
+        })?;
+
+    let manifest_out = matches.get_one::<PathBuf>("manifest-out").cloned();
+    let dry_run = matches.get_flag("dry-run");
+
     let opts = deploy::DeploymentOptions {
         pool: pool.clone(),
         repodir: repo_name.clone(),
@@ -437,15 +787,205 @@ pub async fn deploy(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
         branch: repo_branch.to_string(),
         env: env.to_string(),
         skips,
+        include_disabled,
         configdir: configdir.clone(),
         no_deploy,
         no_load_configs,
         no_load_secrets,
         no_render_configs,
         pre_deploy,
+        versions,
+        concurrency,
+        continue_on_error,
+        wait,
+        wait_timeout,
+        manifest_out,
+        dry_run,
     };
 
     deploy::deploy(pool, &env, repo_name, &repo_url, &repo_branch, &opts).await?;
 
     Ok(())
 }
+
+#[cfg(not(feature = "kubernetes"))]
+pub async fn deploy(_pool: &Pool<Postgres>, _matches: &ArgMatches) -> Result<()> {
+    Err(ops::kubernetes_unsupported("release deploy"))
+}
+
+pub async fn services(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment provided. Use --env <env> to specify an environment.")
+    })?;
+
+    let skips = matches
+        .get_many::<String>("skip")
+        .unwrap_or_default()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+
+    let mut tx = pool.begin().await?;
+    let service_versions = db::get_service_versions(&mut tx, env).await?;
+    tx.commit().await?;
+
+    let known = service_versions
+        .iter()
+        .map(|svc| svc.name.clone())
+        .collect::<Vec<_>>();
+    let pins = versions::load(
+        versions::resolve_path(matches.get_one::<PathBuf>("versions")).as_deref(),
+        &known,
+    )?;
+
+    let rows = pins
+        .apply(service_versions)
+        .into_iter()
+        .map(|svc| ServiceRow {
+            pinned: pins.version_for(&svc.name).is_some(),
+            skipped: ops::is_skipped(&skips, env, &svc.name),
+            service: svc.name,
+            repo: svc.repo_name,
+            revision: svc.revision,
+        })
+        .collect::<Vec<_>>();
+
+    let format = output::get_format(matches)?;
+    let max_width = output::get_max_width(matches);
+    output::print(&rows, format, max_width)?;
+
+    // The summary would get in the way of parsing the other formats.
+    if format == output::Format::Table {
+        let skipped = rows.iter().filter(|row| row.skipped).count();
+        println!(
+            "{} service(s) in {}, {} would be skipped.",
+            rows.len(),
+            env,
+            skipped
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the services whose versions a release of an environment would
+/// change, compared to the manifest left in the releases repository by the
+/// previous release. Skipped services are left out. Nothing is cloned,
+/// downloaded, or written, so this can be run before `release create` to see
+/// what it would do.
+pub async fn preview(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment provided. Use --env <env> to specify an environment.")
+    })?;
+
+    let repo_name = matches.get_one::<String>("repo-name").ok_or_else(|| {
+        anyhow!("No repository provided. Use --repo-name <repo_name> to specify a repository.")
+    })?;
+
+    let skips = matches
+        .get_many::<String>("skip")
+        .unwrap_or_default()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+
+    let include_disabled = matches.get_flag("include-disabled");
+
+    let mut tx = pool.begin().await?;
+    ops::require_environment(&mut tx, env).await?;
+    let env_services = db::get_services_for_env(&mut tx, env).await?;
+    let service_versions = db::get_service_versions(&mut tx, env).await?;
+    tx.commit().await?;
+
+    let known = service_versions
+        .iter()
+        .map(|svc| svc.name.clone())
+        .collect::<Vec<_>>();
+    ops::validate_skips(&skips, &BTreeMap::from([(env.clone(), known.clone())]))?;
+    let pins = versions::load(
+        versions::resolve_path(matches.get_one::<PathBuf>("versions")).as_deref(),
+        &known,
+    )?;
+
+    let released = ops::active_services(env_services, env, &skips, include_disabled)
+        .into_iter()
+        .map(|svc| svc.name)
+        .collect::<BTreeSet<_>>();
+
+    let repo_dir = PathBuf::from(repo_name);
+    let previous = ReleaseManifest::read(&repo_dir)?;
+    if previous.is_none() {
+        println!(
+            "No {} found in {}, so every service would be new.",
+            MANIFEST_FILENAME,
+            repo_dir.display()
+        );
+    }
+
+    let mut changed = 0;
+    let mut unchanged = 0;
+    for svc in pins.apply(service_versions) {
+        if !released.contains(&svc.name) {
+            continue;
+        }
+        let from = previous
+            .as_ref()
+            .and_then(|manifest| manifest.version_for(env, &svc.name));
+        let pinned = if pins.version_for(&svc.name).is_some() {
+            " (pinned)"
+        } else {
+            ""
+        };
+        match from {
+            Some(from) if from == svc.revision => unchanged += 1,
+            Some(from) => {
+                changed += 1;
+                println!("  {}: {} -> {}{}", svc.name, from, svc.revision, pinned);
+            }
+            None => {
+                changed += 1;
+                println!("  {}: (new) -> {}{}", svc.name, svc.revision, pinned);
+            }
+        }
+    }
+
+    if changed == 0 {
+        println!(
+            "No changes: all {} service(s) in {} are already at their released versions.",
+            unchanged, env
+        );
+    } else {
+        println!(
+            "{} service(s) in {} would change, {} would stay the same, {} skipped.",
+            changed,
+            env,
+            unchanged,
+            known.len() - released.len()
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn freeze(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment provided. Use --env <env> to specify an environment.")
+    })?;
+
+    let out = matches.get_one::<PathBuf>("out").ok_or_else(|| {
+        anyhow!("No output file provided. Use --out <file> to specify an output file.")
+    })?;
+
+    let mut tx = pool.begin().await?;
+    ops::require_environment(&mut tx, env).await?;
+    let pins = versions::VersionPins::from(db::get_service_versions(&mut tx, env).await?);
+    tx.commit().await?;
+
+    pins.write(out)?;
+    println!(
+        "Wrote the versions of {} service(s) in {} to {}",
+        pins.services.len(),
+        env,
+        out.display()
+    );
+
+    Ok(())
+}
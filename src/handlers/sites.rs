@@ -1,38 +1,236 @@
-use crate::{config_values::config, db, dolt, git, ops};
-use anyhow::Result;
+use crate::{
+    bundle, cmd,
+    config_values::{
+        answers::Answers,
+        config, diff,
+        format::{self, Format},
+        schema,
+    },
+    db, dolt, exit, git,
+    lint::{self, Severity},
+    ops, preflight,
+    progress::{self, Spinner},
+    repo_path::{self, RepoPathTemplate},
+    warnings::Warnings,
+};
+#[cfg(feature = "tui")]
+use crate::{commit, editor};
+#[cfg(feature = "kubernetes")]
+use crate::{configs, deploy, kubectl};
+use anyhow::{Context, Result};
 use clap::ArgMatches;
-use sqlx::postgres::PgPoolOptions;
+use console::style;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use sqlx::{Pool, Postgres};
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 /**
  * Set up the CLI for the mgmt-site binary.
  */
 
+// A Dolt database that's cloned into the site directory.
+#[derive(Debug, Clone, PartialEq)]
+struct SiteDatabase {
+    repo: String,
+    name: String,
+    // The ref to check out after cloning, if any.
+    reference: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct InitOpts {
     dir: String,
-    db_repo: String,
-    db_name: String,
+
+    // The first database is the primary one. The others are cloned and
+    // started alongside it.
+    databases: Vec<SiteDatabase>,
+
+    // The name of the database to render the values files from.
+    render_db: String,
     force: bool,
+
+    // Whether to pick up an init that stopped partway, reusing the database
+    // and the repos that were already cloned.
+    resume: bool,
     no_db_clone: bool,
     no_repo_clone: bool,
+
+    // Whether to skip checking the cloned repos against the commits the
+    // database expects them to be at.
+    no_verify: bool,
     no_env: bool,
     no_defaults: bool,
     no_values: bool,
     defaults_filename: String,
     values_filename: String,
+
+    // Where the values file is written, for the deployment being set up.
+    deployment_dir: PathBuf,
+
+    // Only write these sections to the values file. Empty means all of them.
+    sections: Vec<String>,
+    quiet: bool,
+
+    // Command to run in each repo directory after it's cloned.
+    post_clone: Option<String>,
+
+    // Whether a failing post-clone command aborts the init, from the global
+    // --strict flag.
+    strict: bool,
+
+    // The upper bound on how long the whole init may take.
+    timeout: Option<Duration>,
+
+    // Whether to skip the confirmation before --force deletes the site.
+    yes: bool,
+
+    // Only clone the repos for the services in this environment.
+    repos_env: Option<String>,
+
+    // Whether to clone the repos' submodules along with them.
+    submodules: bool,
+
+    // Where each repo goes under the repos directory.
+    repo_path_template: RepoPathTemplate,
+
+    // Answers for setting up the environment, from stdin and --set.
+    answers: Answers,
+
+    // Whether to fail instead of prompting for values that weren't answered.
+    no_prompt: bool,
+
+    // Whether to check the free space and the remotes before cloning.
+    preflight: bool,
+
+    // The maximum number of repos to clone at once.
+    clone_concurrency: usize,
+
+    // How many commits of history to clone for each repo, if they shouldn't
+    // be cloned in full.
+    clone_depth: Option<u32>,
+
+    // Whether to show spinners and progress bars instead of plain lines.
+    progress_bars: bool,
+
+    // How many times to retry writing the config values after a deadlock.
+    db_retries: u32,
+
+    // How long to wait for each database server to accept connections.
+    db_startup_timeout: Duration,
+
+    // The format to write the defaults and values files in, if it shouldn't
+    // come from their extensions.
+    format: Option<Format>,
+}
+
+// Prints a progress message for a step of the site initialization unless
+// quiet mode is enabled, and logs it at info level either way. Warnings and
+// errors should not go through this.
+macro_rules! progress {
+    ($opts:expr, $($arg:tt)*) => {
+        tracing::info!($($arg)*);
+        if !$opts.quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+// The databases and connection pools started during the init. They're owned
+// outside of the init itself so that they get cleaned up whether the init
+// succeeds, fails, or runs out of time.
+#[derive(Default)]
+struct InitResources {
+    servers: Vec<dolt::RunningServer>,
+    pools: Vec<Pool<Postgres>>,
+
+    // Stops the clones and post-clone commands. They run on the blocking
+    // thread pool, so they'd keep going after a timeout otherwise.
+    cancel: cmd::Cancel,
+}
+
+impl InitResources {
+    async fn shutdown(self, opts: &InitOpts) -> anyhow::Result<()> {
+        self.cancel.cancel();
+        progress!(opts, "Shutting down the databases...");
+        for pool in self.pools {
+            pool.close().await;
+        }
+        for server in self.servers {
+            server.stop()?;
+        }
+        progress!(opts, "Done shutting down the databases.\n");
+        Ok(())
+    }
+}
+
+// Returns the template from --repo-path-template. Without it, the one recorded
+// in the site directory is used if `saved` is true, and the default otherwise.
+fn repo_path_template_arg(
+    matches: &ArgMatches,
+    site_dir: &Path,
+    saved: bool,
+) -> Result<RepoPathTemplate> {
+    match matches.get_one::<String>("repo-path-template") {
+        Some(template) => {
+            RepoPathTemplate::parse(template).map_err(|e| exit::Error::Usage(e.to_string()).into())
+        }
+        None if saved => RepoPathTemplate::load(site_dir),
+        None => Ok(RepoPathTemplate::default()),
+    }
+}
+
+// Asks the user to confirm that an existing site directory may be deleted
+// when --force is used. Refuses when there's nobody to ask unless --yes was
+// given.
+fn confirm_overwrite(opts: &InitOpts) -> anyhow::Result<()> {
+    let dir = Path::new(&opts.dir);
+    if !opts.force || opts.yes || !dir.exists() {
+        return Ok(());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "Refusing to delete {} without confirmation. Use -y or --yes to delete it anyway.",
+            opts.dir
+        ));
+    }
+
+    let repo_count = repo_path::find_repos(&dir.join("repos")).map_or(0, |repos| repos.len());
+
+    let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "This will delete {}, including {} repo(s). Continue?",
+            opts.dir, repo_count
+        ))
+        .default(false)
+        .interact()?;
+    if !confirmed {
+        return Err(anyhow::anyhow!("Not deleting {}.", opts.dir));
+    }
+
+    Ok(())
 }
 
 // Create the site directory if it doesn't already exist.
-// If it does exist, and force is true, delete it and recreate it.
+// If it does exist, and force is true, delete it and recreate it. If resume
+// is true, the existing directory is kept so the init can pick up where it
+// left off.
 fn create_site_dir(opts: &InitOpts) -> anyhow::Result<()> {
     let dir = &opts.dir;
     let force = opts.force;
     let site_exists = std::path::Path::new(dir).exists();
     if site_exists && force {
         std::fs::remove_dir_all(dir)?;
+    } else if site_exists && opts.resume {
+        progress!(opts, "Resuming the init in {}", dir);
+        std::fs::create_dir_all(Path::new(dir).join("repos"))?;
     } else if site_exists {
         return Err(anyhow::anyhow!(
-            "Directory {} already exists. Use -f or --force to overwrite.",
+            "Directory {} already exists. Use -f or --force to overwrite, or --resume to pick up where a failed init left off.",
             dir
         ));
     } else {
@@ -42,100 +240,689 @@ fn create_site_dir(opts: &InitOpts) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn init(opts: &InitOpts) -> anyhow::Result<()> {
-    // Create the site directory.
-    create_site_dir(&opts)?;
+// Runs the post-clone command in a freshly cloned repo. The name of the repo
+// is available to the command in the MGMT_REPO_NAME environment variable.
+// Returns what the command printed.
+fn run_post_clone(
+    post_clone: &str,
+    repo_name: &str,
+    repo_dir: &Path,
+    cancel: &cmd::Cancel,
+) -> anyhow::Result<String> {
+    cmd::Cmd::shell(post_clone)
+        .dir(repo_dir)
+        .env("MGMT_REPO_NAME", repo_name)
+        .canceled_by(cancel)
+        .output()
+}
+
+// A repo to clone during the init.
+struct RepoClone {
+    url: String,
+    name: String,
+    dir: PathBuf,
+    revision: Option<String>,
+
+    // The commit the database expects the checkout to be at, if any.
+    expected_commit: Option<String>,
+}
+
+// What happened when a repo was cloned, along with the result of its
+// post-clone command if one was run.
+struct CloneResult {
+    repo: RepoClone,
+    cloned: Result<()>,
+    post_clone: Option<Result<String>>,
+
+    // Why the checkout isn't at the commit the database expects, if it isn't.
+    // The post-clone command isn't run in a checkout that fails the check.
+    mismatch: Option<String>,
+
+    // Whether the repo was already cloned by an earlier, resumed init.
+    reused: bool,
+}
 
-    let db_dir: PathBuf;
+// Returns whether a directory holds a usable checkout of the repo: git can
+// read its HEAD commit, it was cloned from the same URL, and, if the database
+// lists a revision for the repo, HEAD is at that revision.
+fn has_checkout(repo: &RepoClone) -> bool {
+    let Ok(head) = git::head_commit(&repo.dir) else {
+        return false;
+    };
+    let same_remote = git::remote_url(&repo.dir)
+        .is_ok_and(|url| url.trim_end_matches(".git") == repo.url.trim_end_matches(".git"));
+    let same_revision = match &repo.revision {
+        Some(revision) => git::rev_parse(&repo.dir, revision).is_ok_and(|rev| rev == head),
+        None => true,
+    };
+    repo.dir.is_dir() && same_remote && same_revision
+}
+
+// The file, inside a repo's .git directory, that records that the post-clone
+// command finished in it, so that a resumed init knows whether to run it again.
+const POST_CLONE_MARKER: &str = "mgmt-post-clone-done";
+
+fn post_clone_marker(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(".git").join(POST_CLONE_MARKER)
+}
+
+// Runs the post-clone command in a repo and records that it finished, so that
+// a resumed init doesn't run it again.
+fn run_and_record_post_clone(
+    post_clone: &str,
+    repo: &RepoClone,
+    cancel: &cmd::Cancel,
+) -> anyhow::Result<String> {
+    let output = run_post_clone(post_clone, &repo.name, &repo.dir, cancel)?;
+    std::fs::write(post_clone_marker(&repo.dir), "")
+        .context("while recording that the post-clone command finished")?;
+    Ok(output)
+}
+
+// Returns why a checkout isn't at the commit the database expects, or None if
+// it is or there's nothing to check.
+fn check_expected_commit(repo: &RepoClone) -> Option<String> {
+    let expected = repo.expected_commit.as_ref()?;
+    git::verify_head(&repo.dir, expected)
+        .err()
+        .map(|e| e.to_string())
+}
+
+// Clones a repo, checks that it's at the commit the database expects, and
+// runs the post-clone command in it. Nothing is printed while this runs, so
+// that repos cloned at the same time don't have their output mixed together.
+// When resuming, a repo that's already checked out at the right revision is
+// left alone, apart from running the post-clone command if it didn't finish
+// last time, and anything else in its directory, such as an interrupted clone
+// or a checkout of another revision, is removed first.
+fn clone_repo(
+    repo: RepoClone,
+    submodules: bool,
+    depth: Option<u32>,
+    post_clone: Option<String>,
+    resume: bool,
+    cancel: &cmd::Cancel,
+) -> CloneResult {
+    let _span = tracing::info_span!("clone", repo = %repo.name).entered();
+    if resume && has_checkout(&repo) {
+        tracing::info!("reusing the existing checkout");
+        let mismatch = check_expected_commit(&repo);
+        let post_clone = match (&mismatch, post_clone) {
+            (None, Some(post_clone)) if !post_clone_marker(&repo.dir).exists() => {
+                Some(run_and_record_post_clone(&post_clone, &repo, cancel))
+            }
+            _ => None,
+        };
+        return CloneResult {
+            repo,
+            cloned: Ok(()),
+            post_clone,
+            mismatch,
+            reused: true,
+        };
+    }
+    if resume && repo.dir.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&repo.dir) {
+            return CloneResult {
+                repo,
+                cloned: Err(e.into()),
+                post_clone: None,
+                mismatch: None,
+                reused: false,
+            };
+        }
+    }
+
+    tracing::debug!(url = %repo.url, dir = %repo.dir.display(), "cloning repo");
+    let cloned = git::clone_quietly(
+        &repo.url,
+        &repo.dir.to_string_lossy(),
+        repo.revision.as_deref(),
+        submodules,
+        depth,
+        cancel,
+    );
+    // The post-clone command only runs once the checkout is known to be the
+    // one the database expects.
+    let mismatch = cloned
+        .as_ref()
+        .ok()
+        .and_then(|_| check_expected_commit(&repo));
+    let post_clone = match (&cloned, &mismatch, post_clone) {
+        (Ok(_), None, Some(post_clone)) => {
+            Some(run_and_record_post_clone(&post_clone, &repo, cancel))
+        }
+        _ => None,
+    };
+    match &cloned {
+        Ok(_) => tracing::info!("cloned repo"),
+        Err(e) => tracing::warn!(error = %e, "failed to clone repo"),
+    }
+    CloneResult {
+        repo,
+        cloned,
+        post_clone,
+        mismatch,
+        reused: false,
+    }
+}
+
+// Returns what's printed once a repo is done cloning, as a single block.
+fn clone_report(result: &CloneResult) -> String {
+    let repo = &result.repo;
+    let mut report = match &repo.revision {
+        _ if result.reused => format!("{} is already cloned", repo.dir.display()),
+        Some(revision) => format!(
+            "Cloning {} at {} into {}",
+            repo.url,
+            revision,
+            repo.dir.display()
+        ),
+        None => format!("Cloning {} into {}", repo.url, repo.dir.display()),
+    };
+    match (&result.cloned, &result.post_clone) {
+        (Err(_), _) => report.push_str(&format!("\n{} failed", style("✗").red())),
+        (Ok(_), None) if result.mismatch.is_some() => report.push_str(&format!(
+            "\n{} not at the expected commit, so the post-clone command wasn't run",
+            style("✗").red()
+        )),
+        (Ok(_), Some(Ok(output))) if !output.trim().is_empty() => {
+            report.push_str(&format!("\n{}", output.trim_end()))
+        }
+        _ => {}
+    }
+    report
+}
+
+// Returns whether a repo's clone is worth printing on its own even when a
+// progress bar is shown: it failed, isn't at the expected commit, or its
+// post-clone command printed output.
+fn clone_is_notable(result: &CloneResult) -> bool {
+    if result.mismatch.is_some() {
+        return true;
+    }
+    match (&result.cloned, &result.post_clone) {
+        (Err(_), _) => true,
+        (Ok(_), Some(Ok(output))) => !output.trim().is_empty(),
+        (Ok(_), Some(Err(_))) => true,
+        (Ok(_), None) => false,
+    }
+}
+
+// Clones the repos, running at most `clone_concurrency` clones at once. A
+// failed clone doesn't stop the others. Returns the result for each repo,
+// sorted by name.
+async fn clone_repos(
+    opts: &InitOpts,
+    repos: Vec<RepoClone>,
+    cancel: &cmd::Cancel,
+) -> Result<Vec<CloneResult>> {
+    let semaphore = Arc::new(Semaphore::new(opts.clone_concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    // With a progress bar, only the repos that failed or whose post-clone
+    // command printed something get a block of their own.
+    let bar = opts
+        .progress_bars
+        .then(|| progress::bar(repos.len() as u64, "Cloning the repos"));
+
+    for repo in repos {
+        let semaphore = Arc::clone(&semaphore);
+        let (submodules, depth, post_clone, quiet, resume) = (
+            opts.submodules,
+            opts.clone_depth,
+            opts.post_clone.clone(),
+            opts.quiet,
+            opts.resume,
+        );
+        let bar = bar.clone();
+        let cancel = cancel.clone();
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let result = tokio::task::spawn_blocking(move || {
+                clone_repo(repo, submodules, depth, post_clone, resume, &cancel)
+            })
+            .await?;
+            match &bar {
+                Some(bar) => {
+                    if clone_is_notable(&result) {
+                        bar.println(format!("{}\n", clone_report(&result)));
+                    }
+                    bar.set_message(result.repo.name.clone());
+                    bar.inc(1);
+                }
+                None if !quiet => println!("{}\n", clone_report(&result)),
+                None => {}
+            }
+            anyhow::Ok(result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined??);
+    }
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    results.sort_by(|a, b| a.repo.name.cmp(&b.repo.name));
+
+    Ok(results)
+}
+
+// Checks that a database has the tables mgmt expects, so that a database
+// cloned from the wrong repo fails with a clear error instead of on the first
+// query that touches a missing table.
+async fn check_schema(pool: &Pool<Postgres>, database: &SiteDatabase) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let missing = db::missing_tables(&mut tx).await?;
+    tx.commit().await?;
+
+    if !missing.is_empty() {
+        return Err(exit::Error::Usage(format!(
+            "the {} database cloned from {} is missing these tables: {}. Check that --db-repo points at a database set up with the migrations in db/migrations.",
+            database.name,
+            database.repo,
+            missing.join(", ")
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+// Warns about a repo that wasn't cloned because of --no-repo-clone if it's
+// missing from the site or was cloned from somewhere else.
+fn check_existing_repo(repo_dir: &Path, repo_url: &str, warnings: &mut Warnings) {
+    if !repo_dir.is_dir() {
+        warnings.warn(format!(
+            "{} is missing and wasn't cloned",
+            repo_dir.display()
+        ));
+        return;
+    }
+
+    match git::remote_url(&repo_dir.to_path_buf()) {
+        Ok(url) if url.trim_end_matches(".git") != repo_url.trim_end_matches(".git") => warnings
+            .warn(format!(
+                "{} was cloned from {}, but the database lists {}",
+                repo_dir.display(),
+                url,
+                repo_url
+            )),
+        Ok(_) => {}
+        Err(e) => warnings.warn(format!(
+            "couldn't check the remote of {}: {:#}",
+            repo_dir.display(),
+            e
+        )),
+    }
+}
+
+// Checks that there's room for the site and that the databases can be cloned
+// before anything is created.
+async fn preflight_site(opts: &InitOpts) -> Result<()> {
+    progress!(opts, "Running the preflight checks...");
+    let dir = Path::new(&opts.dir);
+    let free = tokio::task::spawn_blocking({
+        let dir = dir.to_path_buf();
+        move || preflight::check_free_space(&dir)
+    })
+    .await??;
+    progress!(
+        opts,
+        "{} free for {}",
+        preflight::format_bytes(free),
+        dir.display()
+    );
 
-    // Clone the base database.
-    println!("Cloning the database from {}...", &opts.db_repo);
     if !opts.no_db_clone {
-        db_dir = ops::clone_db(&opts.dir, &opts.db_repo, &opts.db_name, opts.force)?;
-    } else {
-        db_dir = PathBuf::from(&opts.dir).join(&opts.db_name);
+        for database in &opts.databases {
+            preflight::check_dolt_remote(&database.repo).await?;
+            progress!(opts, "{} is reachable", database.repo);
+        }
     }
-    println!("Done cloning the database.\n");
+    progress!(opts, "Done running the preflight checks.\n");
+    Ok(())
+}
 
-    println!("Starting the database...");
-    let db_dir_str = db_dir
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("failed to get database directory as string"))?;
-    let db_handle = dolt::start(db_dir_str)?;
-    println!("Done staring the database.\n");
+// Checks a few of the repos' remotes before cloning any of them, so that a
+// network or credentials problem shows up before the first clone stalls.
+async fn preflight_repos(opts: &InitOpts, repos: &[(i32, String, String)]) -> Result<()> {
+    progress!(opts, "Checking that the repos can be reached...");
+    for (_, repo_url, _) in preflight::sample(repos, preflight::REPO_SAMPLE_SIZE) {
+        preflight::check_git_remote(repo_url).await?;
+        progress!(opts, "{} is reachable", repo_url);
+    }
+    progress!(opts, "Done checking the repos.\n");
+    Ok(())
+}
+
+// The blocking steps of the init (cloning, running hooks) are run on the
+// blocking thread pool so that the overall timeout can still fire while
+// they're in progress.
+async fn init(
+    opts: &InitOpts,
+    resources: &mut InitResources,
+    warnings: &mut Warnings,
+) -> anyhow::Result<()> {
+    if opts.preflight {
+        preflight_site(opts).await?;
+    }
+
+    // Create the site directory.
+    create_site_dir(&opts)?;
+    opts.repo_path_template.save(Path::new(&opts.dir))?;
+
+    // Clone and start each of the databases, giving each one its own port.
+    // The search for the next port starts after the last one used, since the
+    // server that was just started might not be listening on it yet.
+    let mut next_port = dolt::DEFAULT_PORT;
+    for database in &opts.databases {
+        let db_dir: PathBuf;
+
+        let spinner = Spinner::start(
+            format!("Cloning the database from {}...", &database.repo),
+            opts.progress_bars,
+            opts.quiet,
+        );
+        if !opts.no_db_clone {
+            // A database that's only partly there is cloned again from
+            // scratch when resuming.
+            let (dir, repo, name, force, quiet, resume) = (
+                opts.dir.clone(),
+                database.repo.clone(),
+                database.name.clone(),
+                opts.force || opts.resume,
+                opts.progress_bars || opts.quiet,
+                opts.resume,
+            );
+            let reference = database.reference.clone();
+            let cancel = resources.cancel.clone();
+            db_dir = tokio::task::spawn_blocking(move || {
+                let existing = PathBuf::from(&dir).join(&name);
+                let db_dir = match existing.to_str() {
+                    Some(existing_str) if resume && dolt::is_database(existing_str) => {
+                        tracing::info!(dir = existing_str, "reusing the existing database");
+                        existing
+                    }
+                    _ => ops::clone_db(&dir, &repo, &name, force, quiet, &cancel)?,
+                };
+                if let Some(reference) = reference {
+                    let db_dir_str = db_dir.to_str().ok_or_else(|| {
+                        anyhow::anyhow!("failed to get database directory as string")
+                    })?;
+                    dolt::checkout(db_dir_str, &reference)?;
+                }
+                anyhow::Ok(db_dir)
+            })
+            .await??;
+        } else {
+            db_dir = PathBuf::from(&opts.dir).join(&database.name);
+        }
+        spinner.finish(format!(
+            "Done cloning the database from {}.",
+            &database.repo
+        ));
+
+        let port = dolt::free_port(next_port)?;
+        let spinner = Spinner::start(
+            format!(
+                "Starting the {} database on port {}...",
+                &database.name, port
+            ),
+            opts.progress_bars,
+            opts.quiet,
+        );
+        let db_dir_str = db_dir
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("failed to get database directory as string"))?;
+        resources
+            .servers
+            .push(dolt::start(db_dir_str, Some(port), false)?);
+        next_port = port + 1;
+        spinner.finish(format!(
+            "Done starting the {} database on port {}.",
+            &database.name, port
+        ));
+    }
 
-    println!("Connecting to the database...");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name))
+    progress!(opts, "Connecting to the database...");
+    let primary = &opts.databases[0];
+    let pool = resources.servers[0]
+        .connect_when_ready(&primary.name, opts.db_startup_timeout)
         .await?;
+    resources.pools.push(pool.clone());
+    check_schema(&pool, primary).await?;
     let mut tx = pool.begin().await?;
-    println!("Done connecting to the database.\n");
 
-    // Get the list of repos.
-    let repos = db::get_repos(&mut tx).await?;
+    // The values files are rendered from the primary database unless another
+    // one was requested.
+    let render_pool = match opts.databases.iter().position(|d| d.name == opts.render_db) {
+        Some(0) => pool.clone(),
+        Some(i) => {
+            let render_pool = resources.servers[i]
+                .connect_when_ready(&opts.databases[i].name, opts.db_startup_timeout)
+                .await?;
+            resources.pools.push(render_pool.clone());
+            check_schema(&render_pool, &opts.databases[i]).await?;
+            render_pool
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "Database {} is not one of the databases being initialized.",
+                opts.render_db
+            ))
+        }
+    };
+    progress!(opts, "Done connecting to the database.\n");
+
+    // Catch a mistyped --section before spending time on the clones.
+    if !opts.no_env && !opts.no_values {
+        check_sections(&render_pool, &opts.sections).await?;
+    }
+
+    // Get the list of repos, limited to the ones the environment needs if
+    // one was given.
+    let repos = match &opts.repos_env {
+        Some(env) => {
+            let env_id = ops::require_environment(&mut tx, env).await?.id;
+            db::get_repos_for_env(&mut tx, env_id).await?
+        }
+        None => db::get_repos(&mut tx).await?,
+    };
+    if repos.is_empty() {
+        warnings.warn("the database doesn't list any repos to clone");
+    }
+    let revisions = db::get_repo_revisions(&mut tx).await?;
+    let expected_commits = if opts.no_verify {
+        std::collections::HashMap::new()
+    } else {
+        db::get_repo_expected_commits(&mut tx).await?
+    };
+    tx.commit().await?;
+    if opts.preflight && !opts.no_repo_clone {
+        preflight_repos(opts, &repos).await?;
+    }
 
-    println!("Cloning the repos...");
+    progress!(opts, "Cloning the repos...");
+    let mut to_clone = Vec::new();
     for repo in repos {
-        let (repo_url, repo_name) = repo;
-        let repo_dir = Path::new(&opts.dir).join("repos").join(&repo_name);
-        let repo_dir_str = repo_dir
-            .to_str()
-            .ok_or_else(|| anyhow::anyhow!("failed to get repo directory as string"))
-            .unwrap();
+        let (_, repo_url, repo_name) = repo;
+        let repo_dir = opts.repo_path_template.repo_dir(
+            &Path::new(&opts.dir).join("repos"),
+            &repo_url,
+            &repo_name,
+        )?;
 
-        println!("Cloning {} into {}", repo_url, repo_dir_str);
-        if !opts.no_repo_clone {
-            git::clone(&repo_url, repo_dir_str)?;
-        } else {
-            println!("Skipping cloning of {}", repo_url);
+        if opts.no_repo_clone {
+            progress!(opts, "Skipping cloning of {}", repo_url);
+            check_existing_repo(&repo_dir, &repo_url, warnings);
+            continue;
+        }
+
+        // Repos are checked out at the revision the database lists for them,
+        // so that a site can pin services to release tags.
+        to_clone.push(RepoClone {
+            revision: revisions.get(&repo_name).cloned(),
+            expected_commit: expected_commits.get(&repo_name).cloned(),
+            url: repo_url,
+            name: repo_name,
+            dir: repo_dir,
+        });
+    }
+
+    let mut hook_failures: Vec<String> = Vec::new();
+    let mut clone_failures: Vec<String> = Vec::new();
+    let mut mismatches: Vec<String> = Vec::new();
+    for result in clone_repos(opts, to_clone, &resources.cancel).await? {
+        let repo = &result.repo;
+        if let Some(mismatch) = &result.mismatch {
+            mismatches.push(format!("{}: {}", repo.name, mismatch));
+        }
+        match (&result.cloned, &result.post_clone) {
+            (Err(e), _) => {
+                warnings.warn(format!(
+                    "failed to clone {} into {}: {:#}",
+                    repo.url,
+                    repo.dir.display(),
+                    e
+                ));
+                clone_failures.push(repo.name.clone());
+            }
+            (Ok(_), Some(Err(e))) => hook_failures.push(format!("{}: {}", repo.name, e)),
+            _ => {}
+        }
+    }
+    if !clone_failures.is_empty() {
+        eprintln!(
+            "{} {} repo(s) failed to clone: {}",
+            style("✗").red(),
+            clone_failures.len(),
+            clone_failures.join(", ")
+        );
+    }
+    progress!(opts, "Done cloning the repos.\n");
+
+    if !mismatches.is_empty() {
+        return Err(exit::Error::Drift(format!(
+            "{} repo(s) aren't at the commit the database expects. Use --no-verify to skip the check.\n  {}",
+            mismatches.len(),
+            mismatches.join("\n  ")
+        ))
+        .into());
+    }
+
+    if !hook_failures.is_empty() {
+        if opts.strict {
+            return Err(anyhow::anyhow!(
+                "post-clone command failed for {} repo(s): {}",
+                hook_failures.len(),
+                hook_failures.join("; ")
+            ));
+        }
+        for failure in hook_failures {
+            warnings.warn(format!("the post-clone command failed for {}", failure));
         }
-        println!("");
     }
-    println!("Done cloning the repos.\n");
 
     let mut env_config = config::ConfigValues::default();
 
     if !opts.no_env {
-        println!("Setting up the environment...");
-        env_config.ask_for_info(&mut tx).await?;
-        println!("Done setting up the environment.\n");
+        progress!(opts, "Setting up the environment...");
+        if opts.answers.is_empty() && !opts.no_prompt {
+            // The prompts can't be replayed, so this isn't retried.
+            let mut tx = pool.begin().await?;
+            env_config.ask_for_info(&mut tx).await?;
+            tx.commit().await?;
+        } else {
+            // Several sites initialized against a shared server can deadlock
+            // while writing their config values, so the whole batch is run
+            // again if that happens.
+            progress!(opts, "Using {} answer(s)", opts.answers.len());
+            let (answers, prompt) = (&opts.answers, !opts.no_prompt);
+            env_config = db::with_retry(&pool, opts.db_retries, |tx| {
+                let answers = answers.clone();
+                Box::pin(async move {
+                    let answers = answers.resolve(tx).await?;
+                    let mut env_config = config::ConfigValues::default();
+                    env_config.apply_answers(tx, &answers, prompt).await?;
+                    Ok(env_config)
+                })
+            })
+            .await?;
+        }
+        progress!(opts, "Done setting up the environment.\n");
     }
 
     // Write out the default config values into the site directory.
     if !opts.no_defaults {
-        println!("Writing out the default values...");
+        progress!(opts, "Writing out the default values...");
         let defaults_filename = Path::new(&opts.dir).join(&opts.defaults_filename);
-        ops::render_default_values(&pool, Some(defaults_filename)).await?;
-        println!("Done writing out the default values.\n");
+        ops::render_default_values(&render_pool, Some(defaults_filename), opts.format, false)
+            .await?;
+        progress!(opts, "Done writing out the default values.\n");
     }
 
-    tx.commit().await?;
-
     if !opts.no_env && !opts.no_values {
-        println!("Writing out the environment config values...");
-        let values_filename = Path::new(&opts.dir).join(&opts.values_filename);
+        progress!(opts, "Writing out the environment config values...");
+        std::fs::create_dir_all(&opts.deployment_dir).map_err(|e| {
+            anyhow::anyhow!("failed to create {}: {}", opts.deployment_dir.display(), e)
+        })?;
+        let values_filename = opts.deployment_dir.join(&opts.values_filename);
         let mut section_option = config::SectionOptions::default();
         section_option.set_all(true)?;
-        ops::render_values(
-            &pool,
+        ops::render_section_values(
+            &render_pool,
             &env_config.environment,
             &section_option,
+            &opts.sections,
             Some(values_filename),
+            opts.format,
+            false,
+            false,
         )
         .await?;
-        println!("Done writing out the environment config values.\n");
+        progress!(opts, "Done writing out the environment config values.\n");
     }
 
-    // Clean up and shut down
-    println!("Shutting down the database...");
-    pool.close().await;
-    db_handle.kill()?;
-    println!("Done shutting down the database.\n");
-
     Ok(())
 }
 
+// Returns the name of a file init writes values to, given the --format it was
+// asked for. The default name's extension is changed to match the format, so
+// that reading the file back picks the same format. A name passed explicitly
+// with an extension for another format is a usage error.
+fn filename_for_format(
+    matches: &ArgMatches,
+    id: &str,
+    filename: &str,
+    format: Option<Format>,
+) -> Result<String> {
+    let Some(format) = format else {
+        return Ok(filename.to_string());
+    };
+    if matches.value_source(id) == Some(clap::parser::ValueSource::DefaultValue) {
+        return Ok(Path::new(filename)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .into_owned());
+    }
+    match Format::from_path(Path::new(filename)) {
+        Some(from_extension) if from_extension != format => Err(exit::Error::Usage(format!(
+            "--{} {} doesn't match --format {}. Use a .{} filename or leave out --format.",
+            id,
+            filename,
+            format.extension(),
+            format.extension()
+        ))
+        .into()),
+        _ => Ok(filename.to_string()),
+    }
+}
+
+#[cfg(feature = "kubernetes")]
 struct DeployOpts {
     site_dirpath: PathBuf,
     env: String,
@@ -143,9 +930,176 @@ struct DeployOpts {
     services: Vec<String>,
     defaults_filepath: PathBuf,
     values_filepath: PathBuf,
-    builds_dirpath: PathBuf,
+    repo_path_template: RepoPathTemplate,
+    dry_run: bool,
+
+    // Values from --set that replace the ones in the values file, keyed by
+    // section and key.
+    overrides: std::collections::BTreeMap<(String, String), String>,
 }
 
+// A service to deploy, along with its cloned repo, its build file, and its
+// config templates paired with the names of the files they're rendered to.
+#[cfg(feature = "kubernetes")]
+struct PlannedDeployment {
+    service: String,
+    repo_dir: PathBuf,
+    build_file: PathBuf,
+    templates: Vec<(PathBuf, String)>,
+}
+
+// Reads the defaults and values files and merges them, then applies the
+// overrides from --set. Fails if the values file is for a different
+// environment than the one being deployed, since deploying it would use
+// another environment's settings.
+#[cfg(feature = "kubernetes")]
+fn load_site_values(opts: &DeployOpts) -> Result<config::ConfigValues> {
+    // A redacted secret might not even parse as the type of its value, so
+    // the file is checked before it's loaded.
+    let contents = std::fs::read_to_string(&opts.values_filepath)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {}", opts.values_filepath.display(), e))?;
+    if contents.contains(crate::config_values::secrets::PLACEHOLDER) {
+        return Err(exit::Error::Usage(format!(
+            "The values file {} has its secrets redacted, so it can't be used to deploy {}. Render it again without --redact-secrets.",
+            opts.values_filepath.display(),
+            opts.env
+        ))
+        .into());
+    }
+
+    let defaults = config::ConfigValues::from_file(&opts.defaults_filepath)?;
+    let values = config::ConfigValues::from_file(&opts.values_filepath)?;
+
+    if values.environment.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The values file {} doesn't set the Environment, so it can't be used to deploy {}.",
+            opts.values_filepath.display(),
+            opts.env
+        ));
+    }
+    if values.environment != opts.env {
+        return Err(anyhow::anyhow!(
+            "The values file {} is for the {} environment, not {}. Use --values-filename to pick the values file for {}.",
+            opts.values_filepath.display(),
+            values.environment,
+            opts.env,
+            opts.env
+        ));
+    }
+
+    let merged = defaults
+        .merge_with(&values)?
+        .with_overrides(&opts.overrides)?;
+    if merged.namespace().is_empty() {
+        return Err(anyhow::anyhow!(
+            "Neither {} nor {} set the Namespace to deploy {} into.",
+            opts.defaults_filepath.display(),
+            opts.values_filepath.display(),
+            opts.env
+        ));
+    }
+    merged.validate().map_err(|errors| {
+        config::validation_failure(
+            &format!(
+                "{} and {}",
+                opts.defaults_filepath.display(),
+                opts.values_filepath.display()
+            ),
+            &errors,
+        )
+    })?;
+
+    Ok(merged)
+}
+
+// Works out where each service is deployed from before any of them are
+// deployed, so that a missing repo or build file doesn't leave the
+// environment half deployed.
+#[cfg(feature = "kubernetes")]
+async fn plan_deployment(
+    pool: &Pool<Postgres>,
+    opts: &DeployOpts,
+) -> Result<Vec<PlannedDeployment>> {
+    let mut tx = pool.begin().await?;
+
+    // Checked first so that a mistyped environment lists the ones there are.
+    ops::require_environment(&mut tx, &opts.env).await?;
+
+    let known_services = db::list_services(&mut tx, &opts.env).await?;
+    let services = if opts.services.is_empty() {
+        known_services
+    } else {
+        let known_names = known_services
+            .iter()
+            .map(|svc| svc.name.clone())
+            .collect::<Vec<_>>();
+        ops::validate_service_names("--service", &opts.services, &known_names)?;
+        known_services
+            .into_iter()
+            .filter(|svc| opts.services.contains(&svc.name))
+            .collect()
+    };
+
+    let repos_dir = opts.site_dirpath.join("repos");
+    let service_templates = db::list_env_service_templates(&mut tx, &opts.env).await?;
+    let mut planned = Vec::new();
+    for svc in services {
+        let repo = db::get_repo_by_id(&mut tx, svc.repo_id).await?;
+        let repo_dir = opts
+            .repo_path_template
+            .repo_dir(&repos_dir, &repo.url, &repo.name)?;
+        if !repo_dir.exists() {
+            return Err(anyhow::anyhow!(
+                "The repo for {} isn't cloned into {}. Clone {} there or use --repo-path-template to match the site's layout.",
+                svc.name,
+                repo_dir.display(),
+                repo.url
+            ));
+        }
+
+        let build_file = deploy::build_file_path(&opts.site_dirpath, &opts.env, &svc.name);
+        if !build_file.exists() {
+            return Err(anyhow::anyhow!(
+                "There's no build file for {} at {}.",
+                svc.name,
+                build_file.display()
+            ));
+        }
+
+        let mut templates = Vec::new();
+        for tmpl in service_templates
+            .iter()
+            .filter(|tmpl| tmpl.service_name == svc.name)
+        {
+            let tmpl_repo = db::get_repo_by_id(&mut tx, tmpl.repo_id).await?;
+            let tmpl_path = opts
+                .repo_path_template
+                .repo_dir(&repos_dir, &tmpl_repo.url, &tmpl_repo.name)?
+                .join(&tmpl.template_path);
+            if !tmpl_path.exists() {
+                return Err(anyhow::anyhow!(
+                    "There's no config template for {} at {}.",
+                    svc.name,
+                    tmpl_path.display()
+                ));
+            }
+            templates.push((tmpl_path, tmpl.render_path.clone()));
+        }
+
+        planned.push(PlannedDeployment {
+            service: svc.name,
+            repo_dir,
+            build_file,
+            templates,
+        });
+    }
+
+    tx.commit().await?;
+
+    Ok(planned)
+}
+
+#[cfg(feature = "kubernetes")]
 async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
     println!("Deploying {} from {:?}...", opts.env, opts.site_dirpath);
     println!("Using database {}...", opts.db_name);
@@ -157,54 +1111,66 @@ async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
     let db_dir_str = db_dir
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("failed to get database directory as string"))?;
-    let dolt_handle = dolt::start(db_dir_str)?;
+    // Deploying never writes to the database.
+    let server = dolt::start(db_dir_str, None, true)?;
     println!("DONE\n");
 
     print!("Connecting to the database...");
-    // Connect to the database.
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name))
+    let pool = server
+        .connect_when_ready(&opts.db_name, dolt::DEFAULT_STARTUP_TIMEOUT)
         .await?;
-    let mut tx = pool.begin().await?;
     println!("DONE\n");
+    let planned = plan_deployment(&pool, opts).await?;
+    pool.close().await;
 
-    let services_to_deploy: Vec<String>;
+    // The database isn't needed for the deployment itself.
+    server.stop()?;
 
-    if opts.services.is_empty() {
-        services_to_deploy = db::list_services(&mut tx, &opts.env)
-            .await?
-            .into_iter()
-            .map(|svc| svc.name)
-            .collect();
-    } else {
-        services_to_deploy = opts.services.clone();
+    let values = load_site_values(opts)?;
+    let ns = values.namespace();
+    for ((section, key), value) in &opts.overrides {
+        println!("Overriding {}.{} with {}", section, key, value);
     }
 
-    // Create the configs directory for the environment in the site directory if it doesn't already exist.
-    // If it already exists, use it. opts.site_dirpath / configs / opts.env is the format for the config dir.
-
-    // Generate the configs for the service in the configs directory. See configs::generate for the command.
-
-    // Load the configs into the cluster.
-
-    // Load secrets into the cluster for the service.
+    // The configs and secrets are rendered from the merged values, so the
+    // overrides from --set end up in what's loaded into the cluster.
+    let env_configdir = opts.site_dirpath.join("configs").join(&opts.env);
+    let secrets_dir = env_configdir.join("secrets");
+    let templates = planned
+        .iter()
+        .flat_map(|deployment| deployment.templates.iter().cloned())
+        .collect::<Vec<_>>();
+    configs::render_values(&values, &templates, &env_configdir, &secrets_dir)?;
+    println!("Rendered the configs into {}", env_configdir.display());
 
-    for service in services_to_deploy {
-        println!("Deploying service {}...", service);
+    if opts.dry_run {
+        println!("Would load the configs and secrets into {}", ns);
+    } else {
+        if kubectl::ensure_namespace(ns)? {
+            println!("Created namespace {}", ns);
+        }
+        configs::load_configs(ns, "service-configs", &env_configdir)?;
+        configs::load_secrets(ns, &secrets_dir)?;
+    }
 
-        // Find builds file for the service. Need to make sure the de-releases repo is pulled.
-        let builds_pb = Path::new(&opts.builds_dirpath).join(&service);
-        let mut builds_path = builds_pb.to_str().unwrap_or_default().to_string();
-        builds_path.push_str(".json");
-        println!("Build metadata file: {}", builds_path);
+    for deployment in planned {
+        if opts.dry_run {
+            println!(
+                "Would deploy {} into {} from {} with {}",
+                deployment.service,
+                ns,
+                deployment.repo_dir.display(),
+                deployment.build_file.display()
+            );
+            continue;
+        }
 
-        // Deploy the service. See deploy_project in app.rs for a reference.
+        println!("Deploying service {}...", deployment.service);
+        let output = deploy::deploy_service_from(&deployment.repo_dir, &deployment.build_file, ns)
+            .map_err(|e| e.context(format!("failed to deploy {}", deployment.service)))?;
+        print!("{}", output);
     }
 
-    tx.commit().await?;
-    dolt_handle.kill()?;
-
     Ok(())
 }
 
@@ -213,25 +1179,102 @@ pub async fn init_site(matches: &ArgMatches) -> Result<()> {
         anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
     })?;
 
-    let db_repo = matches.get_one::<String>("db-repo").ok_or_else(|| {
-        anyhow::anyhow!(
-            "No Dolt DB remote specified. Use -r or --db-remote to specify a Dolt DB remote."
-        )
-    })?;
+    let db_repos = matches
+        .get_many::<String>("db-repo")
+        .unwrap_or_default()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    if db_repos.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No Dolt DB remote specified. Use -r or --db-repo to specify a Dolt DB remote."
+        ));
+    }
 
-    let db_name = matches.get_one::<String>("db-name").ok_or_else(|| {
-        anyhow::anyhow!("No Dolt DB name specified. Use -n or --db-name to specify a Dolt DB name.")
-    })?;
+    let db_names = matches
+        .get_many::<String>("db-name")
+        .unwrap_or_default()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    if db_names.len() != db_repos.len() {
+        return Err(exit::Error::Usage(format!(
+            "Each --db-repo needs a matching --db-name. Got {} repos and {} names.",
+            db_repos.len(),
+            db_names.len()
+        ))
+        .into());
+    }
+
+    let mut databases = db_repos
+        .into_iter()
+        .zip(db_names)
+        .map(|(repo, name)| SiteDatabase {
+            repo,
+            name,
+            reference: None,
+        })
+        .collect::<Vec<_>>();
+
+    // Only the primary database can be pinned.
+    databases[0].reference = matches.get_one::<String>("db-ref").cloned();
+
+    let render_db = matches
+        .get_one::<String>("render-db")
+        .cloned()
+        .unwrap_or_else(|| databases[0].name.clone());
 
     let no_db_clone = matches.get_flag("no-db-clone");
     let no_repo_clone = matches.get_flag("no-repo-clone");
+    let no_verify = matches.get_flag("no-verify");
     let force = matches.get_flag("force");
+    let resume = matches.get_flag("resume");
     let no_env = matches.get_flag("no-env");
     let no_defaults = matches.get_flag("no-defaults");
     let no_values = matches.get_flag("no-values");
+    let quiet = matches.get_flag("quiet");
+    let post_clone = matches.get_one::<String>("post-clone").cloned();
+    let strict = matches.get_flag("strict");
+    let yes = matches.get_flag("yes");
+    let repos_env = matches.get_one::<String>("env").cloned();
+    let submodules = matches.get_flag("submodules");
+    let no_prompt = matches.get_flag("no-prompt");
+    let preflight = !matches.get_flag("no-preflight");
+    let clone_concurrency = matches
+        .get_one::<u64>("clone-concurrency")
+        .copied()
+        .unwrap_or(4) as usize;
+    let clone_depth = match matches.get_one::<u32>("depth") {
+        Some(depth) => Some(*depth),
+        None if matches.get_flag("shallow") => Some(1),
+        None => None,
+    };
+    let db_retries = matches
+        .get_one::<u32>("db-retries")
+        .copied()
+        .unwrap_or(db::CONFLICT_RETRIES);
+    let db_startup_timeout = matches
+        .get_one::<u64>("db-startup-timeout")
+        .map(|secs| Duration::from_secs(*secs))
+        .unwrap_or(dolt::DEFAULT_STARTUP_TIMEOUT);
+    let progress_bars = !quiet && progress::bars_enabled(matches.get_flag("no-progress"));
+    let format = format::get_format(matches)?;
+    let repo_path_template = repo_path_template_arg(matches, Path::new(dir), resume)?;
+
+    // Answers passed with --set take precedence over the ones from --answers.
+    let mut answers = match matches.get_one::<String>("answers") {
+        Some(path) if !no_env => Answers::from_path(path)?,
+        _ => Answers::default(),
+    };
+    answers.merge(Answers::from_args(
+        matches.get_many::<String>("set").unwrap_or_default(),
+    )?);
+    let timeout = matches
+        .get_one::<u64>("timeout")
+        .map(|secs| Duration::from_secs(*secs));
     let defaults_filename = matches.get_one::<String>("defaults-filename").ok_or_else(|| {
                 anyhow::anyhow!("No defaults filename specified. Use --defaults-filename to specify a defaults filename.")
             })?;
+    let defaults_filename =
+        filename_for_format(matches, "defaults-filename", defaults_filename, format)?;
     let values_filename = matches
         .get_one::<String>("values-filename")
         .ok_or_else(|| {
@@ -239,27 +1282,82 @@ pub async fn init_site(matches: &ArgMatches) -> Result<()> {
                 "No values filename specified. Use --values-filename to specify a values filename."
             )
         })?;
+    let values_filename = filename_for_format(matches, "values-filename", values_filename, format)?;
 
     let opts = InitOpts {
         dir: dir.clone(),
-        db_repo: db_repo.clone(),
-        db_name: db_name.clone(),
+        databases,
+        render_db,
         force,
+        resume,
         no_db_clone,
         no_repo_clone,
+        no_verify,
         no_env,
         no_defaults,
         no_values,
-        defaults_filename: defaults_filename.clone(),
-        values_filename: values_filename.clone(),
+        defaults_filename,
+        values_filename,
+        deployment_dir: deployment_dir_arg(matches, Path::new(dir))?,
+        sections: matches
+            .get_many::<String>("section")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        quiet,
+        post_clone,
+        strict,
+        timeout,
+        yes,
+        repos_env,
+        submodules,
+        repo_path_template,
+        answers,
+        no_prompt,
+        preflight,
+        clone_concurrency,
+        clone_depth,
+        progress_bars,
+        db_retries,
+        db_startup_timeout,
+        format,
+    };
+
+    // Ask before the timeout starts so that waiting on the prompt doesn't
+    // count against it.
+    confirm_overwrite(&opts)?;
+
+    // The resources are shut down before the result of the init is checked
+    // so that nothing is left running when the init fails or times out. The
+    // init's own error is the one that's returned if both fail.
+    let mut resources = InitResources::default();
+    let mut warnings = Warnings::default();
+    let result = match opts.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, init(&opts, &mut resources, &mut warnings))
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow::anyhow!(
+                    "site init did not finish within {} seconds",
+                    timeout.as_secs()
+                ))
+            }),
+        None => init(&opts, &mut resources, &mut warnings).await,
     };
-    init(&opts).await?;
+    let shutdown = resources.shutdown(&opts).await;
+    warnings.print_summary();
+    if let (Err(_), Err(e)) = (&result, &shutdown) {
+        eprintln!("Failed to shut down the databases: {:#}", e);
+    }
+    result?;
+    shutdown?;
+    warnings.check()?;
 
     println!("Site initialized in {}", dir);
 
     Ok(())
 }
 
+#[cfg(feature = "kubernetes")]
 pub async fn deploy_site(matches: &ArgMatches) -> Result<()> {
     let dir = matches.get_one::<PathBuf>("dir").ok_or_else(|| {
         anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
@@ -291,6 +1389,13 @@ pub async fn deploy_site(matches: &ArgMatches) -> Result<()> {
             )
         })?;
 
+    let repo_path_template = repo_path_template_arg(matches, dir, true)?;
+
+    let dry_run = matches.get_flag("dry-run");
+    let overrides = crate::config_values::answers::parse_set_args(
+        matches.get_many::<String>("set").unwrap_or_default(),
+    )?;
+
     let dir_canon = dir.canonicalize()?;
     let opts = DeployOpts {
         site_dirpath: dir_canon.clone(),
@@ -298,11 +1403,751 @@ pub async fn deploy_site(matches: &ArgMatches) -> Result<()> {
         db_name: db_name.clone(),
         services,
         defaults_filepath: Path::new(&dir_canon).join(defaults_filename),
-        values_filepath: Path::new(&dir_canon).join(values_filename),
-        builds_dirpath: Path::new(&dir_canon).join("builds"),
+        values_filepath: deployment_dir_arg(matches, &dir_canon)?.join(values_filename),
+        repo_path_template,
+        dry_run,
+        overrides,
     };
 
     deploy(&opts).await?;
 
     Ok(())
 }
+
+#[cfg(not(feature = "kubernetes"))]
+pub async fn deploy_site(_matches: &ArgMatches) -> Result<()> {
+    Err(ops::kubernetes_unsupported("site deploy"))
+}
+
+pub fn update_site(matches: &ArgMatches) -> Result<()> {
+    let dir = matches.get_one::<PathBuf>("dir").ok_or_else(|| {
+        anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
+    })?;
+
+    let db_name = matches.get_one::<String>("db-name").ok_or_else(|| {
+        anyhow::anyhow!("No Dolt DB name specified. Use -n or --db-name to specify a Dolt DB name.")
+    })?;
+
+    let db_dir = dir.join(db_name);
+    if !db_dir.is_dir() {
+        return Err(exit::Error::Usage(format!(
+            "There's no database at {}. Run site init to create the site first.",
+            db_dir.display()
+        ))
+        .into());
+    }
+    let db_dir = db_dir
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("failed to get database directory as string"))?;
+
+    let before = dolt::local_head_commit(db_dir)?;
+    println!("Pulling the latest releases into {}...", db_dir);
+    dolt::pull(db_dir)?;
+    let after = dolt::local_head_commit(db_dir)?;
+
+    if before == after {
+        println!("{}", style("Already up to date.").green());
+    } else {
+        println!("{} {} -> {}", style("Updated:").green(), before, after);
+    }
+
+    Ok(())
+}
+
+// Prints how many of the keys in each section have a value set for the
+// environment, highlighting the sections where some keys fall back to their
+// defaults.
+async fn print_section_counts(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    env_id: i32,
+    indent: &str,
+) -> Result<()> {
+    for (section, total, populated) in db::list_sections_with_counts(tx, env_id).await? {
+        let counts = format!("{}/{}", populated, total);
+        let counts = if populated < total {
+            style(counts).yellow()
+        } else {
+            style(counts).green()
+        };
+        println!("{}{}: {} key(s) set", indent, section, counts);
+    }
+    Ok(())
+}
+
+pub async fn lint_site(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow::anyhow!("No environment specified. Use -e or --env to specify an environment.")
+    })?;
+
+    let mut tx = pool.begin().await?;
+    let findings = lint::lint_environment(&mut tx, env).await?;
+    let env_id = db::get_env_id(&mut tx, env).await?;
+    println!("{}", style("Sections:").bold());
+    print_section_counts(&mut tx, env_id, "  ").await?;
+    println!();
+    tx.commit().await?;
+
+    let errors: Vec<_> = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .collect();
+    let warnings: Vec<_> = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Warning)
+        .collect();
+
+    for (label, group) in [
+        (style("Errors:").red().bold(), &errors),
+        (style("Warnings:").yellow().bold(), &warnings),
+    ] {
+        if !group.is_empty() {
+            println!("{}", label);
+            for finding in group.iter() {
+                println!("  {}", finding);
+            }
+            println!();
+        }
+    }
+
+    let summary = format!(
+        "{} error(s), {} warning(s) found for the {} environment.",
+        errors.len(),
+        warnings.len(),
+        env
+    );
+    if !errors.is_empty() {
+        println!("{}", style(summary).red());
+    } else if !warnings.is_empty() {
+        println!("{}", style(summary).yellow());
+    } else {
+        println!("{}", style(summary).green());
+    }
+
+    if !errors.is_empty() {
+        return Err(exit::Error::Usage(format!("lint failed for the {} environment", env)).into());
+    }
+    if !warnings.is_empty() && crate::warnings::is_strict() {
+        return Err(crate::warnings::strict_error(warnings.len()));
+    }
+
+    Ok(())
+}
+
+pub async fn diff_envs(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let envs = matches
+        .get_many::<String>("env")
+        .map(|envs| envs.collect::<Vec<_>>())
+        .unwrap_or_default();
+    let [left, right] = envs[..] else {
+        return Err(exit::Error::Usage(
+            "Two environments are needed. Use -e or --env twice to specify them.".to_string(),
+        )
+        .into());
+    };
+    let only_differences = matches.get_flag("only-differences");
+
+    let entries = ops::diff_environments(pool, left, right).await?;
+
+    println!("{}", style(format!("--- {}", left)).red());
+    println!("{}", style(format!("+++ {}", right)).green());
+    for entry in &entries {
+        let name = format!("{}.{}", entry.section, entry.key);
+        match &entry.change {
+            diff::Change::Same(value) => {
+                if !only_differences {
+                    println!("{}", style(format!("  {}: {}", name, value)).dim());
+                }
+            }
+            diff::Change::Changed { left, right } => println!(
+                "{}",
+                style(format!("~ {}: {} -> {}", name, left, right)).yellow()
+            ),
+            diff::Change::OnlyLeft(value) => {
+                println!("{}", style(format!("- {}: {}", name, value)).red())
+            }
+            diff::Change::OnlyRight(value) => {
+                println!("{}", style(format!("+ {}: {}", name, value)).green())
+            }
+        }
+    }
+
+    let differences = entries.iter().filter(|e| e.is_difference()).count();
+    println!();
+    if differences == 0 {
+        println!(
+            "{}",
+            style(format!("{} and {} have the same values.", left, right)).green()
+        );
+        return Ok(());
+    }
+
+    let summary = format!(
+        "{} value(s) differ between {} and {}.",
+        differences, left, right
+    );
+    println!("{}", style(summary).yellow());
+    Err(exit::Error::Drift(format!("the {} and {} environments differ", left, right)).into())
+}
+
+// Renders the values for the environment out to the values file. Returns
+// whether the file was written, which it isn't if nothing changed unless
+// always_write is true.
+async fn render_values_file(
+    pool: &Pool<Postgres>,
+    env: &str,
+    sections: &[String],
+    path: &Path,
+    format: Option<Format>,
+    always_write: bool,
+    redact_secrets: bool,
+) -> Result<bool> {
+    let opts = config::SectionOptions::new_from_db(pool, env).await?;
+    ops::render_section_values(
+        pool,
+        env,
+        &opts,
+        sections,
+        Some(path.to_path_buf()),
+        format,
+        always_write,
+        redact_secrets,
+    )
+    .await
+}
+
+// Returns a usage error listing the valid sections if any of the sections
+// aren't in the database.
+async fn check_sections(pool: &Pool<Postgres>, sections: &[String]) -> Result<()> {
+    if sections.is_empty() {
+        return Ok(());
+    }
+    let mut tx = pool.begin().await?;
+    let known = db::list_sections(&mut tx).await?;
+    tx.commit().await?;
+    if let Some(unknown) = sections.iter().find(|s| !known.contains(s)) {
+        return Err(exit::Error::Usage(format!(
+            "Unknown section {}. Valid sections: {}",
+            unknown,
+            known.join(", ")
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+// The deployment used when --deployment isn't given. Its values file stays at
+// the root of the site directory, where sites with a single deployment have
+// always kept it.
+const DEFAULT_DEPLOYMENT: &str = "default";
+
+// Returns the directory a deployment's values file goes in. The defaults and
+// the database are shared by every deployment at the root of the site.
+fn deployment_dir(site_dir: &Path, deployment: &str) -> Result<PathBuf> {
+    if deployment.is_empty()
+        || deployment == "."
+        || deployment == ".."
+        || deployment.contains(['/', '\\'])
+    {
+        return Err(exit::Error::Usage(format!(
+            "Invalid deployment name '{}'. It can't be empty or contain path separators.",
+            deployment
+        ))
+        .into());
+    }
+    if deployment == DEFAULT_DEPLOYMENT {
+        return Ok(site_dir.to_path_buf());
+    }
+    Ok(site_dir.join("deployments").join(deployment))
+}
+
+// Returns the directory for the deployment named by --deployment.
+fn deployment_dir_arg(matches: &ArgMatches, site_dir: &Path) -> Result<PathBuf> {
+    let deployment = matches
+        .get_one::<String>("deployment")
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_DEPLOYMENT);
+    deployment_dir(site_dir, deployment)
+}
+
+// Returns the path of the values file for one environment when rendering all
+// of them, e.g. deployment-qa.yaml for deployment.yaml.
+fn env_values_path(dir: &Path, values_filename: &Path, env: &str) -> PathBuf {
+    let stem = values_filename
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match values_filename.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, env, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, env),
+    };
+    dir.join(values_filename.with_file_name(name))
+}
+
+// Renders the values file for every environment, reporting how each one went.
+// Returns an error listing the environments that failed, once all of them
+// have been tried.
+async fn render_all_environments(
+    pool: &Pool<Postgres>,
+    dir: &Path,
+    values_filename: &Path,
+    sections: &[String],
+    format: Option<Format>,
+    always_write: bool,
+    redact_secrets: bool,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut envs = db::list_envs(&mut tx).await?;
+    tx.commit().await?;
+    envs.sort();
+
+    let mut failed = Vec::new();
+    for env in &envs {
+        let values_path = env_values_path(dir, values_filename, env);
+        let rendered = render_values_file(
+            pool,
+            env,
+            sections,
+            &values_path,
+            format,
+            always_write,
+            redact_secrets,
+        )
+        .await;
+        match rendered {
+            Ok(true) => println!(
+                "  {} {}: rendered {}",
+                style("✓").green(),
+                env,
+                values_path.display()
+            ),
+            Ok(false) => println!(
+                "  {} {}: {} is unchanged",
+                style("✓").green(),
+                env,
+                values_path.display()
+            ),
+            Err(e) => {
+                println!("  {} {}: {:#}", style("✗").red(), env, e);
+                failed.push(env.as_str());
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} of {} environment(s) failed to render: {}",
+            failed.len(),
+            envs.len(),
+            failed.join(", ")
+        ));
+    }
+    println!("Rendered {} environment(s).", envs.len());
+
+    Ok(())
+}
+
+fn timestamp() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub async fn render_site(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let dir = matches.get_one::<PathBuf>("dir").ok_or_else(|| {
+        anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
+    })?;
+
+    let values_filename = matches
+        .get_one::<PathBuf>("values-filename")
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No values filename specified. Use --values-filename to specify a values filename."
+            )
+        })?;
+
+    let deployment_dir = deployment_dir_arg(matches, dir)?;
+    std::fs::create_dir_all(&deployment_dir)
+        .map_err(|e| anyhow::anyhow!("failed to create {}: {}", deployment_dir.display(), e))?;
+    let dir = deployment_dir.as_path();
+
+    let watch = matches.get_flag("watch");
+    let always_write = matches.get_flag("always-write");
+    let redact_secrets = matches.get_flag("redact-secrets");
+    let format = format::get_format(matches)?;
+
+    let sections = matches
+        .get_many::<String>("section")
+        .unwrap_or_default()
+        .cloned()
+        .collect::<Vec<_>>();
+    check_sections(pool, &sections).await?;
+
+    if matches.get_flag("all-environments") {
+        return render_all_environments(
+            pool,
+            dir,
+            values_filename,
+            &sections,
+            format,
+            always_write,
+            redact_secrets,
+        )
+        .await;
+    }
+
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow::anyhow!("No environment specified. Use -e or --env to specify an environment.")
+    })?;
+    let interval = Duration::from_secs(*matches.get_one::<u64>("interval").unwrap_or(&2));
+    let debounce = Duration::from_millis(*matches.get_one::<u64>("debounce").unwrap_or(&500));
+
+    let values_path = match matches.get_one::<PathBuf>("out") {
+        Some(out) => out.clone(),
+        None => dir.join(values_filename),
+    };
+    if render_values_file(
+        pool,
+        env,
+        &sections,
+        &values_path,
+        format,
+        always_write,
+        redact_secrets,
+    )
+    .await?
+    {
+        println!("[{}] Rendered {}", timestamp(), values_path.display());
+    } else {
+        println!("[{}] {} is unchanged", timestamp(), values_path.display());
+    }
+
+    if !watch {
+        return Ok(());
+    }
+
+    println!("Watching for new commits. Press Ctrl-C to stop.");
+    let mut last_head = dolt::head_commit(pool).await?;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    'watch: loop {
+        tokio::select! {
+            _ = &mut ctrl_c => break 'watch,
+            _ = tokio::time::sleep(interval) => {}
+        }
+
+        let mut head = dolt::head_commit(pool).await?;
+        if head == last_head {
+            continue;
+        }
+
+        // Wait for the HEAD commit to settle so a burst of commits only
+        // triggers a single render.
+        loop {
+            tokio::select! {
+                _ = &mut ctrl_c => break 'watch,
+                _ = tokio::time::sleep(debounce) => {}
+            }
+
+            let settled = dolt::head_commit(pool).await?;
+            if settled == head {
+                break;
+            }
+            head = settled;
+        }
+
+        if render_values_file(
+            pool,
+            env,
+            &sections,
+            &values_path,
+            format,
+            always_write,
+            redact_secrets,
+        )
+        .await?
+        {
+            println!(
+                "[{}] Re-rendered {} for commit {}",
+                timestamp(),
+                values_path.display(),
+                head
+            );
+        } else {
+            println!(
+                "[{}] {} is unchanged at commit {}",
+                timestamp(),
+                values_path.display(),
+                head
+            );
+        }
+        last_head = head;
+    }
+
+    println!("Stopped watching.");
+
+    Ok(())
+}
+
+pub fn print_schema(matches: &ArgMatches) -> Result<()> {
+    let schema = serde_json::to_string_pretty(&schema::json_schema()?)?;
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => {
+            std::fs::write(path, format!("{}\n", schema))?;
+            println!("Wrote the schema to {}", path.display());
+        }
+        None => println!("{}", schema),
+    }
+    Ok(())
+}
+
+pub async fn list_types(pool: &Pool<Postgres>) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let value_types = db::list_value_types(&mut tx).await?;
+    tx.commit().await?;
+
+    for value_type in value_types {
+        println!("{}", value_type);
+    }
+
+    Ok(())
+}
+
+pub async fn site_status(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let single_env = matches.get_one::<String>("env").is_some();
+    let envs = match matches.get_one::<String>("env") {
+        Some(env) => vec![env.clone()],
+        None => {
+            println!(
+                "{} {}",
+                style("Environments:").bold(),
+                db::count_environments(&mut tx).await?
+            );
+            db::list_envs(&mut tx).await?
+        }
+    };
+
+    for env in envs {
+        let env_id = ops::require_environment(&mut tx, &env).await?.id;
+        let count = db::count_config_values_for_env(&mut tx, env_id).await?;
+        let count = if count == 0 {
+            style(count).yellow()
+        } else {
+            style(count).green()
+        };
+        println!("  {}: {} config value(s)", style(&env).bold(), count);
+
+        // Listing the sections for every environment would bury the summary.
+        if single_env {
+            print_section_counts(&mut tx, env_id, "    ").await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    // The database might not be in the site directory, such as when it's
+    // served from somewhere else, in which case there's nothing to check.
+    let dir = matches
+        .get_one::<PathBuf>("dir")
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let db_name = matches
+        .get_one::<String>("db-name")
+        .cloned()
+        .unwrap_or_else(|| "de_releases".to_string());
+    let db_dir = dir.join(&db_name);
+    if db_dir.is_dir() {
+        print_dolt_status(&db_dir.to_string_lossy())?;
+    }
+
+    Ok(())
+}
+
+// Prints the tables with uncommitted changes in the Dolt database.
+fn print_dolt_status(db_dir: &str) -> Result<()> {
+    let status = dolt::status(db_dir)?;
+    if !status.is_dirty() {
+        println!(
+            "{} {}",
+            style("Uncommitted changes:").bold(),
+            style("none").green()
+        );
+        return Ok(());
+    }
+
+    println!("{}", style("Uncommitted changes:").bold());
+    for (label, tables) in [("staged", &status.staged), ("unstaged", &status.unstaged)] {
+        for table in tables {
+            println!(
+                "  {} ({}, {})",
+                style(&table.table).yellow(),
+                table.status,
+                label
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+pub async fn edit_site(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let env = matches.get_one::<String>("env").ok_or_else(|| {
+        anyhow::anyhow!("No environment specified. Use -e or --env to specify an environment.")
+    })?;
+    let author = commit::get_author(matches)?;
+    let template = matches.get_one::<String>("message").ok_or_else(|| {
+        anyhow::anyhow!(
+            "No commit message specified. Use --message <message> to specify a message."
+        )
+    })?;
+
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(exit::Error::Usage(
+            "site edit needs an interactive terminal. Use mgmt configs values set to change values from a script."
+                .to_string(),
+        )
+        .into());
+    }
+
+    let mut tx = pool.begin().await?;
+    ops::require_environment(&mut tx, env).await?;
+    let opts: config::SectionOptions = db::get_feature_flags(&mut tx, env).await?.into();
+    let entries = editor::load_entries(&mut tx, env, &opts).await?;
+    tx.commit().await?;
+
+    let changes = match editor::run(env, entries)? {
+        Some(changes) => changes,
+        None => {
+            println!("Quit without saving. No values were changed.");
+            return Ok(());
+        }
+    };
+
+    // The edits are applied together, so a failure partway through doesn't
+    // leave some of them saved.
+    let actor = db::default_actor();
+    db::transaction(pool, |tx| {
+        let (env, changes, actor) = (env.clone(), changes.clone(), actor.clone());
+        Box::pin(async move {
+            for entry in &changes {
+                ops::set_value_in_tx(
+                    tx,
+                    &env,
+                    &entry.section,
+                    &entry.key,
+                    &entry.value,
+                    entry.value_type.as_str(),
+                    &actor,
+                )
+                .await?;
+            }
+            Ok(())
+        })
+    })
+    .await?;
+
+    let message = commit::render_message(template, env, "");
+    dolt::commit(pool, &message, author.as_ref()).await?;
+    println!("Committed {} change(s) to the database.", changes.len());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+pub async fn edit_site(_pool: &Pool<Postgres>, _matches: &ArgMatches) -> Result<()> {
+    Err(ops::feature_unsupported("site edit", "tui"))
+}
+
+pub async fn export_values(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
+    let out = matches.get_one::<PathBuf>("out").cloned();
+    let format = format::get_format(matches)?;
+
+    if !ops::export_all_values(pool, out.clone(), format).await? {
+        if let Some(out) = out {
+            println!("{} is unchanged", out.display());
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn export_site(matches: &ArgMatches) -> Result<()> {
+    let dir = matches.get_one::<PathBuf>("dir").ok_or_else(|| {
+        anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
+    })?;
+
+    let db_name = matches.get_one::<String>("db-name").ok_or_else(|| {
+        anyhow::anyhow!("No Dolt DB name specified. Use -n or --db-name to specify a Dolt DB name.")
+    })?;
+
+    let out = matches.get_one::<PathBuf>("out").ok_or_else(|| {
+        anyhow::anyhow!("No bundle file specified. Use -o or --out to specify the bundle file.")
+    })?;
+
+    let files = ["defaults-filename", "values-filename"]
+        .iter()
+        .filter_map(|id| matches.get_one::<PathBuf>(id).cloned())
+        .collect::<Vec<_>>();
+
+    let template = RepoPathTemplate::load(dir)?;
+    let manifest = bundle::export(dir, db_name, &files, &template, out)?;
+
+    println!(
+        "Exported {} at commit {} with {} file(s) and {} repo(s) to {}",
+        manifest.db_name,
+        manifest.db_commit,
+        manifest.files.len(),
+        manifest.repos.len(),
+        out.display()
+    );
+
+    Ok(())
+}
+
+pub async fn import_site(matches: &ArgMatches) -> Result<()> {
+    let bundle_path = matches.get_one::<PathBuf>("bundle").ok_or_else(|| {
+        anyhow::anyhow!("No bundle file specified. Use -b or --bundle to specify the bundle file.")
+    })?;
+
+    let dir = matches.get_one::<PathBuf>("dir").ok_or_else(|| {
+        anyhow::anyhow!("No directory specified. Use -d or --dir to specify a directory.")
+    })?;
+
+    let no_repo_clone = matches.get_flag("no-repo-clone");
+    let submodules = matches.get_flag("submodules");
+
+    println!(
+        "Unpacking {} into {}...",
+        bundle_path.display(),
+        dir.display()
+    );
+    let manifest = bundle::import(bundle_path, dir)?;
+    println!(
+        "Done unpacking {} at commit {}.\n",
+        manifest.db_name, manifest.db_commit
+    );
+
+    // Record the template in the site so that the commands run on it later
+    // find the repos where they're cloned.
+    let template = RepoPathTemplate::parse(&manifest.repo_path_template)?;
+    template.save(dir)?;
+
+    if no_repo_clone {
+        println!("Skipping cloning of {} repo(s)", manifest.repos.len());
+    } else {
+        println!("Cloning the repos...");
+        for repo in &manifest.repos {
+            if bundle::restore_repo(dir, repo, &template, submodules)? {
+                println!("Checked out {} at {}", repo.name, repo.commit);
+            } else {
+                println!("{} is already at {}", repo.name, repo.commit);
+            }
+        }
+        println!("Done cloning the repos.\n");
+    }
+
+    println!("Site imported into {}", dir.display());
+
+    Ok(())
+}
@@ -17,7 +17,7 @@ fn base64_encode(value: &Value, _: &HashMap<String, Value>) -> TeraResult<Value>
 }
 
 /// Creates a new Tera instance with the base64_encode filter registered.
-fn new_tera() -> Tera {
+pub(crate) fn new_tera() -> Tera {
     let mut tera = Tera::default();
     tera.register_filter("base64_encode", base64_encode);
     tera
@@ -82,13 +82,9 @@ pub fn render_template(
     values_path: &PathBuf,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
-    let defaults_file = fs::File::open(defaults_path)?;
-    let mut default_values: ConfigValues = serde_yaml::from_reader(defaults_file)?;
-    default_values.set_section_options(default_values.generate_section_options());
+    let default_values = ConfigValues::from_file(defaults_path)?;
 
-    let values_file = fs::File::open(values_path)?;
-    let mut values: ConfigValues = serde_yaml::from_reader(values_file)?;
-    values.set_section_options(values.generate_section_options());
+    let values = ConfigValues::from_file(values_path)?;
 
     Ok(render_t(template_path, &default_values, &values, out_path)?)
 }
@@ -127,13 +123,9 @@ pub fn render_template_dir(
     values_path: &PathBuf,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
-    let defaults_file = fs::File::open(defaults_path)?;
-    let mut defaults_values: ConfigValues = serde_yaml::from_reader(defaults_file)?;
-    defaults_values.set_section_options(defaults_values.generate_section_options());
+    let defaults_values = ConfigValues::from_file(defaults_path)?;
 
-    let values_file = fs::File::open(values_path)?;
-    let mut values: ConfigValues = serde_yaml::from_reader(values_file)?;
-    values.set_section_options(values.generate_section_options());
+    let values = ConfigValues::from_file(values_path)?;
 
     Ok(render_d(
         templates_path,
@@ -0,0 +1,108 @@
+//! # Kubectl
+//!
+//! Wrappers around the `kubectl` commands used while deploying. They're run
+//! with the `cmd` runner, so a failure includes the command line and the
+//! stderr kubectl printed.
+use crate::cmd::Cmd;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Duration;
+
+/// Creates the namespace if it doesn't already exist. Returns true if the
+/// namespace was created.
+///
+/// # Examples
+/// ```ignore
+///     if kubectl::ensure_namespace("qa")? {
+///         println!("Created namespace qa");
+///     }
+/// ```
+pub fn ensure_namespace(ns: &str) -> Result<bool> {
+    let existing = Cmd::new("kubectl")
+        .args(["get", "namespace", ns, "--ignore-not-found", "-o", "name"])
+        .output()
+        .with_context(|| format!("failed to look up namespace {}", ns))?;
+    if !existing.trim().is_empty() {
+        return Ok(false);
+    }
+
+    Cmd::new("kubectl")
+        .args(["create", "namespace", ns])
+        .output()
+        .with_context(|| format!("failed to create namespace {}", ns))?;
+
+    Ok(true)
+}
+
+/// Applies the manifest at `manifest_path` to the namespace, printing what
+/// kubectl reports it changed.
+///
+/// # Examples
+/// ```ignore
+///     kubectl::apply(Path::new("configs/qa/secrets/apps.yaml"), "qa")?;
+/// ```
+pub fn apply(manifest_path: &Path, ns: &str) -> Result<()> {
+    let path = manifest_path
+        .to_str()
+        .context("failed to get the manifest path as a string")?;
+    let output = Cmd::new("kubectl")
+        .args(["apply", "--namespace", ns, "-f", path])
+        .output()
+        .with_context(|| format!("failed to apply {} to {}", path, ns))?;
+    print!("{}", output);
+
+    Ok(())
+}
+
+/// Returns the manifest for a generic secret holding each of the files in
+/// `dir`, without creating it in the cluster.
+///
+/// # Examples
+/// ```ignore
+///     let manifest = kubectl::secret_manifest("service-configs", Path::new("configs/qa"), "qa")?;
+/// ```
+pub fn secret_manifest(name: &str, dir: &Path, ns: &str) -> Result<String> {
+    let dir = dir
+        .to_str()
+        .context("failed to get the secret directory as a string")?;
+    Cmd::new("kubectl")
+        .args([
+            "create",
+            "secret",
+            "generic",
+            name,
+            "--namespace",
+            ns,
+            &format!("--from-file={}", dir),
+            "--dry-run=client",
+            "-o",
+            "yaml",
+        ])
+        .output()
+        .with_context(|| format!("failed to render the {} secret for {}", name, ns))
+}
+
+/// Waits up to `timeout` for the rollout of a deployment to finish, returning
+/// an error if it fails or doesn't finish in time. kubectl treats a timeout of
+/// zero as no timeout at all, so the timeout is rounded up to a whole second.
+///
+/// # Examples
+/// ```ignore
+///     kubectl::rollout_status("apps", "qa", Duration::from_secs(300))?;
+/// ```
+pub fn rollout_status(deployment: &str, ns: &str, timeout: Duration) -> Result<()> {
+    let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+    Cmd::new("kubectl")
+        .args([
+            "rollout",
+            "status",
+            &format!("deployment/{}", deployment),
+            "--namespace",
+            ns,
+            &format!("--timeout={}s", secs.max(1)),
+        ])
+        .output()
+        .with_context(|| format!("deployment {} in {} did not become ready", deployment, ns))?;
+
+    Ok(())
+}
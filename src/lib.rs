@@ -1,10 +1,33 @@
+#[cfg(feature = "kubernetes")]
 pub mod app;
+pub mod bundle;
+pub mod changelog;
 pub mod cli;
+pub mod cmd;
+pub mod color;
+pub mod commit;
 pub mod config_values;
 pub mod configs;
 pub mod db;
+#[cfg(feature = "kubernetes")]
 pub mod deploy;
 pub mod dolt;
+#[cfg(feature = "tui")]
+pub mod editor;
+pub mod exit;
 pub mod git;
 pub mod handlers;
+#[cfg(feature = "kubernetes")]
+pub mod kubectl;
+pub mod lint;
+pub mod logging;
+pub mod manifests;
 pub mod ops;
+pub mod output;
+pub mod preflight;
+pub mod progress;
+pub mod repo_path;
+pub mod selftest;
+pub mod suggest;
+pub mod versions;
+pub mod warnings;
@@ -0,0 +1,223 @@
+//! # Lint
+//!
+//! Checks the configuration for an environment for the classes of mistakes
+//! that tend to break deployments: missing required values, values that don't match
+//! their type, malformed URLs and ports, duplicated keys, invalid namespaces,
+//! and services that reference repositories that don't exist.
+use crate::config_values::{config::SectionOptions, schema, value_type::ValueType};
+use crate::db::{self, ConfigurationValue};
+use crate::ops;
+use sqlx::{Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A single problem found while linting an environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(location: &str, message: String) -> Self {
+        Finding {
+            severity: Severity::Error,
+            location: location.to_string(),
+            message,
+        }
+    }
+
+    fn warning(location: &str, message: String) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            location: location.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+fn is_url_key(key: &str) -> bool {
+    key.ends_with("URL") || key.ends_with("URI") || key.ends_with("Url")
+}
+
+fn is_port_key(key: &str) -> bool {
+    key == "Port" || key.ends_with(".Port")
+}
+
+/// Returns whether the namespace is a valid Kubernetes namespace name, which
+/// must be an RFC 1123 DNS label.
+pub fn is_valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && namespace.len() <= 63
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !namespace.starts_with('-')
+        && !namespace.ends_with('-')
+}
+
+// Checks a single effective value (the environment's value if it has one,
+// otherwise the default). An empty value is only a problem for the keys in
+// `required`; optional ones are often left blank on purpose.
+fn check_value(
+    cfg: &ConfigurationValue,
+    required: &HashSet<(String, String)>,
+    findings: &mut Vec<Finding>,
+) {
+    let location = format!("{}.{}", cfg.section, cfg.key);
+
+    if cfg.value.is_empty() {
+        if required.contains(&(cfg.section.clone(), cfg.key.clone())) {
+            findings.push(Finding::error(&location, "no value is set".to_string()));
+        }
+        return;
+    }
+
+    match cfg.value_type.parse::<ValueType>() {
+        Ok(value_type) => {
+            if let Err(e) = value_type.validate(&cfg.value) {
+                findings.push(Finding::error(&location, e.to_string()));
+            }
+        }
+        Err(e) => findings.push(Finding::error(&location, e.to_string())),
+    }
+
+    if is_url_key(&cfg.key) {
+        if let Err(e) = url::Url::parse(&cfg.value) {
+            findings.push(Finding::warning(
+                &location,
+                format!("'{}' does not parse as a URL: {}", cfg.value, e),
+            ));
+        }
+    }
+
+    if is_port_key(&cfg.key) {
+        match cfg.value.parse::<u16>() {
+            Ok(0) | Err(_) => findings.push(Finding::error(
+                &location,
+                format!("'{}' is not a valid port number (1-65535)", cfg.value),
+            )),
+            Ok(_) => (),
+        }
+    }
+}
+
+/// Runs all of the lint checks against an environment and returns the
+/// findings, errors first.
+///
+/// # Examples
+/// ```ignore
+///     let mut tx = pool.begin().await?;
+///     let findings = lint::lint_environment(&mut tx, "qa").await?;
+///     tx.commit().await?;
+/// ```
+pub async fn lint_environment(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    // Make sure the environment exists before doing anything else.
+    let environment = ops::require_environment(tx, env).await?;
+
+    let section_options: SectionOptions = match db::get_feature_flags(tx, env).await {
+        Ok(ff) => ff.into(),
+        Err(_) => {
+            findings.push(Finding::warning(
+                env,
+                "no feature flags are set, so optional sections were not checked".to_string(),
+            ));
+            SectionOptions::default()
+        }
+    };
+
+    // Duplicate section.key entries for the environment.
+    let env_values = db::list_config_values(tx, Some(env), None, None).await?;
+    let mut counts: HashMap<(String, String), usize> = HashMap::new();
+    for cfg in &env_values {
+        *counts
+            .entry((cfg.section.clone(), cfg.key.clone()))
+            .or_default() += 1;
+    }
+    for ((section, key), count) in counts.iter().filter(|(_, c)| **c > 1) {
+        findings.push(Finding::error(
+            &format!("{}.{}", section, key),
+            format!("is set {} times in the environment", count),
+        ));
+    }
+
+    // Check the effective value of every default in the included sections.
+    let env_values: HashMap<(String, String), ConfigurationValue> = env_values
+        .into_iter()
+        .map(|cfg| ((cfg.section.clone(), cfg.key.clone()), cfg))
+        .collect();
+    let required = schema::required_keys()?;
+    let defaults = db::list_default_config_values(tx, None, None).await?;
+    for default in defaults
+        .into_iter()
+        .filter(|cfg| section_options.include_section(&cfg.section))
+    {
+        let cfg = env_values
+            .get(&(default.section.clone(), default.key.clone()))
+            .unwrap_or(&default);
+        check_value(cfg, &required, &mut findings);
+    }
+
+    let namespace = environment.namespace;
+    if !is_valid_namespace(&namespace) {
+        findings.push(Finding::error(
+            env,
+            format!(
+                "namespace '{}' is not a valid Kubernetes namespace",
+                namespace
+            ),
+        ));
+    }
+
+    let repo_ids: HashSet<i32> = db::list_repos(tx)
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+    for svc in db::list_services(tx, env).await? {
+        if !repo_ids.contains(&svc.repo_id) {
+            findings.push(Finding::error(
+                &svc.name,
+                format!(
+                    "service references repository {} which does not exist",
+                    svc.repo_id
+                ),
+            ));
+        }
+    }
+
+    findings.sort_by(|a, b| {
+        a.severity
+            .cmp(&b.severity)
+            .then_with(|| a.location.cmp(&b.location))
+    });
+
+    Ok(findings)
+}
@@ -0,0 +1,56 @@
+//! # Logging
+//!
+//! Diagnostic logging with `tracing`. Logs go to stderr so they never end up
+//! mixed into output that's meant to be piped, like a rendered values file.
+//! Only warnings are logged by default; each `-v` raises the level by one,
+//! and `--log-format json` writes one JSON object per line for CI to parse.
+use anyhow::{anyhow, Result};
+use tracing::level_filters::LevelFilter;
+
+/// The values accepted by the `--log-format` flag.
+pub const FORMATS: [&str; 2] = ["text", "json"];
+
+// Returns the most detailed level that's logged for the number of times -v
+// was passed.
+fn level(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Installs the global subscriber that writes the logs to stderr. Should only
+/// be called once, before anything is logged.
+///
+/// # Examples
+/// ```ignore
+///     logging::init(2, "json")?;
+///     tracing::debug!(command = "git status", "running command");
+/// ```
+pub fn init(verbosity: u8, format: &str) -> Result<()> {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level(verbosity))
+        .with_writer(std::io::stderr)
+        .with_ansi(console::colors_enabled_stderr());
+    let installed = match format {
+        "text" => builder.with_target(false).try_init(),
+        "json" => builder.json().try_init(),
+        _ => return Err(anyhow!("unknown log format: {}", format)),
+    };
+    installed.map_err(|e| anyhow!("failed to set up logging: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(0), LevelFilter::WARN);
+        assert_eq!(level(1), LevelFilter::INFO);
+        assert_eq!(level(2), LevelFilter::DEBUG);
+        assert_eq!(level(5), LevelFilter::TRACE);
+    }
+}
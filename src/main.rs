@@ -1,27 +1,119 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use clap::{arg, Command};
+#[cfg(feature = "kubernetes")]
+use mgmt::app;
 use mgmt::cli::{
     configs, container_images, deploy, envs, release, repos, services, site, templates,
 };
 use mgmt::handlers;
-use mgmt::{app, db};
-use sqlx::postgres::PgPoolOptions;
+use mgmt::{cmd, color, db, dolt, exit, logging, ops, selftest, warnings};
 use tabled::Table;
+#[cfg(feature = "kubernetes")]
 use which::which;
 
+// Returns the local copy of a database a read-only command was asked to read
+// from, if any: the site's own copy for site render and site diff with
+// --db-name, and the directory given with --db-dir for env list and env show.
+fn read_only_db_dir(commands: &clap::ArgMatches) -> Option<PathBuf> {
+    match commands.subcommand()? {
+        ("site", sub_m) => match sub_m.subcommand()? {
+            ("render" | "diff", sub_m) => {
+                let dir = sub_m.get_one::<PathBuf>("dir")?;
+                Some(dir.join(sub_m.get_one::<String>("db-name")?))
+            }
+            _ => None,
+        },
+        ("env", sub_m) => match sub_m.subcommand()? {
+            ("list" | "show", sub_m) => sub_m.get_one::<PathBuf>("db-dir").cloned(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Errors are printed the same way they would be if main returned them, but
+// the exit code depends on the kind of error. See the exit module for the codes.
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(exit::code(&e));
+    }
+}
+
+async fn run() -> Result<()> {
     let commands = Command::new("mgmt")
         .version("0.1.0")
         .about("Discovery Environment deployment management tool")
         .subcommand_required(true)
         .arg(
             arg!(-d --"database-url" <DATABASE>)
-                .default_value("postgresql://root@127.0.0.1:5432/de_releases?sslmode=disable")
+                .default_value(db::DEFAULT_DATABASE_URL)
+                .value_parser(db::parse_database_url),
+        )
+        .arg(
+            arg!(--color [WHEN] "Whether to color the output")
+                .long_help("Whether to color the output. With auto, output is only colored when it's going to a terminal and the NO_COLOR environment variable is not set.")
+                .global(true)
+                .default_value("auto")
+                .value_parser(clap::builder::PossibleValuesParser::new(color::CHOICES)),
+        )
+        .arg(
+            arg!(-v --verbose... "Log more detail to stderr")
+                .long_help("Log more detail to stderr. Only warnings are logged by default; pass it once to log each step as it happens, twice to also log the git and dolt command lines that are run, and three times for everything. It goes before the subcommand, as in mgmt -vv site init, since some subcommands use -v for other flags.")
+                .action(clap::ArgAction::Count),
+        )
+        .arg(
+            arg!(--"log-format" [FORMAT] "The format to write logs in")
+                .long_help("The format to write logs in. Use json to get one JSON object per line, which is easier for CI to parse.")
+                .global(true)
+                .default_value("text")
+                .value_parser(clap::builder::PossibleValuesParser::new(logging::FORMATS)),
+        )
+        .arg(
+            arg!(--"dolt-binary" [PATH] "The dolt executable to run")
+                .long_help("The dolt executable to run. Defaults to the MGMT_DOLT environment variable, then to dolt on the PATH.")
+                .global(true)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--"git-binary" [PATH] "The git executable to run")
+                .long_help("The git executable to run. Defaults to the MGMT_GIT environment variable, then to git on the PATH.")
+                .global(true)
                 .value_parser(clap::value_parser!(String)),
         )
+        .arg(
+            arg!(--strict "Fail the command if it reports any warnings")
+                .long_help("Fail the command if it reports any warnings, such as repos that couldn't be cloned or lint warnings. Meant for CI, where a clean run should be enforced. Without it, warnings are reported but don't change the outcome.")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!(--"db-acquire-timeout" [SECONDS] "How long to wait for a database connection")
+                .long_help("How long to wait for a connection to the database before giving up, in seconds. This covers opening a new connection as well as waiting for one in the pool to free up.")
+                .visible_alias("db-connect-timeout")
+                .global(true)
+                .default_value("30")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"db-max-connections" [N] "How many connections to the database to open at most")
+                .long_help("How many connections each database pool may open at most. Raise it for large sites, or lower it on CI runners with tighter resource budgets.")
+                .global(true)
+                .default_value("5")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            arg!(--"db-statement-timeout" [SECONDS] "How long a database statement may run")
+                .long_help("How long a database statement may run before it's cancelled, in seconds. Zero means statements can run for as long as they need to.")
+                .global(true)
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64)),
+        )
         .subcommand(configs::cli())
         .subcommand(container_images::cli())
         .subcommand(release::cli())
@@ -33,21 +125,82 @@ async fn main() -> Result<()> {
         .subcommand(repos::cli())
         .get_matches();
 
+    let color_choice = commands
+        .get_one::<String>("color")
+        .map(|c| c.as_str())
+        .unwrap_or("auto");
+    color::init(color_choice)?;
+    logging::init(
+        commands.get_count("verbose"),
+        commands
+            .get_one::<String>("log-format")
+            .map(|f| f.as_str())
+            .unwrap_or("text"),
+    )?;
+    warnings::init_strict(commands.get_flag("strict"));
+    db::init_timeouts(
+        commands
+            .get_one::<u64>("db-acquire-timeout")
+            .map_or(db::DEFAULT_ACQUIRE_TIMEOUT, |secs| {
+                Duration::from_secs(*secs)
+            }),
+        commands
+            .get_one::<u64>("db-statement-timeout")
+            .filter(|secs| **secs > 0)
+            .map(|secs| Duration::from_secs(*secs)),
+    );
+
+    db::init_max_connections(
+        commands
+            .get_one::<u32>("db-max-connections")
+            .copied()
+            .unwrap_or(db::DEFAULT_MAX_CONNECTIONS),
+    );
+
+    cmd::init_programs(
+        cmd::OVERRIDABLE_PROGRAMS
+            .iter()
+            .filter_map(|name| {
+                commands
+                    .get_one::<String>(&format!("{}-binary", name))
+                    .map(|path| (name.to_string(), path.clone()))
+            })
+            .collect(),
+    );
+
     let database_url = commands.get_one::<String>("database-url").context(
         "No database URL specified. Use --database-url <url> to specify a database URL.",
     )?;
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
-        .await
-        .map_err(|e| anyhow::anyhow!("error connecting to database: {}", e))?;
+    // The self-test brings up its own database and the schema doesn't need
+    // one, so neither should need the one given with --database-url to be
+    // reachable.
+    if let Some(("site", sub_m)) = commands.subcommand() {
+        match sub_m.subcommand() {
+            Some(("selftest", _)) => return selftest::run().await,
+            Some(("schema", sub_m)) => return handlers::sites::print_schema(sub_m),
+            _ => {}
+        }
+    }
+
+    // The read-only commands pointed at a local copy of a database read it
+    // through a temporary read-only Dolt server instead of the database given
+    // with --database-url. The server is stopped once the command is done.
+    let (pool, _read_only_server) = match read_only_db_dir(&commands) {
+        Some(db_dir) => {
+            let (server, pool) = dolt::connect_read_only(&db_dir).await?;
+            (pool, Some(server))
+        }
+        None => (db::connect(database_url).await?, None),
+    };
 
     match commands.subcommand() {
         Some(("configs", sub_m)) => match sub_m.subcommand() {
             Some(("sections", sub_m)) => handlers::configs::sections(&pool, &sub_m).await?,
             Some(("defaults", sub_m)) => handlers::configs::defaults(&pool, &sub_m).await?,
             Some(("values", sub_m)) => handlers::configs::values(&pool, &sub_m).await?,
+            Some(("backup", sub_m)) => handlers::configs::backup(&pool, sub_m).await?,
+            Some(("restore", sub_m)) => handlers::configs::restore(&pool, sub_m).await?,
             _ => unreachable!("Bad configs subcommand"),
         },
 
@@ -68,18 +221,39 @@ async fn main() -> Result<()> {
         Some(("release", sub_m)) => match sub_m.subcommand() {
             Some(("create", sub_m)) => handlers::releases::create(&pool, &sub_m).await?,
             Some(("deploy", sub_m)) => handlers::releases::deploy(&pool, &sub_m).await?,
+            Some(("services", sub_m)) => handlers::releases::services(&pool, sub_m).await?,
+            Some(("preview", sub_m)) => handlers::releases::preview(&pool, sub_m).await?,
+            Some(("freeze", sub_m)) => handlers::releases::freeze(&pool, sub_m).await?,
+            Some(("ping", _)) => ops::ping(&pool, database_url).await?,
             _ => unreachable!("Bad release subcommand"),
         },
 
         Some(("site", sub_m)) => match sub_m.subcommand() {
             Some(("init", sub_m)) => handlers::sites::init_site(&sub_m).await?,
             Some(("deploy", sub_m)) => handlers::sites::deploy_site(&sub_m).await?,
+            Some(("update", sub_m)) => handlers::sites::update_site(sub_m)?,
+            Some(("lint", sub_m)) => handlers::sites::lint_site(&pool, sub_m).await?,
+            Some(("diff", sub_m)) => handlers::sites::diff_envs(&pool, sub_m).await?,
+            Some(("render", sub_m)) => handlers::sites::render_site(&pool, sub_m).await?,
+            Some(("status", sub_m)) => handlers::sites::site_status(&pool, sub_m).await?,
+            Some(("types", _)) => handlers::sites::list_types(&pool).await?,
+            Some(("edit", sub_m)) => handlers::sites::edit_site(&pool, sub_m).await?,
+            Some(("export", sub_m)) => handlers::sites::export_site(sub_m).await?,
+            Some(("export-values", sub_m)) => handlers::sites::export_values(&pool, sub_m).await?,
+            Some(("import", sub_m)) => handlers::sites::import_site(sub_m).await?,
+            Some(("selftest", _)) => unreachable!("site selftest is run before connecting"),
+            Some(("schema", _)) => unreachable!("site schema is run before connecting"),
+            Some(("db", sub_m)) => match sub_m.subcommand() {
+                Some(("ping", _)) => ops::ping(&pool, database_url).await?,
+                _ => unreachable!("Bad site db subcommand"),
+            },
             _ => unreachable!("Bad site subcommand"),
         },
 
         Some(("deploy", sub_m)) => match sub_m.subcommand() {
+            #[cfg(feature = "kubernetes")]
             Some(("backwards-compat", sub_m)) => {
-                let git_path = which("git").context("git not found")?;
+                let git_path = which(cmd::program("git")).context("git not found")?;
                 let skaffold_path = which("skaffold").context("skaffold not found")?;
                 let kubectl_path = which("kubectl").context("kubectl not found")?;
                 let gomplate_path = which("gomplate").context("gomplate not found")?;
@@ -92,6 +266,10 @@ async fn main() -> Result<()> {
                 let a = app::App::from(&sub_m)?;
                 a.process()?;
             }
+            #[cfg(not(feature = "kubernetes"))]
+            Some(("backwards-compat", _)) => {
+                return Err(ops::kubernetes_unsupported("deploy backwards-compat"))
+            }
             _ => unreachable!("Bad deploy subcommand"),
         },
 
@@ -292,8 +470,13 @@ async fn main() -> Result<()> {
                     "No repository revision specified. Use --revision <revision> to specify a repository revision.",
                 )?;
 
+                let expected_commit = sub_m.get_one::<String>("expected-commit");
+
                 let mut tx = pool.begin().await?;
                 let new_id = db::add_repo(&mut tx, &name, &url, &revision).await?;
+                if let Some(commit) = expected_commit {
+                    db::set_repo_expected_commit(&mut tx, &name, Some(commit)).await?;
+                }
                 tx.commit().await?;
 
                 println!("Added repository {} with ID {}", name, new_id);
@@ -0,0 +1,120 @@
+//! # Manifests
+//!
+//! Writes the manifests `release deploy --manifest-out` saves before they're
+//! applied. Some of them contain secrets, so the directory is only readable by
+//! its owner (0700) and so are the files in it (0600). Manifests containing
+//! secrets also get a `secret-` prefix and a comment at the top, so that it's
+//! obvious they shouldn't be committed or shared.
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+const DIR_MODE: u32 = 0o700;
+const FILE_MODE: u32 = 0o600;
+
+/// The prefix given to the names of manifests that contain secrets.
+pub const SECRET_PREFIX: &str = "secret-";
+
+const SECRET_HEADER: &str =
+    "# SECRET: this manifest contains sensitive values. Do not commit or share it.\n";
+
+/// Creates the output directory if it doesn't exist and makes sure only its
+/// owner can read it, even if it already existed.
+///
+/// # Examples
+/// ```ignore
+///     manifests::create_dir(Path::new("manifests/qa"))?;
+/// ```
+pub fn create_dir(dir: &Path) -> Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(DIR_MODE)
+        .create(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(DIR_MODE))
+        .with_context(|| format!("failed to set the permissions on {}", dir.display()))?;
+    Ok(())
+}
+
+/// Returns the name a manifest is written under.
+pub fn file_name(name: &str, secret: bool) -> String {
+    let name = if name.ends_with(".yaml") || name.ends_with(".yml") {
+        name.to_string()
+    } else {
+        format!("{}.yaml", name)
+    };
+    if secret {
+        format!("{}{}", SECRET_PREFIX, name)
+    } else {
+        name
+    }
+}
+
+/// Writes a manifest to the output directory, readable only by its owner.
+/// Returns the path it was written to.
+///
+/// # Examples
+/// ```ignore
+///     manifests::write(&out_dir, "apps", &rendered, false)?;
+///     manifests::write(&out_dir, "service-configs", &configs, true)?;
+/// ```
+pub fn write(dir: &Path, name: &str, contents: &str, secret: bool) -> Result<PathBuf> {
+    let path = dir.join(file_name(name, secret));
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(FILE_MODE)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+
+    // The mode only applies to new files, so tighten up existing ones before
+    // anything is written to them.
+    fs::set_permissions(&path, fs::Permissions::from_mode(FILE_MODE))
+        .with_context(|| format!("failed to set the permissions on {}", path.display()))?;
+
+    if secret {
+        file.write_all(SECRET_HEADER.as_bytes())?;
+    }
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("apps", false), "apps.yaml");
+        assert_eq!(file_name("apps.yml", false), "apps.yml");
+        assert_eq!(
+            file_name("service-configs", true),
+            "secret-service-configs.yaml"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir()
+            .join(format!("mgmt-manifests-test-{}", std::process::id()))
+            .join("out");
+        create_dir(&dir).unwrap();
+
+        let path = write(&dir, "keycloak", "kind: Secret\n", true).unwrap();
+        assert_eq!(path, dir.join("secret-keycloak.yaml"));
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("# SECRET:"));
+        assert!(contents.ends_with("kind: Secret\n"));
+
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), DIR_MODE);
+        assert_eq!(mode(&path), FILE_MODE);
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
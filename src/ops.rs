@@ -5,14 +5,130 @@
 //! cloning repos into it, and the various handlers for the subcommands
 //! implemented by the tools inside this crate.
 //!
-use crate::config_values::config;
+use crate::config_values::{
+    config, diff,
+    format::Format,
+    interpolate,
+    secrets::{self, Redactions},
+};
 use crate::db::{self, ConfigurationValue, LoadFromDatabase};
-use crate::{dolt, git, handlers::envs::populate_env_templates};
-use anyhow::{anyhow, Context};
+use crate::{cmd, commit, dolt, exit, git, handlers::envs::populate_env_templates, suggest};
+use anyhow::Context;
 use sqlx::{Pool, Postgres};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Returns the error for an environment that isn't in the database,
+/// suggesting the closest environment that is and listing the ones there are.
+///
+/// # Example
+/// ```ignore
+///     if db::get_environment(&mut tx, "prdo").await?.is_none() {
+///         return Err(ops::unknown_environment(&mut tx, "prdo").await?);
+///     }
+/// ```
+pub async fn unknown_environment(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<anyhow::Error> {
+    let envs = db::list_envs(tx).await?;
+    let available = if envs.is_empty() {
+        "There are no environments in the database.".to_string()
+    } else {
+        format!("Available environments: {}", envs.join(", "))
+    };
+    Ok(exit::Error::Usage(format!(
+        "environment {} does not exist{}. {}",
+        environment,
+        suggest::did_you_mean(environment, &envs),
+        available
+    ))
+    .into())
+}
+
+/// Returns the environment with the given name, or the error from
+/// `unknown_environment` if there isn't one.
+///
+/// # Example
+/// ```ignore
+///     let env = ops::require_environment(&mut tx, "qa").await?;
+///     println!("{} {}", env.id, env.namespace);
+/// ```
+pub async fn require_environment(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<db::Environment> {
+    match db::get_environment(tx, environment).await? {
+        Some(env) => Ok(env),
+        None => Err(unknown_environment(tx, environment).await?),
+    }
+}
+
+/// Returns the error for a command that needs the Kubernetes integrations when
+/// they weren't compiled in.
+///
+/// # Example
+/// ```ignore
+///     return Err(ops::kubernetes_unsupported("release deploy"));
+/// ```
+pub fn kubernetes_unsupported(command: &str) -> anyhow::Error {
+    feature_unsupported(command, "kubernetes")
+}
+
+/// Returns the error for a command that needs a cargo feature that wasn't
+/// compiled in.
+///
+/// # Example
+/// ```ignore
+///     return Err(ops::feature_unsupported("site edit", "tui"));
+/// ```
+pub fn feature_unsupported(command: &str, feature: &str) -> anyhow::Error {
+    exit::Error::Usage(format!(
+        "{} is not available because mgmt was built without {} support",
+        command, feature
+    ))
+    .into()
+}
+
+// Returns the error for a section that isn't in the database, suggesting the
+// closest section that is.
+async fn unknown_section(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    section: &str,
+) -> anyhow::Result<anyhow::Error> {
+    let sections = db::list_sections(tx).await?;
+    Ok(exit::Error::Usage(format!(
+        "No section found with name: {section}{}",
+        suggest::did_you_mean(section, &sections)
+    ))
+    .into())
+}
+
+// Returns the error for a key that doesn't have a default value, suggesting
+// the closest key in the section, or the closest section if the section
+// doesn't have any default values.
+async fn missing_default(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    section: &str,
+    key: &str,
+) -> anyhow::Result<anyhow::Error> {
+    let keys = db::list_default_config_values(tx, Some(section), None)
+        .await?
+        .into_iter()
+        .map(|cfg| cfg.key)
+        .collect::<Vec<_>>();
+    let hint = if keys.is_empty() {
+        suggest::did_you_mean(section, &db::list_sections(tx).await?)
+    } else {
+        suggest::did_you_mean(key, &keys)
+    };
+    Ok(exit::Error::Usage(format!(
+        "No default value found for section: {section}, key: {key}{hint}"
+    ))
+    .into())
+}
+
 /// Adds a set of configuration values for an environment to the database.
 /// Interactively prompts the user for all of the values, including the
 /// environment.
@@ -22,12 +138,9 @@ use std::path::{Path, PathBuf};
 /// # Example
 ///
 /// ```ignore
-///     let pool = MySqlPoolOptions::new()
-///        .max_connections(5)
-///        .connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name))
-///        .await?;
+///     let pool = db::connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name)).await?;
 ///
-///     populate_env(&pool).await?;
+///     populate_env(&pool, "qa").await?;
 /// ```
 pub async fn populate_env(pool: &Pool<Postgres>, from_env: &str) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
@@ -101,16 +214,23 @@ pub async fn set_default_value(
     value: &str,
     value_type: &str,
 ) -> anyhow::Result<()> {
-    let mut tx = pool.begin().await?;
-    let has_section = db::has_section(&mut tx, section).await?;
-    if has_section {
-        let cfg_id =
-            db::set_default_config_value(&mut tx, section, &key, &value, &value_type).await?;
-        println!("Added default config value with and ID of {}", cfg_id);
-    } else {
-        return Err(anyhow!("No section found with name: {section}"));
-    }
-    tx.commit().await?;
+    // The transaction is retried if it conflicts with another writer.
+    let cfg_id = db::transaction(pool, |tx| {
+        let (section, key, value, value_type) = (
+            section.to_string(),
+            key.to_string(),
+            value.to_string(),
+            value_type.to_string(),
+        );
+        Box::pin(async move {
+            if !db::has_section(tx, &section).await? {
+                return Err(unknown_section(tx, &section).await?);
+            }
+            db::set_default_config_value(tx, &section, &key, &value, &value_type).await
+        })
+    })
+    .await?;
+    println!("Added default config value with and ID of {}", cfg_id);
     Ok(())
 }
 
@@ -136,12 +256,10 @@ pub async fn get_default_value(
         if has_default_value {
             cfg = db::get_default_config_value(&mut tx, section, key).await?;
         } else {
-            return Err(anyhow!(
-                "No default value found for section: {section}, key: {key}"
-            ));
+            return Err(missing_default(&mut tx, section, key).await?);
         }
     } else {
-        return Err(anyhow!("No section found with name: {section}"));
+        return Err(unknown_section(&mut tx, section).await?);
     }
     tx.commit().await?;
     println!("{:?}", cfg);
@@ -169,18 +287,49 @@ pub async fn delete_default_value(
         if has_default_value {
             db::delete_default_config_value(&mut tx, section, key).await?;
         } else {
-            return Err(anyhow!(
-                "No default value found for section: {section}, key: {key}"
-            ));
+            return Err(missing_default(&mut tx, section, key).await?);
         }
     } else {
-        return Err(anyhow!("No section found with name: {section}"));
+        return Err(unknown_section(&mut tx, section).await?);
     }
     tx.commit().await?;
     println!("Deleted default value: {}.{}", section, key);
     Ok(())
 }
 
+/// Marks a configuration value as a secret, or as not a secret, and prints
+/// out a status message to stdout. Secrets are redacted from values rendered
+/// with `--redact-secrets`.
+///
+/// Handler for the `mgmt-configs defaults secret` command.
+///
+/// # Example
+/// ```ignore
+///     set_default_secret(&pool, "Agave", "Secret", true).await?;
+/// ```
+pub async fn set_default_secret(
+    pool: &Pool<Postgres>,
+    section: &str,
+    key: &str,
+    secret: bool,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    if !db::has_section(&mut tx, section).await? {
+        return Err(unknown_section(&mut tx, section).await?);
+    }
+    if !db::has_default_config_value(&mut tx, section, key).await? {
+        return Err(missing_default(&mut tx, section, key).await?);
+    }
+    db::set_default_secret(&mut tx, section, key, secret).await?;
+    tx.commit().await?;
+    if secret {
+        println!("Marked {}.{} as a secret", section, key);
+    } else {
+        println!("Marked {}.{} as not a secret", section, key);
+    }
+    Ok(())
+}
+
 /// Lists default configuration values from the database and prints them to
 /// stdout.
 ///
@@ -220,56 +369,210 @@ pub async fn list_default_values(
     Ok(())
 }
 
+// Builds the ConfigValues for the sections enabled in the options out of the
+// configuration values retrieved from the database.
+fn build_config_values(
+    opts: &config::SectionOptions,
+    cfgs: Vec<ConfigurationValue>,
+) -> anyhow::Result<config::ConfigValues> {
+    let mut cv = config::ConfigValues::default();
+    cv.set_section_options(opts.clone());
+    cv.reset_sections()?;
+    cv.cfg_set_keys(cfgs)?;
+    Ok(cv)
+}
+
+// Writes the contents to the file unless it already holds exactly those
+// contents, so that re-rendering doesn't touch files that haven't changed.
+// Returns whether the file was written.
+fn write_if_changed(path: &Path, contents: &str, always_write: bool) -> anyhow::Result<bool> {
+    if !always_write {
+        if let Ok(existing) = fs::read(path) {
+            if existing == contents.as_bytes() {
+                return Ok(false);
+            }
+        }
+    }
+
+    fs::write(path, contents).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Serializes configuration values in the requested format. If a destination
+/// file is given, the result is written to it. Otherwise, it's printed to
+/// stdout. A destination file that already has the same contents is left
+/// alone unless `always_write` is true. Returns whether anything was written.
+///
+/// # Example
+/// ```ignore
+///     render(&cv, Format::Yaml, Some(PathBuf::from("deployment.yaml")), false)?;
+/// ```
+pub fn render<T: serde::Serialize>(
+    cv: &T,
+    format: Format,
+    destination: Option<PathBuf>,
+    always_write: bool,
+) -> anyhow::Result<bool> {
+    let rendered = format.serialize(cv)?;
+
+    if let Some(output_file) = destination {
+        write_if_changed(&output_file, &rendered, always_write)
+    } else {
+        println!("{}", rendered);
+        Ok(true)
+    }
+}
+
+// Returns the name of the file a section is written to when the sections are
+// split up, e.g. dashboard-aggregator.yaml for DashboardAggregator.
+fn section_filename(section: &str, format: Format) -> String {
+    let mut name = String::new();
+    let mut prev: Option<char> = None;
+    for c in section.chars() {
+        if c.is_uppercase() && matches!(prev, Some(p) if p.is_lowercase() || p.is_ascii_digit()) {
+            name.push('-');
+        }
+        name.extend(c.to_lowercase());
+        prev = Some(c);
+    }
+    format!("{}.{}", name, format.extension())
+}
+
+/// Serializes configuration values into one file per section in the output
+/// directory, along with an index file that holds the top-level settings and
+/// maps each section to its file. Each section file keeps the section name as
+/// its top-level key so that the files can be passed to tools that merge
+/// values files. Files that already have the same contents are left alone
+/// unless `always_write` is true. Returns whether anything was written.
+///
+/// # Example
+/// ```ignore
+///     render_split(&cv, Format::Yaml, Path::new("values"), false)?;
+/// ```
+pub fn render_split<T: serde::Serialize>(
+    cv: &T,
+    format: Format,
+    output_dir: &Path,
+    always_write: bool,
+) -> anyhow::Result<bool> {
+    let value = serde_yaml::to_value(cv)?;
+    let sections = value
+        .as_mapping()
+        .ok_or_else(|| anyhow::anyhow!("the config values didn't serialize to a mapping"))?;
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("failed to create {}", output_dir.display()))?;
+
+    let mut written = false;
+    let mut index = serde_yaml::Mapping::new();
+    let mut files = serde_yaml::Mapping::new();
+    for (key, section_value) in sections {
+        match (key.as_str(), section_value) {
+            (Some(section), serde_yaml::Value::Mapping(_)) => {
+                let filename = section_filename(section, format);
+                let mut contents = serde_yaml::Mapping::new();
+                contents.insert(key.clone(), section_value.clone());
+                let rendered = format.serialize(&contents)?;
+                written |= write_if_changed(&output_dir.join(&filename), &rendered, always_write)?;
+                files.insert(key.clone(), filename.into());
+            }
+            // Sections that aren't configured for the environment don't get
+            // a file.
+            (_, serde_yaml::Value::Null) => {}
+            _ => {
+                index.insert(key.clone(), section_value.clone());
+            }
+        }
+    }
+    index.insert("Sections".into(), files.into());
+
+    let index_file = output_dir.join(format!("index.{}", format.extension()));
+    written |= write_if_changed(&index_file, &format.serialize(&index)?, always_write)?;
+    Ok(written)
+}
+
 /// Gets all of the default configuration values from the database and
-/// serializes them to YAML. If an output file is specified, the YAML is
-/// written to that file. Otherwise, the YAML is printed to stdout.
+/// serializes them. If an output file is specified, the values are written to
+/// that file. Otherwise, they're printed to stdout. The format is the one
+/// given, then the one matching the output file's extension, then YAML.
 ///
 /// Handler for the `mgmt-configs defaults render` command.
 ///
 /// # Example
 /// To render all of the default configuration values to stdout:
 /// ```ignore
-///     render_default_values(&pool, None).await?;
+///     render_default_values(&pool, None, None, false).await?;
 /// ```
 ///
-/// To render all of the default configuration values to a file:
+/// To render all of the default configuration values to a file, leaving it
+/// alone if it hasn't changed:
 /// ```ignore
-///     render_default_values(&pool, Some(PathBuf::from("defaults.yaml"))).await?;
+///     render_default_values(&pool, Some(PathBuf::from("defaults.yaml")), None, false).await?;
 /// ```
 pub async fn render_default_values(
     pool: &Pool<Postgres>,
     output_file: Option<PathBuf>,
-) -> anyhow::Result<()> {
+    format: Option<Format>,
+    always_write: bool,
+) -> anyhow::Result<bool> {
     let mut tx = pool.begin().await?;
 
     let all_default_cfgs = db::list_default_config_values(&mut tx, None, None).await?;
-    let mut cv = config::ConfigValues::default();
+    let all_default_cfgs = interpolate::interpolate(all_default_cfgs)?;
     let mut section_options = config::SectionOptions::default();
     section_options.set_all(true)?;
-    cv.set_section_options(section_options);
-    cv.reset_sections()?;
-    cv.cfg_set_keys(all_default_cfgs)?;
+    let cv = build_config_values(&section_options, all_default_cfgs)?;
 
-    if let Some(output_file) = output_file {
-        let yaml = serde_yaml::to_string(&cv)?;
-        std::fs::write(output_file, yaml)?;
-    } else {
-        let yaml = serde_yaml::to_string(&cv)?;
-        println!("{}", yaml);
-    }
+    let format = Format::resolve(format, output_file.as_deref())?;
+    let written = render(&cv, format, output_file, always_write)?;
 
     tx.commit().await?;
 
-    Ok(())
+    Ok(written)
 }
 
-/// Sets a configuration value for an environment in the database.
+/// Sets a configuration value for an environment in a transaction, recording
+/// the actor as the one who set it. Returns whether the environment didn't
+/// have its own value for the key before.
+///
+/// # Example
+/// ```ignore
+///    let added = set_value_in_tx(&mut tx, "prod", "Agave", "Key", "12345", "string", "jdoe").await?;
+/// ```
+pub async fn set_value_in_tx(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    environment: &str,
+    section: &str,
+    key: &str,
+    value: &str,
+    value_type: &str,
+    actor: &str,
+) -> anyhow::Result<bool> {
+    let env_id = require_environment(tx, environment).await?.id;
+
+    if !db::has_default_config_value(tx, section, key).await? {
+        return Err(missing_default(tx, section, key).await?);
+    }
+
+    if !db::has_config_value(tx, environment, section, key).await? {
+        let cfg_id = db::set_config_value_as(tx, section, key, value, value_type, actor).await?;
+        db::add_env_cfg_value(tx, env_id, cfg_id).await?;
+        Ok(true)
+    } else {
+        let cfg_id =
+            db::update_env_cfg_value(tx, environment, section, key, value, value_type).await?;
+        db::record_provenance(tx, cfg_id, actor).await?;
+        Ok(false)
+    }
+}
+
+/// Sets a configuration value for an environment in the database, recording
+/// the actor as the one who set it.
 ///
 /// Handler for the `mgmt-configs values set` command.
 ///
 /// # Example
 /// ```ignore
-///    set_value(&pool, "prod", "Agave", "Key", "12345", "string").await?;
+///    set_value(&pool, "prod", "Agave", "Key", "12345", "string", "jdoe").await?;
 /// ```
 pub async fn set_value(
     pool: &Pool<Postgres>,
@@ -278,39 +581,46 @@ pub async fn set_value(
     key: &str,
     value: &str,
     value_type: &str,
+    actor: &str,
 ) -> anyhow::Result<()> {
-    let mut tx = pool.begin().await?;
-
-    let env_id = db::get_env_id(&mut tx, &environment).await?;
-
-    let has_default = db::has_default_config_value(&mut tx, section, &key).await?;
-    let has_config_value = db::has_config_value(&mut tx, environment, section, &key).await?;
+    // The transaction is retried if it conflicts with another writer, so the
+    // outcome is only reported once it's committed.
+    let added = db::transaction(pool, |tx| {
+        let (environment, section, key, value, value_type, actor) = (
+            environment.to_string(),
+            section.to_string(),
+            key.to_string(),
+            value.to_string(),
+            value_type.to_string(),
+            actor.to_string(),
+        );
+        Box::pin(async move {
+            set_value_in_tx(
+                tx,
+                &environment,
+                &section,
+                &key,
+                &value,
+                &value_type,
+                &actor,
+            )
+            .await
+        })
+    })
+    .await?;
 
-    if has_default {
-        if !has_config_value {
-            let cfg_id = db::set_config_value(&mut tx, section, &key, &value, &value_type).await?;
-            db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
-            println!(
-                "Added config value to environment '{}': {}.{} = {}",
-                environment, section, key, value
-            );
-        } else {
-            db::update_env_cfg_value(&mut tx, &environment, &section, &key, &value, &value_type)
-                .await?;
-            println!(
-                "Updated config value in environment '{}': {}.{} = {}",
-                environment, section, key, value
-            );
-        }
+    if added {
+        println!(
+            "Added config value to environment '{}': {}.{} = {}",
+            environment, section, key, value
+        );
     } else {
-        tx.rollback().await?;
-        return Err(anyhow!(
-            "No default value found for section: {section}, key: {key}"
-        ));
+        println!(
+            "Updated config value in environment '{}': {}.{} = {}",
+            environment, section, key, value
+        );
     }
 
-    tx.commit().await?;
-
     Ok(())
 }
 
@@ -342,10 +652,9 @@ pub async fn get_value(
         if has_default_value {
             cfg = db::get_default_config_value(&mut tx, section, key).await?;
         } else {
+            let err = missing_default(&mut tx, section, key).await?;
             tx.rollback().await?;
-            return Err(anyhow!(
-                "No default value found for section: {section}, key: {key}"
-            ));
+            return Err(err);
         }
     }
 
@@ -417,134 +726,596 @@ pub async fn list_values(
 }
 
 /// Gets all of the configuration values for an environment from the database
-/// and serializes them to YAML. If an output file is specified, the YAML is
-/// written to that file. Otherwise, the YAML is printed to stdout.
+/// and serializes them. If an output file is specified, the values are written
+/// to that file. Otherwise, they're printed to stdout. The format is the one
+/// given, then the one matching the output file's extension, then YAML. If
+/// `redact_secrets` is true, the values marked as secrets in the database are
+/// replaced with a placeholder, along with the values that refer to them.
 ///
 /// Handler  for the `mgmt-configs values render` command.
 ///
 /// # Example
 /// To render all of the configuration values for an environment to stdout:
 /// ```ignore
-///    render_values(&pool, "prod", &opts, None).await?;
+///    render_values(&pool, "prod", &opts, None, None, false, false).await?;
 /// ```
 ///
-/// To render all of the configuration values for an environment to a file:
+/// To render all of the configuration values for an environment to a file,
+/// leaving it alone if it hasn't changed:
 /// ```ignore
-///   render_values(&pool, "prod", &opts, Some(PathBuf::from("prod.yaml"))).await?;
+///   render_values(&pool, "prod", &opts, Some(PathBuf::from("prod.toml")), None, false, false).await?;
 /// ```
 pub async fn render_values(
     pool: &Pool<Postgres>,
     environment: &str,
     opts: &config::SectionOptions,
     output_file: Option<PathBuf>,
-) -> anyhow::Result<()> {
+    format: Option<Format>,
+    always_write: bool,
+    redact_secrets: bool,
+) -> anyhow::Result<bool> {
+    render_section_values(
+        pool,
+        environment,
+        opts,
+        &[],
+        output_file,
+        format,
+        always_write,
+        redact_secrets,
+    )
+    .await
+}
+
+/// Like `render_values`, but only renders the named sections. The section
+/// for the top-level settings is `TopLevel`. Rendering every section, like
+/// `render_values` does, is what an empty list of sections means.
+///
+/// # Example
+/// ```ignore
+///    let sections = vec!["DE".to_string(), "Keycloak".to_string()];
+///    render_section_values(&pool, "prod", &opts, &sections, Some(PathBuf::from("prod.yaml")), None, false, false).await?;
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn render_section_values(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    opts: &config::SectionOptions,
+    sections: &[String],
+    output_file: Option<PathBuf>,
+    format: Option<Format>,
+    always_write: bool,
+    redact_secrets: bool,
+) -> anyhow::Result<bool> {
+    let (cv, redactions) =
+        get_config_values(pool, environment, opts, sections, redact_secrets).await?;
+    let format = Format::resolve(format, output_file.as_deref())?;
+    if redactions.is_empty() {
+        return render(&cv, format, output_file, always_write);
+    }
+    render(
+        &redact(&cv, &redactions)?,
+        format,
+        output_file,
+        always_write,
+    )
+}
+
+/// Gets all of the configuration values for an environment from the database
+/// and writes each of the selected sections to its own file in the output
+/// directory, along with an index file. See `render_split` for the layout.
+/// The format is the one given, then YAML. Secrets are redacted like they are
+/// by `render_values`.
+///
+/// Handler for the `mgmt-configs values render --split-sections` command.
+///
+/// # Example
+/// ```ignore
+///    render_values_split(&pool, "prod", &opts, Path::new("prod"), None, false, false).await?;
+/// ```
+pub async fn render_values_split(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    opts: &config::SectionOptions,
+    output_dir: &Path,
+    format: Option<Format>,
+    always_write: bool,
+    redact_secrets: bool,
+) -> anyhow::Result<bool> {
+    let (cv, redactions) = get_config_values(pool, environment, opts, &[], redact_secrets).await?;
+    let format = format.unwrap_or_default();
+    if redactions.is_empty() {
+        return render_split(&cv, format, output_dir, always_write);
+    }
+    render_split(&redact(&cv, &redactions)?, format, output_dir, always_write)
+}
+
+/// Gets the configuration values for every environment in the database and
+/// serializes them into a single document keyed by environment name, so that
+/// a whole site can be backed up or diffed at once. The environments,
+/// sections, and keys are all sorted, so exporting the same database twice
+/// gives the same output. Each environment gets the sections enabled for it.
+/// The output goes to the file if one is given, and to stdout otherwise. Only
+/// YAML and JSON are supported, since TOML can't hold the unset values.
+///
+/// Handler for the `mgmt-site export-values` command.
+///
+/// # Example
+/// ```ignore
+///    export_all_values(&pool, Some(PathBuf::from("site.yaml")), None).await?;
+/// ```
+pub async fn export_all_values(
+    pool: &Pool<Postgres>,
+    output_file: Option<PathBuf>,
+    format: Option<Format>,
+) -> anyhow::Result<bool> {
+    let format = Format::resolve(format, output_file.as_deref())?;
+    if format == Format::Toml {
+        return Err(exit::Error::Usage(
+            "all of the environments can only be exported as YAML or JSON".to_string(),
+        )
+        .into());
+    }
+
     let mut tx = pool.begin().await?;
-    let mut all_cfgs: Vec<ConfigurationValue> = Vec::new();
-    let all_default_cfgs = db::list_default_config_values(&mut tx, None, None).await?;
+    let envs = db::list_envs(&mut tx).await?;
+    tx.commit().await?;
 
-    for default in all_default_cfgs
-        .into_iter()
-        .filter(|cfg| opts.include_section(&cfg.section.clone()))
-    {
-        let section = default.section.clone();
-        let key = default.key.clone();
-        let has_config_value = db::has_config_value(&mut tx, environment, &section, &key)
+    // serde_json's maps are sorted by key, which is what makes the output
+    // deterministic.
+    let mut export = BTreeMap::new();
+    for env in envs {
+        let opts = config::SectionOptions::new_from_db(pool, &env).await?;
+        let (cv, _) = get_config_values(pool, &env, &opts, &[], false)
             .await
-            .unwrap_or(false);
+            .with_context(|| format!("failed to export the {} environment", env))?;
+        export.insert(env, serde_json::to_value(&cv)?);
+    }
 
-        if has_config_value {
-            all_cfgs.push(
-                db::get_config_value(&mut tx, environment, &section, &key)
-                    .await
-                    .unwrap(),
-            );
-        } else {
-            all_cfgs.push(default);
+    render(&export, format, output_file, false)
+}
+
+/// Compares the config values of two environments, falling back to the
+/// defaults for the values an environment doesn't set. Values are compared as
+/// they're stored, and the ones marked as secrets are replaced with the
+/// redaction placeholder after they're compared.
+///
+/// # Example
+/// ```ignore
+///    let entries = diff_environments(&pool, "staging", "prod").await?;
+/// ```
+pub async fn diff_environments(
+    pool: &Pool<Postgres>,
+    left: &str,
+    right: &str,
+) -> anyhow::Result<Vec<diff::Entry>> {
+    let mut tx = pool.begin().await?;
+    let left_env = require_environment(&mut tx, left).await?;
+    let right_env = require_environment(&mut tx, right).await?;
+    let left_cfgs = db::get_config_values(&mut tx, left_env.id).await?;
+    let right_cfgs = db::get_config_values(&mut tx, right_env.id).await?;
+    let secrets = db::list_secret_keys(&mut tx).await?;
+    tx.commit().await?;
+
+    let mut entries = diff::diff(&left_cfgs, &right_cfgs);
+    for entry in &mut entries {
+        if !secrets.contains(&(entry.section.clone(), entry.key.clone())) {
+            continue;
+        }
+        match &mut entry.change {
+            diff::Change::Same(v) | diff::Change::OnlyLeft(v) | diff::Change::OnlyRight(v) => {
+                *v = secrets::PLACEHOLDER.to_string()
+            }
+            diff::Change::Changed { left, right } => {
+                *left = secrets::PLACEHOLDER.to_string();
+                *right = secrets::PLACEHOLDER.to_string();
+            }
         }
     }
 
-    let mut cv = config::ConfigValues::default();
-    cv.set_section_options(opts.clone());
-    cv.reset_sections()?;
-    cv.cfg_set_keys(all_cfgs)?;
+    Ok(entries)
+}
 
-    tx.commit().await?;
+// Serializes the config values with the redacted ones replaced.
+fn redact(cv: &config::ConfigValues, redactions: &Redactions) -> anyhow::Result<serde_yaml::Value> {
+    let mut value = serde_yaml::to_value(cv)?;
+    redactions.apply(&mut value);
+    Ok(value)
+}
+
+// Gets the configuration values for the selected sections in an environment,
+// falling back to the defaults for the settings the environment doesn't set.
+// If any sections are named, only those are included. References to other
+// values are resolved against all of the sections, not just the selected ones.
+// If `redact_secrets` is true, the values to redact are returned too.
+async fn get_config_values(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    opts: &config::SectionOptions,
+    sections: &[String],
+    redact_secrets: bool,
+) -> anyhow::Result<(config::ConfigValues, Redactions)> {
+    let mut tx = pool.begin().await?;
+    let env = require_environment(&mut tx, environment).await?;
+    let all_cfgs = db::get_config_values(&mut tx, env.id).await?;
 
-    if let Some(output_file) = output_file {
-        let yaml = serde_yaml::to_string(&cv)?;
-        std::fs::write(output_file, yaml)?;
+    // The secrets have to be found before interpolating, while the values
+    // that refer to them still say so.
+    let redactions = if redact_secrets {
+        let secrets = db::list_secret_keys(&mut tx).await?;
+        Redactions::new(&all_cfgs, &secrets)
     } else {
-        let yaml = serde_yaml::to_string(&cv)?;
-        println!("{}", yaml);
-    }
+        Redactions::default()
+    };
 
-    Ok(())
+    let selected_cfgs = interpolate::interpolate(all_cfgs)?
+        .into_iter()
+        .filter(|cfg| opts.include_section(&cfg.section))
+        .filter(|cfg| sections.is_empty() || sections.contains(&cfg.section))
+        .collect();
+    let cv = build_config_values(opts, selected_cfgs)?;
+
+    tx.commit().await?;
+
+    Ok((cv, redactions))
 }
 
-/// Imports a YAML file into the database. The YAML file must be in the same
-/// format as the output of the `mgmt-configs values render` command.
+/// Imports a values file into the database for an environment, adding the
+/// values the environment doesn't have yet and updating the ones that
+/// differ. The file must be in the same format as the output of the
+/// `mgmt-configs values render` command. The environment's feature flags are
+/// then set up from the sections it has values for. Everything happens in
+/// one transaction, so a bad value partway through leaves the environment as
+/// it was. With `dry_run`, the changes are reported but rolled back.
 ///
-/// Handler for importing files.
+/// Handler for the `mgmt-configs values import` command.
 ///
 /// # Example
 /// ```ignore
-///    import_yaml_file(&pool, PathBuf::from("prod.yaml"), "prod").await?;
+///    import_yaml_file(&pool, PathBuf::from("prod.yaml"), "prod", false).await?;
 /// ```
 pub async fn import_yaml_file(
     pool: &Pool<Postgres>,
     path: PathBuf,
     environment: &str,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
+    let cv = config::ConfigValues::from_file(path)?;
+
     let mut tx = pool.begin().await?;
-    let file = std::fs::File::open(path)?;
-    let mut cv: config::ConfigValues = serde_yaml::from_reader(file)?;
-    cv.set_section_options(cv.generate_section_options());
+    let env_id = require_environment(&mut tx, environment).await?.id;
+    let (add, update) = if dry_run {
+        ("Would add", "Would update")
+    } else {
+        ("Added", "Updated")
+    };
 
+    let (mut added, mut updated, mut unchanged) = (0, 0, 0);
     let items: Vec<db::ConfigurationValue> = cv.into();
-    for item in items.into_iter() {
-        let section = item.section.clone();
-        let key = item.key.clone();
-        let value = item.value.clone();
-        let value_type = item.value_type.clone();
-        println!("{}.{} = {}", section, key, value);
-        let real_section: String;
-        if section.is_empty() {
-            real_section = "TopLevel".to_string();
+    for item in items {
+        let section = if item.section.is_empty() {
+            "TopLevel".to_string()
         } else {
-            real_section = section;
+            item.section
+        };
+        let (key, value, value_type) = (item.key, item.value, item.value_type);
+
+        if !db::has_default_config_value(&mut tx, &section, &key).await? {
+            let err = missing_default(&mut tx, &section, &key).await?;
+            tx.rollback().await?;
+            return Err(err);
         }
 
-        if db::has_default_config_value(&mut tx, &real_section, &key).await? {
+        if db::has_config_value(&mut tx, environment, &section, &key).await? {
+            let current = db::get_config_value(&mut tx, environment, &section, &key).await?;
+            if current.value == value && current.value_type == value_type {
+                unchanged += 1;
+                continue;
+            }
             let cfg_id =
-                db::set_config_value(&mut tx, &real_section, &key, &value, &value_type).await?;
-            let env_id = db::get_env_id(&mut tx, &environment).await?;
-            db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+                db::update_env_cfg_value(&mut tx, environment, &section, &key, &value, &value_type)
+                    .await?;
+            db::record_provenance(&mut tx, cfg_id, &db::default_actor()).await?;
+            println!(
+                "{}: {}.{} = {} (was {})",
+                update, section, key, value, current.value
+            );
+            updated += 1;
         } else {
-            tx.rollback().await?;
-            return Err(anyhow!(
-                "No default value found for section: {real_section}, key: {key}"
-            ));
+            let cfg_id = db::set_config_value(&mut tx, &section, &key, &value, &value_type).await?;
+            db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+            println!("{}: {}.{} = {}", add, section, key, value);
+            added += 1;
         }
     }
 
+    // The feature flags follow from everything the environment sets now,
+    // not just what was in the file.
+    let env_cfgs: config::ConfigValues =
+        db::list_config_values(&mut tx, Some(environment), None, None)
+            .await?
+            .into();
+    let section_options = env_cfgs.generate_section_options();
+    db::upsert_feature_flags(&mut tx, environment, &section_options.into()).await?;
+
+    if dry_run {
+        tx.rollback().await?;
+        println!(
+            "Would add {} value(s), update {}, and leave {} unchanged in the {} environment. Nothing was written.",
+            added, updated, unchanged, environment
+        );
+    } else {
+        tx.commit().await?;
+        println!(
+            "Added {} value(s), updated {}, and left {} unchanged in the {} environment.",
+            added, updated, unchanged, environment
+        );
+    }
+
+    Ok(())
+}
+
+// Returns whether a backup file should be read or written as JSON rather than
+// YAML, based on its extension.
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Writes all of the configuration data in the database to a file: the value
+/// types, sections, defaults, and every environment's feature flags and
+/// values. The file is JSON if its name ends in .json and YAML otherwise.
+///
+/// Handler for the `mgmt-configs backup` command.
+///
+/// # Example
+/// ```ignore
+///    export_all(&pool, Path::new("config-backup.yaml")).await?;
+/// ```
+pub async fn export_all(pool: &Pool<Postgres>, path: &Path) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let backup = db::export_all(&mut tx).await?;
+    tx.commit().await?;
+
+    let contents = if is_json(path) {
+        serde_json::to_string_pretty(&backup)?
+    } else {
+        serde_yaml::to_string(&backup)?
+    };
+    fs::write(path, contents)
+        .with_context(|| format!("failed to write backup file {}", path.display()))?;
+
+    println!(
+        "Backed up {} default(s) and {} environment(s) to {}",
+        backup.defaults.len(),
+        backup.environments.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Replaces the configuration data in the database with the contents of a
+/// file written by `export_all`. The import happens in a single transaction,
+/// so the database is left untouched if any part of it fails.
+///
+/// Handler for the `mgmt-configs restore` command.
+///
+/// # Example
+/// ```ignore
+///    import_all(&pool, Path::new("config-backup.yaml")).await?;
+/// ```
+pub async fn import_all(pool: &Pool<Postgres>, path: &Path) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read backup file {}", path.display()))?;
+    let backup: db::ConfigBackup = if is_json(path) {
+        serde_json::from_str(&contents)?
+    } else {
+        serde_yaml::from_str(&contents)?
+    };
+
+    if backup.schema_version > db::BACKUP_SCHEMA_VERSION {
+        return Err(exit::Error::Usage(format!(
+            "backup file {} has schema version {}, but this version of the tool only supports up to {}",
+            path.display(),
+            backup.schema_version,
+            db::BACKUP_SCHEMA_VERSION
+        ))
+        .into());
+    }
+
+    let mut tx = pool.begin().await?;
+    db::import_all(&mut tx, &backup)
+        .await
+        .with_context(|| format!("while restoring {}", path.display()))?;
     tx.commit().await?;
 
+    println!(
+        "Restored {} default(s) and {} environment(s) from {}",
+        backup.defaults.len(),
+        backup.environments.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Checks that the database is reachable and prints how long a trivial query
+/// took along with the server's version.
+///
+/// Handler for the `release ping` and `site db ping` commands.
+///
+/// # Example
+/// ```ignore
+///     ping(&pool, "postgresql://root@127.0.0.1:5432/de_releases").await?;
+/// ```
+pub async fn ping(pool: &Pool<Postgres>, database_url: &str) -> anyhow::Result<()> {
+    let (latency, version) = db::ping(pool).await?;
+    println!("Connected to {}", db::redact_url(database_url));
+    println!("Server version: {}", version);
+    println!("Latency: {:.2} ms", latency.as_secs_f64() * 1000.0);
     Ok(())
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReleaseOpts {
-    pub env: String,
+    pub envs: Vec<String>,
     pub repo_name: String,
     pub repo_url: String,
     pub repo_branch: String,
     pub skips: Vec<String>,
+    pub include_disabled: bool,
     pub no_fail: bool,
     pub no_clone: bool,
     pub no_push: bool,
     pub no_commit: bool,
     pub no_tag: bool,
     pub increment_field: String,
+
+    // Whether to overwrite the changes left in the releases repository by a
+    // release that didn't finish.
+    pub force: bool,
+
+    // The file pinning the versions of the services, if there is one.
+    pub versions: Option<PathBuf>,
+
+    // The directory containing clones of the services' repositories, which
+    // are used to list the commits in the changelog.
+    pub repos_dir: PathBuf,
+
+    // The identity and message template for the release commit.
+    pub author: Option<commit::Author>,
+    pub message: String,
+}
+
+/// Returns whether a service should be skipped for an environment. A skip is
+/// either a bare service name, which applies to every environment, or
+/// `<env>:<service>`, which only applies to the named environment.
+///
+/// # Examples
+/// ```ignore
+/// let skips = vec!["apps".to_string(), "prod:analyses".to_string()];
+/// assert!(ops::is_skipped(&skips, "qa", "apps"));
+/// assert!(ops::is_skipped(&skips, "prod", "analyses"));
+/// assert!(!ops::is_skipped(&skips, "qa", "analyses"));
+/// ```
+pub fn is_skipped(skips: &[String], env: &str, service: &str) -> bool {
+    skips.iter().any(|skip| match skip.split_once(':') {
+        Some((skip_env, skip_service)) => skip_env == env && skip_service == service,
+        None => skip == service,
+    })
+}
+
+/// Returns the services in an environment that should be released or
+/// deployed: the enabled ones that aren't skipped, plus the disabled ones if
+/// `include_disabled` is true. The disabled services that are left out are
+/// reported so that it's clear why they're missing.
+///
+/// # Examples
+/// ```ignore
+/// let services = db::get_services_for_env(&mut tx, "qa").await?;
+/// let services = ops::active_services(services, "qa", &opts.skips, opts.include_disabled);
+/// ```
+pub fn active_services(
+    services: Vec<db::EnvService>,
+    env: &str,
+    skips: &[String],
+    include_disabled: bool,
+) -> Vec<db::Service> {
+    services
+        .into_iter()
+        .filter(|svc| !is_skipped(skips, env, &svc.name))
+        .filter(|svc| {
+            if !svc.enabled && !include_disabled {
+                println!("Skipping {}, which is disabled in {}", svc.name, env);
+                return false;
+            }
+            true
+        })
+        .map(db::Service::from)
+        .collect()
+}
+
+// Describes an unknown service for an error message. `label` is what the user
+// passed in, which may include an environment prefix.
+fn describe_unknown_service(label: &str, service: &str, known: &[String]) -> String {
+    match suggest::closest(service, known) {
+        Some(closest) => format!("{} (did you mean '{}'?)", label, closest),
+        None => label.to_string(),
+    }
+}
+
+fn unknown_services_result(flag: &str, unknown: Vec<String>) -> anyhow::Result<()> {
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(exit::Error::Usage(format!(
+        "unknown service(s) passed to {}: {}",
+        flag,
+        unknown.join("; ")
+    ))
+    .into())
+}
+
+/// Returns an error listing each of the names that isn't one of the known
+/// service names, along with the closest known name for each. `flag` is the option
+/// the names were passed in with and is only used in the error message.
+///
+/// # Examples
+/// ```ignore
+/// let known = vec!["apps".to_string(), "analyses".to_string()];
+/// assert!(ops::validate_service_names("--service", &["apps".to_string()], &known).is_ok());
+/// assert!(ops::validate_service_names("--service", &["ap".to_string()], &known).is_err());
+/// ```
+pub fn validate_service_names(
+    flag: &str,
+    names: &[String],
+    known: &[String],
+) -> anyhow::Result<()> {
+    let unknown = names
+        .iter()
+        .filter(|name| !known.contains(name))
+        .map(|name| describe_unknown_service(name, name, known))
+        .collect();
+    unknown_services_result(flag, unknown)
+}
+
+/// Returns an error listing each of the skips that doesn't name a service in
+/// the environments it applies to. `services` maps the names of the
+/// environments being worked on to the names of their services. See
+/// `is_skipped` for the format of the skips.
+///
+/// # Examples
+/// ```ignore
+/// let services = BTreeMap::from([("qa".to_string(), vec!["apps".to_string()])]);
+/// assert!(ops::validate_skips(&["qa:apps".to_string()], &services).is_ok());
+/// assert!(ops::validate_skips(&["prod:apps".to_string()], &services).is_err());
+/// ```
+pub fn validate_skips(
+    skips: &[String],
+    services: &BTreeMap<String, Vec<String>>,
+) -> anyhow::Result<()> {
+    let all = services
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let unknown = skips
+        .iter()
+        .filter_map(|skip| match skip.split_once(':') {
+            Some((env, service)) => match services.get(env) {
+                Some(known) if known.iter().any(|k| k == service) => None,
+                Some(known) => Some(describe_unknown_service(skip, service, known)),
+                None => Some(format!("{} (environment {} is not included)", skip, env)),
+            },
+            None if all.contains(skip) => None,
+            None => Some(describe_unknown_service(skip, skip, &all)),
+        })
+        .collect();
+    unknown_services_result("--skip", unknown)
 }
 
 /// Creates or clones the release directory and creates the builds and services subdirectories.
@@ -555,7 +1326,7 @@ pub struct ReleaseOpts {
 /// # Examples
 /// ```ignore
 /// let opts = ReleaseOpts {
-///  env: "dev".to_string(),
+///  envs: vec!["dev".to_string()],
 ///  repo_name: "de-releases".to_string(),
 ///  repo_url: "https://github.com/cyverse-de/de-releases".to_string(),
 /// }
@@ -578,6 +1349,7 @@ pub fn setup_release_dir(opts: &ReleaseOpts) -> anyhow::Result<(PathBuf, PathBuf
                     .as_path()
                     .to_str()
                     .context("cannot convert repo directory to string")?,
+                false,
             )?;
         } else {
             let git_dir = repo_dir.join(".git");
@@ -630,11 +1402,24 @@ pub fn create_db_dir(dir: &str, db_name: &str, force: bool) -> anyhow::Result<Pa
 }
 
 // Use the dolt command to clone the initial database state from the remote.
-pub fn clone_db(dir: &str, db_repo: &str, db_name: &str, force: bool) -> anyhow::Result<PathBuf> {
+// Dolt's output is captured if `quiet` is true. Dolt is killed if `cancel` is
+// canceled before the clone finishes.
+pub fn clone_db(
+    dir: &str,
+    db_repo: &str,
+    db_name: &str,
+    force: bool,
+    quiet: bool,
+    cancel: &cmd::Cancel,
+) -> anyhow::Result<PathBuf> {
     let db_dir = create_db_dir(&dir, &db_name, force)?;
     let db_dir_str = db_dir
         .to_str()
         .context("could not get name of the database directory")?;
-    dolt::clone(db_repo, db_dir_str)?;
+    if quiet {
+        dolt::clone_quietly(db_repo, db_dir_str, cancel)?;
+    } else {
+        dolt::clone(db_repo, db_dir_str, cancel)?;
+    }
     Ok(db_dir)
 }
@@ -0,0 +1,220 @@
+//! # Output
+//!
+//! Formatting for the commands that print lists of records. Every record type
+//! that's printed this way needs to implement both `tabled::Tabled` and
+//! `serde::Serialize`.
+use anyhow::{anyhow, Result};
+use clap::{arg, Arg, ArgMatches};
+use serde::Serialize;
+use std::str::FromStr;
+use tabled::{
+    settings::{peaker::PriorityMax, Width},
+    Table, Tabled,
+};
+
+/// The names of the supported formats.
+pub const FORMATS: [&str; 3] = ["table", "csv", "json"];
+
+/// The output formats for list-style commands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A table meant for reading in a terminal.
+    #[default]
+    Table,
+
+    /// RFC 4180 CSV with a header row.
+    Csv,
+
+    /// A pretty-printed JSON array.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!("unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Returns the `--format` argument shared by the list-style commands.
+pub fn format_arg() -> Arg {
+    arg!(--format [FORMAT] "The output format")
+        .default_value("table")
+        .value_parser(clap::builder::PossibleValuesParser::new(FORMATS))
+}
+
+/// Returns the format selected with the `--format` argument, or the default
+/// format if the command doesn't have one.
+pub fn get_format(matches: &ArgMatches) -> Result<Format> {
+    matches
+        .get_one::<String>("format")
+        .map(|f| f.parse())
+        .unwrap_or(Ok(Format::default()))
+}
+
+/// Returns the `--no-truncate` argument shared by the list-style commands.
+pub fn no_truncate_arg() -> Arg {
+    arg!(--"no-truncate" "Show long values in full instead of fitting tables to the terminal")
+        .action(clap::ArgAction::SetTrue)
+        .value_parser(clap::value_parser!(bool))
+}
+
+/// Returns the width that tables should be truncated to, which is the width
+/// of the terminal. Returns `None` when `--no-truncate` was given or when
+/// stdout isn't a terminal, so that piped output is always complete.
+pub fn get_max_width(matches: &ArgMatches) -> Option<usize> {
+    let no_truncate = matches
+        .try_get_one::<bool>("no-truncate")
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(false);
+    if no_truncate {
+        return None;
+    }
+
+    console::Term::stdout()
+        .size_checked()
+        .map(|(_, cols)| cols as usize)
+}
+
+// Quotes a CSV field if it contains a delimiter, a quote, or a line break.
+// Quotes inside the field are doubled.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_record<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|f| csv_field(f.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Renders the rows in the requested format. Tables wider than `max_width`
+/// have their widest columns truncated with an ellipsis; the other formats
+/// are never truncated. The result doesn't end with a trailing newline
+/// unless the format requires one.
+///
+/// # Examples
+/// ```ignore
+///     let csv = output::render(&rows, output::Format::Csv, None)?;
+/// ```
+pub fn render<T: Tabled + Serialize>(
+    rows: &[T],
+    format: Format,
+    max_width: Option<usize>,
+) -> Result<String> {
+    Ok(match format {
+        Format::Table => {
+            let mut table = Table::new(rows);
+            if let Some(max_width) = max_width {
+                table.with(
+                    Width::truncate(max_width)
+                        .suffix("...")
+                        .priority::<PriorityMax>(),
+                );
+            }
+            table.to_string()
+        }
+        Format::Json => serde_json::to_string_pretty(rows)?,
+        Format::Csv => {
+            // RFC 4180 records end with CRLF, including the last one.
+            let mut csv = csv_record(&T::headers());
+            csv.push_str("\r\n");
+            for row in rows {
+                csv.push_str(&csv_record(&row.fields()));
+                csv.push_str("\r\n");
+            }
+            csv
+        }
+    })
+}
+
+/// Prints the rows to stdout in the requested format.
+///
+/// # Examples
+/// ```ignore
+///     output::print(&rows, output::get_format(matches)?, output::get_max_width(matches))?;
+/// ```
+pub fn print<T: Tabled + Serialize>(
+    rows: &[T],
+    format: Format,
+    max_width: Option<usize>,
+) -> Result<()> {
+    let rendered = render(rows, format, max_width)?;
+    if rendered.ends_with('\n') {
+        print!("{}", rendered);
+    } else {
+        println!("{}", rendered);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Tabled, Serialize)]
+    struct Row {
+        key: String,
+        value: String,
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let rows = vec![
+            Row {
+                key: "Agave.Key".to_string(),
+                value: "a,b".to_string(),
+            },
+            Row {
+                key: "Agave.Secret".to_string(),
+                value: "plain".to_string(),
+            },
+        ];
+        assert_eq!(
+            render(&rows, Format::Csv, Some(10)).unwrap(),
+            "key,value\r\nAgave.Key,\"a,b\"\r\nAgave.Secret,plain\r\n"
+        );
+    }
+
+    #[test]
+    fn test_render_table_truncates() {
+        let rows = vec![Row {
+            key: "Agave.Key".to_string(),
+            value: "x".repeat(200),
+        }];
+        let full = render(&rows, Format::Table, None).unwrap();
+        assert!(full.lines().all(|line| line.chars().count() > 200));
+
+        let truncated = render(&rows, Format::Table, Some(40)).unwrap();
+        assert!(truncated.lines().all(|line| line.chars().count() <= 40));
+        assert!(truncated.contains("..."));
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("csv".parse::<Format>().unwrap(), Format::Csv);
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! # Preflight
+//!
+//! Checks run at the start of `site init` so that problems that would
+//! otherwise surface deep into the init, such as a full disk or a remote that
+//! can't be reached, fail it right away with an error that says what to fix.
+//! Each check gives up after `CHECK_TIMEOUT` so that a dead remote can't stall
+//! the preflight itself.
+use crate::{cmd, exit};
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+/// How long each reachability check may take.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The least free space a site init needs. A database and a few dozen repos
+/// add up to a few hundred megabytes, so this leaves room to spare.
+pub const MIN_FREE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// The number of repos whose remotes are checked.
+pub const REPO_SAMPLE_SIZE: usize = 3;
+
+// The host DoltHub remotes like `cyverse/de_releases` are cloned from.
+const DOLTHUB_REMOTE_HOST: &str = "doltremoteapi.dolthub.com";
+
+const NO_PREFLIGHT_HINT: &str = "Use --no-preflight to skip the preflight checks.";
+
+/// Formats a number of bytes for people, e.g. `1.5 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+// Parses the space available, in bytes, from the output of `df -Pk`.
+fn parse_df_available(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let kilobytes = line.split_whitespace().nth(3)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Returns the free space on the filesystem `path` is on. The path doesn't
+/// have to exist yet; the closest directory above it that does is used.
+///
+/// # Examples
+/// ```ignore
+///     let free = preflight::free_space(Path::new("site"))?;
+/// ```
+pub fn free_space(path: &Path) -> Result<u64> {
+    let absolute = std::env::current_dir()?.join(path);
+    let existing = absolute
+        .ancestors()
+        .find(|p| p.exists())
+        .context("couldn't find an existing directory to check the free space of")?;
+    let existing = existing
+        .to_str()
+        .context("couldn't get the directory to check as a string")?;
+
+    let output = cmd::Cmd::new("df").args(["-Pk", existing]).output()?;
+    parse_df_available(&output)
+        .ok_or_else(|| anyhow!("couldn't parse the free space from df: {}", output.trim()))
+}
+
+/// Fails if there's less than `MIN_FREE_BYTES` of free space where the site
+/// will be created. Returns the free space.
+pub fn check_free_space(dir: &Path) -> Result<u64> {
+    let free = free_space(dir)?;
+    if free < MIN_FREE_BYTES {
+        return Err(exit::Error::Usage(format!(
+            "only {} is free on the filesystem for {}, but site init needs at least {}. Free up some space or pick another --dir. {}",
+            format_bytes(free),
+            dir.display(),
+            format_bytes(MIN_FREE_BYTES),
+            NO_PREFLIGHT_HINT
+        ))
+        .into());
+    }
+    Ok(free)
+}
+
+// Where a Dolt remote is cloned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DoltRemote {
+    Host(String, u16),
+    Path(String),
+    Unknown,
+}
+
+fn dolt_remote(repo: &str) -> DoltRemote {
+    if !repo.contains("://") {
+        return DoltRemote::Host(DOLTHUB_REMOTE_HOST.to_string(), 443);
+    }
+    match url::Url::parse(repo) {
+        Ok(url) if url.scheme() == "file" => DoltRemote::Path(url.path().to_string()),
+        Ok(url) => match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => DoltRemote::Host(host.to_string(), port),
+            _ => DoltRemote::Unknown,
+        },
+        Err(_) => DoltRemote::Unknown,
+    }
+}
+
+/// Checks that the host a Dolt database is cloned from accepts connections.
+/// Remotes whose host can't be determined, such as cloud storage ones, are
+/// assumed to be reachable.
+///
+/// # Examples
+/// ```ignore
+///     preflight::check_dolt_remote("cyverse/de_releases").await?;
+/// ```
+pub async fn check_dolt_remote(repo: &str) -> Result<()> {
+    match dolt_remote(repo) {
+        DoltRemote::Host(host, port) => {
+            let connected =
+                tokio::time::timeout(CHECK_TIMEOUT, TcpStream::connect((host.as_str(), port)))
+                    .await
+                    .map_err(|_| anyhow!("timed out after {} seconds", CHECK_TIMEOUT.as_secs()))
+                    .and_then(|r| r.map_err(anyhow::Error::from));
+            connected.map(|_| ()).map_err(|e| {
+                exit::Error::ExternalTool(format!(
+                    "couldn't reach {}:{} to clone the database from {}: {:#}. Check the network connection and the --db-repo. {}",
+                    host, port, repo, e, NO_PREFLIGHT_HINT
+                ))
+                .into()
+            })
+        }
+        DoltRemote::Path(path) if !Path::new(&path).exists() => Err(exit::Error::Usage(format!(
+            "the database remote {} doesn't exist. Check the --db-repo. {}",
+            path, NO_PREFLIGHT_HINT
+        ))
+        .into()),
+        DoltRemote::Path(_) | DoltRemote::Unknown => Ok(()),
+    }
+}
+
+/// Checks that a git remote can be listed, which means it's reachable and the
+/// credentials for it work. Git is told not to prompt for credentials, so a
+/// private repo fails the check instead of waiting for input.
+///
+/// # Examples
+/// ```ignore
+///     preflight::check_git_remote("https://github.com/cyverse-de/apps").await?;
+/// ```
+pub async fn check_git_remote(url: &str) -> Result<()> {
+    let output = Command::new(cmd::program("git"))
+        .args(["ls-remote", "--quiet", url, "HEAD"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true)
+        .output();
+
+    let failure = match tokio::time::timeout(CHECK_TIMEOUT, output).await {
+        Err(_) => format!("timed out after {} seconds", CHECK_TIMEOUT.as_secs()),
+        Ok(Err(e)) => format!("couldn't run git: {}", e),
+        Ok(Ok(output)) if !output.status.success() => {
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        }
+        Ok(Ok(_)) => return Ok(()),
+    };
+
+    Err(exit::Error::ExternalTool(format!(
+        "couldn't reach the repo {}: {}. Check the network connection and the credentials for the repo. {}",
+        url, failure, NO_PREFLIGHT_HINT
+    ))
+    .into())
+}
+
+/// Picks up to `count` items spread evenly through `items`, always including
+/// the first one.
+pub fn sample<T>(items: &[T], count: usize) -> Vec<&T> {
+    if items.len() <= count {
+        return items.iter().collect();
+    }
+    (0..count)
+        .map(|i| &items[i * items.len() / count])
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_df_available() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                      /dev/sda1        102400000  51200000  51200000      50% /\n";
+        assert_eq!(parse_df_available(output), Some(51200000 * 1024));
+        assert_eq!(parse_df_available("Filesystem\n"), None);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(MIN_FREE_BYTES), "2.0 GiB");
+    }
+
+    #[test]
+    fn test_dolt_remote() {
+        assert_eq!(
+            dolt_remote("cyverse/de_releases"),
+            DoltRemote::Host(DOLTHUB_REMOTE_HOST.to_string(), 443)
+        );
+        assert_eq!(
+            dolt_remote("https://dolt.example.org/cyverse/de_releases"),
+            DoltRemote::Host("dolt.example.org".to_string(), 443)
+        );
+        assert_eq!(
+            dolt_remote("file:///srv/dolt/de_releases"),
+            DoltRemote::Path("/srv/dolt/de_releases".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let items = (0..10).collect::<Vec<_>>();
+        assert_eq!(sample(&items, 3), vec![&0, &3, &6]);
+        assert_eq!(sample(&items[..2], 3), vec![&0, &1]);
+        assert!(sample::<i32>(&[], 3).is_empty());
+    }
+}
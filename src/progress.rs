@@ -0,0 +1,100 @@
+//! # Progress
+//!
+//! Spinners and progress bars for the long-running steps of `site init`. They
+//! are only drawn when stdout is a terminal and `--no-progress` wasn't given.
+//! Otherwise each step is reported as plain lines, one when it starts and one
+//! when it's done, so that CI logs stay readable.
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+// The last tick string is shown once a spinner is finished.
+const SPINNER_TICKS: [&str; 11] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏", "✓"];
+
+/// Returns whether spinners and progress bars should be drawn.
+///
+/// # Examples
+/// ```ignore
+///     let bars = progress::bars_enabled(matches.get_flag("no-progress"));
+/// ```
+pub fn bars_enabled(no_progress: bool) -> bool {
+    !no_progress && console::Term::stdout().is_term()
+}
+
+/// A step that's shown as a spinner while it runs. Without progress bars, the
+/// step's start and end are printed as lines instead. Nothing is shown when
+/// `quiet` is true.
+pub struct Spinner {
+    bar: Option<ProgressBar>,
+    quiet: bool,
+}
+
+impl Spinner {
+    /// Shows the spinner, or prints the message if progress bars are off.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let spinner = Spinner::start("Cloning the database...", bars, quiet);
+    ///     clone()?;
+    ///     spinner.finish("Done cloning the database.");
+    /// ```
+    pub fn start(message: impl Into<String>, bars: bool, quiet: bool) -> Self {
+        let message = message.into();
+        if quiet {
+            return Spinner { bar: None, quiet };
+        }
+        if !bars {
+            println!("{}", message);
+            return Spinner { bar: None, quiet };
+        }
+
+        let style = ProgressStyle::with_template("{spinner:.cyan} {msg}")
+            .expect("the spinner template is valid")
+            .tick_strings(&SPINNER_TICKS);
+        let bar = ProgressBar::new_spinner()
+            .with_style(style)
+            .with_message(message);
+        bar.enable_steady_tick(TICK_INTERVAL);
+        Spinner {
+            bar: Some(bar),
+            quiet,
+        }
+    }
+
+    /// Replaces the spinner with a check mark and the message, or prints the
+    /// message followed by a blank line if progress bars are off.
+    pub fn finish(self, message: impl Into<String>) {
+        let message = message.into();
+        match self.bar {
+            Some(bar) => bar.finish_with_message(message),
+            None if !self.quiet => println!("{}\n", message),
+            None => {}
+        }
+    }
+}
+
+/// Returns a progress bar for `len` items of work, such as the repos being
+/// cloned, labeled with `prefix`. The bar's message is shown after it, e.g.
+/// for the item that was just done. Lines that need to be printed while it's
+/// drawn should go through its `println` so that they show up above it.
+///
+/// # Examples
+/// ```ignore
+///     let bar = progress::bar(repos.len() as u64, "Cloning the repos");
+///     bar.set_message("terrain");
+///     bar.inc(1);
+///     bar.finish();
+/// ```
+pub fn bar(len: u64, prefix: impl Into<String>) -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "{prefix} [{bar:40.cyan/blue}] {pos}/{len} ({elapsed}) {wide_msg:.dim}",
+    )
+    .expect("the progress bar template is valid")
+    .progress_chars("=> ");
+    let bar = ProgressBar::new(len)
+        .with_style(style)
+        .with_prefix(prefix.into());
+    bar.enable_steady_tick(TICK_INTERVAL);
+    bar
+}
@@ -0,0 +1,325 @@
+//! # Repo Path
+//!
+//! Where `site init` clones each repo under the site's `repos` directory. The
+//! location comes from a template with `{org}` and `{name}` placeholders, so
+//! that repos with the same name in different organizations can be kept apart
+//! with `{org}/{name}`. The default, `{name}`, clones every repo directly into
+//! `repos`. The template is recorded in the site directory, so that the
+//! commands that look for the repos later find them where init put them.
+use anyhow::{anyhow, Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// The template used when `--repo-path-template` isn't given.
+pub const DEFAULT_TEMPLATE: &str = "{name}";
+
+/// The file in the site directory that records the template the repos were
+/// cloned with.
+pub const TEMPLATE_FILENAME: &str = ".repo-path-template";
+
+const PLACEHOLDERS: [&str; 2] = ["{org}", "{name}"];
+
+/// Returns the organization (or user) that owns a repo, taken from the path of
+/// its URL. Works for HTTPS URLs as well as `git@host:org/name.git` ones.
+///
+/// # Examples
+/// ```ignore
+///     assert_eq!(org_from_url("https://github.com/cyverse-de/apps.git"), Some("cyverse-de".to_string()));
+/// ```
+pub fn org_from_url(url: &str) -> Option<String> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => url.split_once(':')?.1,
+    };
+    let mut segments = path
+        .trim_end_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    segments.pop()?;
+    segments.pop().map(|org| org.to_string())
+}
+
+/// A template for the path of a repo relative to the site's `repos`
+/// directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoPathTemplate {
+    template: String,
+}
+
+impl Default for RepoPathTemplate {
+    fn default() -> Self {
+        RepoPathTemplate {
+            template: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl RepoPathTemplate {
+    /// Checks that the template only uses the supported placeholders and
+    /// includes `{name}`, so that every repo gets its own directory.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut rest = template.to_string();
+        for placeholder in PLACEHOLDERS {
+            rest = rest.replace(placeholder, "");
+        }
+        if rest.contains('{') || rest.contains('}') {
+            return Err(anyhow!(
+                "repo path template '{}' may only use the {{org}} and {{name}} placeholders",
+                template
+            ));
+        }
+        if !template.contains("{name}") {
+            return Err(anyhow!(
+                "repo path template '{}' must include {{name}}",
+                template
+            ));
+        }
+        Ok(RepoPathTemplate {
+            template: template.to_string(),
+        })
+    }
+
+    /// Returns the template as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Returns the template recorded in the site directory, or the default
+    /// one for sites that don't have a template recorded.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let template = RepoPathTemplate::load(Path::new("site"))?;
+    /// ```
+    pub fn load(site_dir: &Path) -> Result<Self> {
+        let path = site_dir.join(TEMPLATE_FILENAME);
+        match std::fs::read_to_string(&path) {
+            Ok(template) => Self::parse(template.trim())
+                .with_context(|| format!("invalid repo path template in {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Records the template in the site directory for `load`.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     template.save(Path::new("site"))?;
+    /// ```
+    pub fn save(&self, site_dir: &Path) -> Result<()> {
+        let path = site_dir.join(TEMPLATE_FILENAME);
+        std::fs::write(&path, format!("{}\n", self.template))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Returns the directory to clone a repo into. Fails if the template
+    /// needs an organization that can't be found in the URL, or if the
+    /// resulting path would end up outside of `repos_dir`.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let template = RepoPathTemplate::parse("{org}/{name}")?;
+    ///     let dir = template.repo_dir(Path::new("site/repos"), "https://github.com/cyverse-de/apps", "apps")?;
+    /// ```
+    pub fn repo_dir(&self, repos_dir: &Path, url: &str, name: &str) -> Result<PathBuf> {
+        let mut path = self.template.replace("{name}", name);
+        if self.template.contains("{org}") {
+            let org = org_from_url(url)
+                .ok_or_else(|| anyhow!("couldn't find the organization in the repo URL {}", url))?;
+            path = path.replace("{org}", &org);
+        }
+
+        let relative = Path::new(&path);
+        let contained = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !contained || relative.components().next().is_none() {
+            return Err(anyhow!(
+                "the path {} for {} isn't inside {}",
+                path,
+                url,
+                repos_dir.display()
+            ));
+        }
+
+        Ok(repos_dir.join(relative))
+    }
+
+    /// Returns the name of the repo cloned into `repo_dir`, the reverse of
+    /// `repo_dir`. Returns `None` if the template wouldn't put a repo cloned
+    /// from `url` there.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let template = RepoPathTemplate::parse("{org}/{name}")?;
+    ///     let name = template.repo_name(Path::new("site/repos"), "https://github.com/cyverse-de/apps", Path::new("site/repos/cyverse-de/apps"));
+    ///     assert_eq!(name, Some("apps".to_string()));
+    /// ```
+    pub fn repo_name(&self, repos_dir: &Path, url: &str, repo_dir: &Path) -> Option<String> {
+        let relative = repo_dir.strip_prefix(repos_dir).ok()?.to_str()?;
+        let mut pattern = self.template.clone();
+        if pattern.contains("{org}") {
+            pattern = pattern.replace("{org}", &org_from_url(url)?);
+        }
+        let (prefix, suffix) = pattern.split_once("{name}")?;
+        let name = relative.strip_prefix(prefix)?.strip_suffix(suffix)?;
+
+        // The template may use {name} more than once, so check the whole path.
+        match self.repo_dir(repos_dir, url, name) {
+            Ok(dir) if dir == repo_dir => Some(name.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the git repos under `repos_dir`, sorted, however deeply the
+/// template nested them. Directories inside a repo aren't searched.
+///
+/// # Examples
+/// ```ignore
+///     let repo_dirs = repo_path::find_repos(Path::new("site/repos"))?;
+/// ```
+pub fn find_repos(repos_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut repos = Vec::new();
+    if !repos_dir.is_dir() {
+        return Ok(repos);
+    }
+
+    let mut pending = vec![repos_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            if path.join(".git").exists() {
+                repos.push(path);
+            } else {
+                pending.push(path);
+            }
+        }
+    }
+    repos.sort();
+
+    Ok(repos)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_org_from_url() {
+        assert_eq!(
+            org_from_url("https://github.com/cyverse-de/apps.git"),
+            Some("cyverse-de".to_string())
+        );
+        assert_eq!(
+            org_from_url("https://github.com/cyverse-de/apps/"),
+            Some("cyverse-de".to_string())
+        );
+        assert_eq!(
+            org_from_url("git@github.com:cyverse/terrain.git"),
+            Some("cyverse".to_string())
+        );
+        assert_eq!(org_from_url("https://github.com/apps"), None);
+        assert_eq!(org_from_url("apps"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(RepoPathTemplate::parse("{org}/{name}").is_ok());
+        assert!(RepoPathTemplate::parse("{org}").is_err());
+        assert!(RepoPathTemplate::parse("{owner}/{name}").is_err());
+    }
+
+    #[test]
+    fn test_repo_dir() {
+        let repos_dir = Path::new("site/repos");
+        let url = "https://github.com/cyverse-de/apps.git";
+
+        assert_eq!(
+            RepoPathTemplate::default()
+                .repo_dir(repos_dir, url, "apps")
+                .unwrap(),
+            PathBuf::from("site/repos/apps")
+        );
+        assert_eq!(
+            RepoPathTemplate::parse("{org}/{name}")
+                .unwrap()
+                .repo_dir(repos_dir, url, "apps")
+                .unwrap(),
+            PathBuf::from("site/repos/cyverse-de/apps")
+        );
+
+        let template = RepoPathTemplate::parse("../{name}").unwrap();
+        assert!(template.repo_dir(repos_dir, url, "apps").is_err());
+        let template = RepoPathTemplate::parse("/tmp/{name}").unwrap();
+        assert!(template.repo_dir(repos_dir, url, "apps").is_err());
+        let template = RepoPathTemplate::default();
+        assert!(template.repo_dir(repos_dir, url, "..").is_err());
+    }
+
+    #[test]
+    fn test_repo_name() {
+        let repos_dir = Path::new("site/repos");
+        let url = "https://github.com/cyverse-de/apps.git";
+
+        assert_eq!(
+            RepoPathTemplate::default().repo_name(repos_dir, url, Path::new("site/repos/apps")),
+            Some("apps".to_string())
+        );
+        let template = RepoPathTemplate::parse("{org}/{name}").unwrap();
+        assert_eq!(
+            template.repo_name(repos_dir, url, Path::new("site/repos/cyverse-de/apps")),
+            Some("apps".to_string())
+        );
+        assert_eq!(
+            template.repo_name(repos_dir, url, Path::new("site/repos/cyverse/apps")),
+            None
+        );
+        assert_eq!(
+            template.repo_name(repos_dir, url, Path::new("elsewhere/apps")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_save_load_and_find_repos() {
+        let site_dir =
+            std::env::temp_dir().join(format!("mgmt-repo-path-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&site_dir);
+        std::fs::create_dir_all(&site_dir).unwrap();
+
+        assert_eq!(
+            RepoPathTemplate::load(&site_dir).unwrap(),
+            RepoPathTemplate::default()
+        );
+        let template = RepoPathTemplate::parse("{org}/{name}").unwrap();
+        template.save(&site_dir).unwrap();
+        assert_eq!(RepoPathTemplate::load(&site_dir).unwrap(), template);
+
+        let repos_dir = site_dir.join("repos");
+        for repo in [
+            "cyverse-de/apps",
+            "cyverse/terrain",
+            "cyverse/terrain/vendor/lib",
+        ] {
+            std::fs::create_dir_all(repos_dir.join(repo).join(".git")).unwrap();
+        }
+        std::fs::create_dir_all(repos_dir.join("empty-org")).unwrap();
+        assert_eq!(
+            find_repos(&repos_dir).unwrap(),
+            vec![
+                repos_dir.join("cyverse/terrain"),
+                repos_dir.join("cyverse-de/apps"),
+            ]
+        );
+        assert!(find_repos(&site_dir.join("missing")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&site_dir).unwrap();
+    }
+}
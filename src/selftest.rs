@@ -0,0 +1,220 @@
+//! # Self-Test
+//!
+//! `mgmt site selftest` checks that an install of `mgmt` and the tools it
+//! depends on works by running a miniature site init end to end: it creates
+//! an empty Dolt database in a temporary directory, starts a server for it,
+//! applies the migrations embedded in the binary, sets a couple of config
+//! values, renders a values file, and checks what was rendered. Everything it
+//! creates is removed afterwards, whether the self-test passes or not.
+use crate::config_values::{config, format::Format};
+use crate::{db, dolt, ops};
+use anyhow::{anyhow, Context, Result};
+use console::style;
+use sqlx::{migrate::Migrator, Pool, Postgres};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// The migrations in db/migrations, embedded so that the self-test doesn't
+// depend on being run from a checkout of the repo.
+static MIGRATOR: Migrator = sqlx::migrate!("db/migrations");
+
+const DB_NAME: &str = "mgmt_selftest";
+const ENV_NAME: &str = "selftest";
+const ACTOR: &str = "mgmt-selftest";
+const ACTOR_EMAIL: &str = "mgmt-selftest@localhost";
+
+// The values set in the self-test environment. The base URI refers to the
+// environment name so that interpolation is exercised too.
+const VALUES: [(&str, &str, &str); 2] = [
+    ("TopLevel", "Environment", ENV_NAME),
+    (
+        "DE",
+        "BaseURI",
+        "https://${TopLevel.Environment}.example.org",
+    ),
+];
+const EXPECTED_BASE_URI: &str = "https://selftest.example.org";
+
+// The server takes a moment to start accepting connections.
+const CONNECT_ATTEMPTS: u32 = 30;
+const CONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+// Everything the self-test creates. Dropping it stops the server and deletes
+// the temporary directory, so that nothing is left behind even if the
+// self-test bails out partway through.
+struct Scratch {
+    dir: PathBuf,
+    server: Option<dolt::RunningServer>,
+    pool: Option<Pool<Postgres>>,
+}
+
+impl Scratch {
+    fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("mgmt-selftest-{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("failed to remove {}", dir.display()))?;
+        }
+        fs::create_dir_all(dir.join(DB_NAME))
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Scratch {
+            dir,
+            server: None,
+            pool: None,
+        })
+    }
+
+    async fn cleanup(mut self) -> Result<()> {
+        if let Some(pool) = self.pool.take() {
+            pool.close().await;
+        }
+        if let Some(server) = self.server.take() {
+            server.stop()?;
+        }
+        fs::remove_dir_all(&self.dir)
+            .with_context(|| format!("failed to remove {}", self.dir.display()))?;
+        Ok(())
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        // The server has to stop before its directory can be removed.
+        self.server.take();
+        if self.dir.exists() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+// Prints whether a step passed and passes its result along.
+fn report<T>(step: &str, result: Result<T>) -> Result<T> {
+    match &result {
+        Ok(_) => println!("  {} {}", style("✓").green(), step),
+        Err(_) => println!("  {} {}", style("✗").red(), step),
+    }
+    result.with_context(|| format!("self-test step failed: {}", step))
+}
+
+async fn connect(port: u16) -> Result<Pool<Postgres>> {
+    let url = format!("mysql://root@127.0.0.1:{}/{}", port, DB_NAME);
+    let mut attempt = 1;
+    loop {
+        match db::pool_options(&url).connect(&url).await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt >= CONNECT_ATTEMPTS => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("couldn't connect to the database on port {}", port)))
+            }
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(CONNECT_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn set_values(pool: &Pool<Postgres>) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let env_id = db::upsert_environment(&mut tx, ENV_NAME, ENV_NAME).await?;
+    for (section, key, value) in VALUES {
+        let cfg_id = db::set_config_value_as(&mut tx, section, key, value, "string", ACTOR).await?;
+        db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn check_rendered(path: &Path) -> Result<()> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let values = Format::Yaml.parse(&contents)?;
+
+    let environment = values.get("Environment").and_then(|v| v.as_str());
+    if environment != Some(ENV_NAME) {
+        return Err(anyhow!(
+            "expected Environment to be {}, got {:?}",
+            ENV_NAME,
+            environment
+        ));
+    }
+
+    let base_uri = values
+        .get("DE")
+        .and_then(|de| de.get("BaseURI"))
+        .and_then(|v| v.as_str());
+    if base_uri.is_none_or(|uri| uri.trim_end_matches('/') != EXPECTED_BASE_URI) {
+        return Err(anyhow!(
+            "expected DE.BaseURI to be {}, got {:?}",
+            EXPECTED_BASE_URI,
+            base_uri
+        ));
+    }
+
+    Ok(())
+}
+
+async fn run_steps(scratch: &mut Scratch) -> Result<()> {
+    let db_dir = scratch.dir.join(DB_NAME);
+    let db_dir = db_dir
+        .to_str()
+        .context("could not get name of the database directory")?
+        .to_string();
+
+    let initialized = dolt::init(&db_dir, ACTOR, ACTOR_EMAIL);
+    report("Create an empty Dolt database", initialized)?;
+
+    let server = dolt::start(&db_dir, None, false);
+    let server = report("Start the Dolt SQL server", server)?;
+    let port = server.port();
+    scratch.server = Some(server);
+
+    let pool = report("Connect to the database", connect(port).await)?;
+    scratch.pool = Some(pool.clone());
+
+    report(
+        "Apply the migrations",
+        MIGRATOR.run(&pool).await.map_err(anyhow::Error::from),
+    )?;
+    report("Set config values", set_values(&pool).await)?;
+
+    let values_file = scratch.dir.join("deployment.yaml");
+    let rendered = ops::render_values(
+        &pool,
+        ENV_NAME,
+        &config::SectionOptions::default(),
+        Some(values_file.clone()),
+        Some(Format::Yaml),
+        true,
+        false,
+    )
+    .await;
+    report("Render the values file", rendered)?;
+    report("Check the rendered values", check_rendered(&values_file))?;
+
+    Ok(())
+}
+
+/// Runs the self-test, printing each step as it passes or fails. Returns an
+/// error naming the step that failed. The temporary directory and the Dolt
+/// server are cleaned up either way.
+///
+/// Handler for the `mgmt site selftest` command.
+///
+/// # Examples
+/// ```ignore
+///     selftest::run().await?;
+/// ```
+pub async fn run() -> Result<()> {
+    let mut scratch = Scratch::new()?;
+    println!("Running the self-test in {}...", scratch.dir.display());
+
+    let result = run_steps(&mut scratch).await;
+    let cleaned_up = report("Clean up", scratch.cleanup().await);
+    result?;
+    cleaned_up?;
+
+    println!("{}", style("Self-test passed.").green());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! # Suggestions
+//!
+//! Helpers for suggesting the name a user probably meant when they pass in
+//! a section, key, environment, or service that doesn't exist.
+
+// Returns the number of single-character insertions, deletions, and
+// substitutions needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b_chars.len()).collect::<Vec<_>>();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a_char != *b_char);
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b_chars.len()]
+}
+
+/// Returns the candidate closest to the input by edit distance, ignoring case.
+/// Returns `None` if none of the candidates are close enough to be a likely
+/// typo of the input.
+///
+/// # Examples
+/// ```ignore
+///     let known = vec!["apps".to_string(), "analyses".to_string()];
+///     if let Some(service) = suggest::closest("aps", &known) {
+///         println!("Did you mean {}?", service);
+///     }
+/// ```
+pub fn closest<'a, S: AsRef<str>>(input: &str, candidates: &'a [S]) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    let max_distance = (input.chars().count() / 3).max(2);
+
+    candidates
+        .iter()
+        .map(|c| c.as_ref())
+        .map(|c| (edit_distance(&input, &c.to_lowercase()), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Returns a "did you mean" hint for the input that can be appended to an
+/// error message, or an empty string if there's nothing worth suggesting.
+///
+/// # Examples
+/// ```ignore
+///     let sections = db::list_sections(&mut tx).await?;
+///     let hint = suggest::did_you_mean(section, &sections);
+///     return Err(anyhow::anyhow!("unknown section {}{}", section, hint));
+/// ```
+pub fn did_you_mean<S: AsRef<str>>(input: &str, candidates: &[S]) -> String {
+    closest(input, candidates)
+        .map(|c| format!("; did you mean '{}'?", c))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("apps", "apps"), 0);
+        assert_eq!(edit_distance("aps", "apps"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let candidates = vec![
+            "payment-service".to_string(),
+            "apps".to_string(),
+            "analyses".to_string(),
+        ];
+        assert_eq!(
+            closest("payment-servce", &candidates),
+            Some("payment-service")
+        );
+        assert_eq!(closest("Apps", &candidates), Some("apps"));
+        assert_eq!(closest("analysis", &candidates), Some("analyses"));
+        assert_eq!(closest("aps", &candidates), Some("apps"));
+        assert_eq!(closest("notifications", &candidates), None);
+        assert_eq!(closest::<String>("apps", &[]), None);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let sections = vec!["Agave".to_string()];
+        assert_eq!(did_you_mean("Agve", &sections), "; did you mean 'Agave'?");
+        assert_eq!(did_you_mean("Keycloak", &sections), "");
+    }
+}
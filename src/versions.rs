@@ -0,0 +1,253 @@
+//! # Versions
+//!
+//! A versions file pins the version of each service that gets released and
+//! deployed, overriding the revision recorded in the database. It's meant to
+//! be checked into the site and edited by hand, so that version changes get
+//! reviewed like any other change. The versions are the tags of the services'
+//! GitHub releases:
+//!
+//! ```yaml
+//! schema_version: 1
+//! services:
+//!   analyses: v1.0.4
+//!   apps: v3.2.1
+//! ```
+use crate::{db::ServiceVersion, exit, suggest};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The version of the versions file layout written by this version of the tool.
+pub const VERSIONS_SCHEMA_VERSION: u32 = 1;
+
+/// The name of the versions file that's used when one isn't passed in.
+pub const VERSIONS_FILENAME: &str = "versions.yaml";
+
+/// The pinned versions, keyed by service name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionPins {
+    pub schema_version: u32,
+
+    #[serde(default)]
+    pub services: BTreeMap<String, String>,
+}
+
+impl Default for VersionPins {
+    fn default() -> Self {
+        VersionPins {
+            schema_version: VERSIONS_SCHEMA_VERSION,
+            services: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<Vec<ServiceVersion>> for VersionPins {
+    fn from(versions: Vec<ServiceVersion>) -> Self {
+        VersionPins {
+            services: versions
+                .into_iter()
+                .map(|svc| (svc.name, svc.revision))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl VersionPins {
+    /// Reads a versions file from disk. Files written by a newer version of
+    /// the tool are rejected.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open versions file {}", path.display()))?;
+        let pins: VersionPins = serde_yaml::from_reader(file)
+            .with_context(|| format!("failed to parse versions file {}", path.display()))?;
+
+        if pins.schema_version > VERSIONS_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "versions file {} has schema version {}, but this version of the tool only supports up to {}",
+                path.display(),
+                pins.schema_version,
+                VERSIONS_SCHEMA_VERSION
+            ));
+        }
+
+        Ok(pins)
+    }
+
+    /// Writes the pinned versions to a file, replacing it if it exists.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_yaml::to_string(self)?)
+            .with_context(|| format!("failed to write versions file {}", path.display()))
+    }
+
+    /// Returns an error if any of the versions are blank or any of the pinned
+    /// services aren't in `known`, along with the closest known name for each
+    /// unknown service.
+    pub fn validate(&self, known: &[String]) -> Result<()> {
+        let blank = self
+            .services
+            .iter()
+            .filter(|(_, version)| version.trim().is_empty())
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        if !blank.is_empty() {
+            return Err(exit::Error::Usage(format!(
+                "no version is set in the versions file for: {}",
+                blank.join(", ")
+            ))
+            .into());
+        }
+
+        let unknown = self
+            .services
+            .keys()
+            .filter(|name| !known.contains(name))
+            .map(|name| match suggest::closest(name, known) {
+                Some(closest) => format!("{} (did you mean '{}'?)", name, closest),
+                None => name.to_string(),
+            })
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(exit::Error::Usage(format!(
+                "unknown service(s) in the versions file: {}",
+                unknown.join("; ")
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the pinned version of a service, if it has one.
+    pub fn version_for(&self, service: &str) -> Option<&str> {
+        self.services.get(service).map(|v| v.as_str())
+    }
+
+    /// Replaces the revisions of the pinned services with their pinned
+    /// versions. Services that aren't pinned keep their revisions from the
+    /// database.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     let versions = db::get_service_versions(&mut tx, "qa").await?;
+    ///     let versions = pins.apply(versions);
+    /// ```
+    pub fn apply(&self, versions: Vec<ServiceVersion>) -> Vec<ServiceVersion> {
+        versions
+            .into_iter()
+            .map(|svc| match self.version_for(&svc.name) {
+                Some(version) => ServiceVersion {
+                    revision: version.to_string(),
+                    ..svc
+                },
+                None => svc,
+            })
+            .collect()
+    }
+}
+
+/// Returns the path to the versions file to use. A path that was passed in is
+/// always used, so a missing file gets reported, while the default file is
+/// only used if it exists.
+pub fn resolve_path(path: Option<&PathBuf>) -> Option<PathBuf> {
+    match path {
+        Some(path) => Some(path.clone()),
+        None => {
+            let default = PathBuf::from(VERSIONS_FILENAME);
+            default.exists().then_some(default)
+        }
+    }
+}
+
+/// Loads and validates the versions file at `path`, if there is one. Returns
+/// empty pins when there isn't.
+///
+/// # Examples
+/// ```ignore
+///     let pins = versions::load(versions::resolve_path(None).as_deref(), &known)?;
+/// ```
+pub fn load(path: Option<&Path>, known: &[String]) -> Result<VersionPins> {
+    match path {
+        Some(path) => {
+            let pins = VersionPins::from_file(path)?;
+            pins.validate(known)
+                .with_context(|| format!("in {}", path.display()))?;
+            println!(
+                "Using {} pinned version(s) from {}",
+                pins.services.len(),
+                path.display()
+            );
+            Ok(pins)
+        }
+        None => Ok(VersionPins::default()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn service_version(name: &str, revision: &str) -> ServiceVersion {
+        ServiceVersion {
+            name: name.to_string(),
+            repo_name: name.to_string(),
+            revision: revision.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let pins: VersionPins =
+            serde_yaml::from_str("schema_version: 1\nservices:\n  apps: v3.2.1\n").unwrap();
+        assert_eq!(pins.version_for("apps"), Some("v3.2.1"));
+        assert_eq!(pins.version_for("analyses"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let known = vec!["apps".to_string(), "analyses".to_string()];
+        let mut pins = VersionPins::default();
+        pins.services
+            .insert("apps".to_string(), "v3.2.1".to_string());
+        assert!(pins.validate(&known).is_ok());
+
+        pins.services
+            .insert("aps".to_string(), "v1.0.0".to_string());
+        let err = pins.validate(&known).unwrap_err().to_string();
+        assert!(err.contains("did you mean 'apps'?"), "{}", err);
+
+        pins.services.remove("aps");
+        pins.services
+            .insert("analyses".to_string(), " ".to_string());
+        assert!(pins.validate(&known).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut pins = VersionPins::default();
+        pins.services
+            .insert("apps".to_string(), "v3.2.1".to_string());
+
+        let versions = pins.apply(vec![
+            service_version("analyses", "v1.0.4"),
+            service_version("apps", "v3.0.0"),
+        ]);
+        assert_eq!(
+            versions,
+            vec![
+                service_version("analyses", "v1.0.4"),
+                service_version("apps", "v3.2.1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_service_versions() {
+        let pins = VersionPins::from(vec![service_version("apps", "v3.0.0")]);
+        assert_eq!(pins.schema_version, VERSIONS_SCHEMA_VERSION);
+        assert_eq!(pins.version_for("apps"), Some("v3.0.0"));
+    }
+}
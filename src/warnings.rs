@@ -0,0 +1,122 @@
+//! # Warnings
+//!
+//! Collects the non-fatal problems found during long-running commands such as
+//! `site init` and `release deploy`. Each warning is printed when it happens,
+//! so that it shows up next to the step that caused it, and again in a summary
+//! at the end of the run so that it doesn't get lost in the scrollback.
+//!
+//! With the global `--strict` flag, a run that recorded any warnings fails
+//! once it's done, so that pipelines can insist on a clean run while warnings
+//! stay non-fatal for interactive use.
+use anyhow::{anyhow, Result};
+use console::style;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether warnings fail the command, from the global `--strict` flag.
+///
+/// # Examples
+/// ```ignore
+///     warnings::init_strict(matches.get_flag("strict"));
+/// ```
+pub fn init_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Returns whether warnings fail the command.
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Returns the error that fails a strict run with warnings.
+pub fn strict_error(count: usize) -> anyhow::Error {
+    anyhow!("{} warning(s) were reported and --strict was given", count)
+}
+
+/// The warnings collected during a run, in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Warnings {
+    messages: Vec<String>,
+}
+
+impl Warnings {
+    /// Records a warning and prints it to stderr.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     warnings.warn(format!("failed to clone {}", repo_url));
+    /// ```
+    pub fn warn<S: Into<String>>(&mut self, message: S) {
+        let message = message.into();
+        eprintln!("{} {}", style("warning:").yellow().bold(), message);
+        self.messages.push(message);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+
+    /// Returns the summary printed at the end of a run, or `None` if there
+    /// weren't any warnings.
+    pub fn summary(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut summary = format!("Completed with {} warning(s):", self.len());
+        for message in &self.messages {
+            summary.push_str("\n  - ");
+            summary.push_str(message);
+        }
+        Some(summary)
+    }
+
+    /// Prints the summary of the warnings to stderr, if there were any.
+    pub fn print_summary(&self) {
+        if let Some(summary) = self.summary() {
+            eprintln!("\n{}", style(summary).yellow());
+        }
+    }
+
+    /// Returns an error if any warnings were recorded in strict mode. Call
+    /// it once the run is otherwise done.
+    ///
+    /// # Examples
+    /// ```ignore
+    ///     warnings.print_summary();
+    ///     warnings.check()?;
+    /// ```
+    pub fn check(&self) -> Result<()> {
+        if is_strict() && !self.is_empty() {
+            return Err(strict_error(self.len()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut warnings = Warnings::default();
+        assert_eq!(warnings.summary(), None);
+
+        warnings.warn("failed to clone https://github.com/cyverse-de/apps");
+        warnings.warn("repos/terrain is not cloned".to_string());
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings.summary().unwrap(),
+            "Completed with 2 warning(s):\n  - failed to clone https://github.com/cyverse-de/apps\n  - repos/terrain is not cloned"
+        );
+    }
+}